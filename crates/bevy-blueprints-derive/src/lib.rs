@@ -0,0 +1,201 @@
+//! Derive macros for `bevy_blueprints`, re-exported from there.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parenthesized, parse_macro_input, DeriveInput, Expr, Ident, Token, Type};
+
+/// Implements `Blueprint` by stamping every field of the bundle from an expression.
+///
+/// ```ignore
+/// #[derive(Blueprint)]
+/// #[blueprint(
+/// 	for = BlockBlueprint<CameraBlockBlueprint>,
+/// 	bundle = CameraBlockBundle,
+/// 	param(mma: MMA<'static>),
+/// 	stamp(
+/// 		pbr = self.pbr_bundle(mma),
+/// 		name = Name::new("CameraBlock"),
+/// 		marker = CameraBlockMarker,
+/// 	)
+/// )]
+/// pub struct CameraBlockBlueprint;
+/// ```
+///
+/// - `bundle`: the `Blueprint::Bundle`, required
+/// - `for`: the type to implement `Blueprint` for, by default the type deriving it
+/// - `param(name: Type)`: the `Blueprint::StampSystemParam`, available as `name` when stamping.
+///   By default `()`
+/// - `stamp(field = expr, ..)`: the expression for every field of the bundle,
+///   which can use `self`. Without it the bundle is stamped with `Default::default()`
+/// - `expansion_set = expr`: the `Blueprint::expansion_set`
+#[proc_macro_derive(Blueprint, attributes(blueprint))]
+pub fn derive_blueprint(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	blueprint(input)
+		.unwrap_or_else(syn::Error::into_compile_error)
+		.into()
+}
+
+/// Implements `NetworkedBlueprintBundle` and `Deref` for a bundle,
+/// with the blueprint component marked `#[blueprint]`.
+///
+/// ```ignore
+/// #[derive(Bundle, NetworkedBlueprintBundle)]
+/// pub struct SpawnPointBlueprintBundle {
+/// 	transform: Transform,
+/// 	#[blueprint]
+/// 	blueprint: SpawnPointBlueprintComponent,
+/// }
+/// ```
+#[proc_macro_derive(NetworkedBlueprintBundle, attributes(blueprint))]
+pub fn derive_networked_blueprint_bundle(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	networked_blueprint_bundle(input)
+		.unwrap_or_else(syn::Error::into_compile_error)
+		.into()
+}
+
+fn blueprint(input: DeriveInput) -> syn::Result<TokenStream2> {
+	let mut target: Option<Type> = None;
+	let mut bundle: Option<Type> = None;
+	let mut param: Option<(Ident, Type)> = None;
+	let mut fields: Option<Vec<(Ident, Expr)>> = None;
+	let mut expansion_set: Option<Expr> = None;
+
+	for attr in input
+		.attrs
+		.iter()
+		.filter(|attr| attr.path().is_ident("blueprint"))
+	{
+		attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("for") {
+				target = Some(meta.value()?.parse()?);
+			} else if meta.path.is_ident("bundle") {
+				bundle = Some(meta.value()?.parse()?);
+			} else if meta.path.is_ident("param") {
+				let content;
+				parenthesized!(content in meta.input);
+				let name: Ident = content.parse()?;
+				content.parse::<Token![:]>()?;
+				param = Some((name, content.parse()?));
+			} else if meta.path.is_ident("stamp") {
+				let fields = fields.get_or_insert_with(Vec::new);
+				meta.parse_nested_meta(|field| {
+					let name = field
+						.path
+						.get_ident()
+						.cloned()
+						.ok_or_else(|| field.error("expected the name of a field of the bundle"))?;
+					fields.push((name, field.value()?.parse()?));
+					Ok(())
+				})?;
+			} else if meta.path.is_ident("expansion_set") {
+				expansion_set = Some(meta.value()?.parse()?);
+			} else {
+				return Err(meta.error("expected `for`, `bundle`, `param`, `stamp` or `expansion_set`"));
+			}
+			Ok(())
+		})?;
+	}
+
+	let Some(bundle) = bundle else {
+		return Err(syn::Error::new_spanned(
+			&input.ident,
+			"#[derive(Blueprint)] needs #[blueprint(bundle = ..)]",
+		));
+	};
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	let ident = &input.ident;
+	let target = match target {
+		Some(target) => quote!(#target),
+		None => quote!(#ident #ty_generics),
+	};
+	let (param_name, param_type) = match param {
+		Some((name, ty)) => (quote!(#name), quote!(#ty)),
+		None => (quote!(_param), quote!(())),
+	};
+	let stamped = match fields {
+		Some(fields) => {
+			let (names, exprs): (Vec<_>, Vec<_>) = fields.into_iter().unzip();
+			quote!(Self::Bundle { #(#names: #exprs,)* })
+		}
+		None => quote!(::core::default::Default::default()),
+	};
+	let expansion_set = expansion_set.map(|set| {
+		quote! {
+			fn expansion_set() -> ::core::option::Option<::bevy_blueprints::__private::InternedSystemSet> {
+				::core::option::Option::Some(::bevy_blueprints::__private::SystemSet::intern(&(#set)))
+			}
+		}
+	});
+
+	Ok(quote! {
+		impl #impl_generics ::bevy_blueprints::Blueprint for #target #where_clause {
+			type Bundle = #bundle;
+			type StampSystemParam = #param_type;
+
+			#[allow(unused_variables)]
+			fn stamp(
+				&self,
+				#param_name: &mut ::bevy_blueprints::__private::SystemParamItem<'_, '_, Self::StampSystemParam>,
+			) -> Self::Bundle {
+				#stamped
+			}
+
+			#expansion_set
+		}
+	})
+}
+
+fn networked_blueprint_bundle(input: DeriveInput) -> syn::Result<TokenStream2> {
+	let syn::Data::Struct(data) = &input.data else {
+		return Err(syn::Error::new_spanned(
+			&input.ident,
+			"#[derive(NetworkedBlueprintBundle)] only works on structs",
+		));
+	};
+	let mut marked = data.fields.iter().enumerate().filter(|(_, field)| {
+		field
+			.attrs
+			.iter()
+			.any(|attr| attr.path().is_ident("blueprint"))
+	});
+	let Some((index, field)) = marked.next() else {
+		return Err(syn::Error::new_spanned(
+			&input.ident,
+			"Mark the blueprint component with #[blueprint]",
+		));
+	};
+	if let Some((_, other)) = marked.next() {
+		return Err(syn::Error::new_spanned(
+			other,
+			"Only one field can be the #[blueprint]",
+		));
+	}
+
+	let ident = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+	let component = &field.ty;
+	let member = match &field.ident {
+		Some(name) => quote!(#name),
+		None => {
+			let index = syn::Index::from(index);
+			quote!(#index)
+		}
+	};
+
+	Ok(quote! {
+		impl #impl_generics ::bevy_blueprints::NetworkedBlueprintBundle for #ident #ty_generics #where_clause {
+			type NetworkedBlueprintComponent = #component;
+		}
+
+		impl #impl_generics ::core::ops::Deref for #ident #ty_generics #where_clause {
+			type Target = #component;
+
+			fn deref(&self) -> &Self::Target {
+				&self.#member
+			}
+		}
+	})
+}
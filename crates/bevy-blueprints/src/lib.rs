@@ -1,16 +1,56 @@
-mod prelude {
+//! Blueprints are [Component]s that describe what an [Entity] is,
+//! and are expanded into everything needed to show and simulate it.
+//! Only the blueprint has to be sent over the network,
+//! every instance of the game expands it the same way.
+//!
+//! Add the [BlueprintsPlugin] for the schedule that expands blueprints,
+//! then [BlueprintsAppExt::register_blueprint] every blueprint component.
+
+pub use api::*;
+
+// so that the derive macros work in this crate too
+extern crate self as bevy_blueprints;
+
+mod api {
+	pub use bevy_blueprints_derive::{Blueprint, NetworkedBlueprintBundle};
+
+	pub use crate::children::BlueprintChildren;
+	pub use crate::components::*;
 	pub use crate::plugin::*;
+	pub use crate::registration::BlueprintsAppExt;
 	pub use crate::sets::*;
-	pub use crate::components::*;
+	pub use crate::traits::*;
+}
+
+/// Used by the derive macros
+#[doc(hidden)]
+pub mod __private {
+	pub use bevy::ecs::schedule::{InternedSystemSet, SystemSet};
+	pub use bevy::ecs::system::SystemParamItem;
+}
+
+mod prelude {
+	pub use crate::api::*;
 
 	// bevy
+	pub use bevy::ecs::schedule::{InternedScheduleLabel, InternedSystemSet, ScheduleLabel};
+	pub use bevy::ecs::system::{StaticSystemParam, SystemParam, SystemParamItem};
 	pub use bevy::prelude::*;
-	pub use bevy::ecs::schedule::{ScheduleLabel, InternedScheduleLabel};
+	pub use bevy::reflect::GetTypeRegistration;
+
+	// networking
+	pub use bevy_replicon::prelude::AppReplicationExt;
+	pub use serde::{de::DeserializeOwned, Serialize};
 }
 
 mod plugin {
 	use crate::prelude::*;
 
+	use crate::registration::RegisteredBlueprints;
+
+	/// Expands every registered blueprint when its schedule runs,
+	/// which is [Blueprints] by default.
+	/// Running the schedule is up to you.
 	#[derive(Debug)]
 	pub struct BlueprintsPlugin {
 		schedule: InternedScheduleLabel,
@@ -21,6 +61,9 @@ mod plugin {
 			type BS = BlueprintsSet;
 			app
 				.register_type::<BlueprintNeedsUpdating>()
+				.register_type::<FreshlyExpanded>()
+				.register_type::<ExpandedChild>()
+				.insert_resource(RegisteredBlueprints::new(self.schedule))
 				.configure_sets(
 					self.schedule,
 					(
@@ -29,19 +72,48 @@ mod plugin {
 						BS::ApplyDeferred2,
 						BS::ExpandBlueprints,
 						BS::ApplyDeferred3,
+						BS::Expanded,
 					)
 						.chain(),
+				)
+				.add_systems(
+					self.schedule,
+					(
+						apply_deferred.in_set(BS::ApplyDeferred1),
+						Self::clear_freshly_expanded.in_set(BS::MarkChanged),
+						apply_deferred.in_set(BS::ApplyDeferred2),
+						apply_deferred.in_set(BS::ApplyDeferred3),
+					),
 				);
 		}
 	}
 
+	impl Default for BlueprintsPlugin {
+		fn default() -> Self {
+			Self::new(Blueprints)
+		}
+	}
+
 	impl BlueprintsPlugin {
 		pub fn new(schedule: impl ScheduleLabel) -> Self {
 			Self {
 				schedule: schedule.intern(),
 			}
 		}
+
+		fn clear_freshly_expanded(
+			markers: Query<Entity, With<FreshlyExpanded>>,
+			mut commands: Commands,
+		) {
+			for entity in markers.iter() {
+				commands.entity(entity).remove::<FreshlyExpanded>();
+			}
+		}
 	}
+
+	/// The default schedule for the [BlueprintsPlugin]
+	#[derive(ScheduleLabel, Hash, Debug, PartialEq, Eq, Clone, Copy)]
+	pub struct Blueprints;
 }
 
 mod sets {
@@ -53,15 +125,32 @@ mod sets {
 		ApplyDeferred1,
 
 		/// Adds the [BlueprintNeedsUpdating] marker component to
-		/// relevant [Entity]s.
+		/// every [Entity] whose blueprint was added or changed,
+		/// and removes [FreshlyExpanded] from the last run.
 		MarkChanged,
 
 		ApplyDeferred2,
 
-		/// Expands blueprints that have the [BlueprintNeedsUpdating] marker component.
+		/// Expands blueprints that have the [BlueprintNeedsUpdating] marker component,
+		/// in the order they were registered, see [ExpandBlueprint].
 		ExpandBlueprints,
 
 		ApplyDeferred3,
+
+		/// For systems reacting to [FreshlyExpanded] entities,
+		/// e.g. to spawn things that can't be stamped from a blueprint.
+		Expanded,
+	}
+
+	/// Expands the blueprint named by [ExpandBlueprint::of],
+	/// for ordering expansions relative to each other.
+	#[derive(SystemSet, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+	pub struct ExpandBlueprint(&'static str);
+
+	impl ExpandBlueprint {
+		pub fn of<B: 'static>() -> Self {
+			Self(std::any::type_name::<B>())
+		}
 	}
 }
 
@@ -71,27 +160,494 @@ mod components {
 	/// Marker [Component] that communicates an [Entity] is still in the
 	/// process of being expanded.
 	/// Added in the [BlueprintsSet] [BlueprintsSet::MarkChanged],
-	/// and maybe removed in the [BlueprintsSet::ExpandBlueprints] depending
-	/// on implementation details per blueprint type.
+	/// and removed once it is expanded in [BlueprintsSet::ExpandBlueprints].
 	#[derive(Component, Reflect, Debug, Default)]
 	pub struct BlueprintNeedsUpdating;
+
+	/// If an entity has this component, its blueprint was just expanded.
+	/// Removed the next time the [BlueprintsPlugin]'s schedule runs.
+	#[derive(Component, Reflect, Debug, Default)]
+	#[component(storage = "SparseSet")]
+	pub struct FreshlyExpanded;
+
+	/// On every child stamped by [BlueprintChildren::stamp],
+	/// so it can be kept when its parent is expanded again
+	#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+	pub struct ExpandedChild {
+		/// Hash of the key it was stamped with
+		pub(crate) key: u64,
+
+		/// Hash of the blueprint it was stamped from
+		pub(crate) fingerprint: u64,
+	}
+}
+
+mod traits {
+	use crate::prelude::*;
+
+	/// Represents a type [Blueprint] that can be [Blueprint::stamp]ed into
+	/// a bundle that can be spawned.
+	pub trait Blueprint: std::fmt::Debug {
+		/// The bundle type that this blueprint can be stamped into.
+		type Bundle: Bundle;
+
+		/// A way to access the world when stamping,
+		/// for things like [AssetServer] or [ResMut<Assets<Mesh>>].
+		/// Written with `'static` lifetimes, e.g. `ResMut<'static, Assets<Mesh>>`.
+		type StampSystemParam: SystemParam + 'static;
+
+		/// Stamps this blueprint into a bundle that can be spawned.
+		fn stamp(
+			&self,
+			system_param: &mut SystemParamItem<'_, '_, Self::StampSystemParam>,
+		) -> Self::Bundle;
+
+		/// Stamps the children of an expanded blueprint, none by default.
+		/// Children whose blueprint didn't change since the last expansion are kept,
+		/// see [BlueprintChildren::stamp].
+		fn stamp_children(
+			&self,
+			_system_param: &mut SystemParamItem<'_, '_, Self::StampSystemParam>,
+			_children: &mut BlueprintChildren,
+		) {
+		}
+
+		/// A set to expand this blueprint in, besides its [ExpandBlueprint] set
+		fn expansion_set() -> Option<InternedSystemSet> {
+			None
+		}
+	}
+
+	/// A bundle to spawn a blueprint that is synced over the network.
+	/// The [NetworkedBlueprintBundle::NetworkedBlueprintComponent] is what is replicated,
+	/// together with whatever else is in the bundle, like a [Transform].
+	pub trait NetworkedBlueprintBundle:
+		Bundle + std::ops::Deref<Target = Self::NetworkedBlueprintComponent>
+	{
+		type NetworkedBlueprintComponent: Component + Serialize + DeserializeOwned + Blueprint;
+	}
+}
+
+mod children {
+	use std::collections::hash_map::DefaultHasher;
+	use std::hash::{Hash, Hasher};
+
+	use bevy::utils::{HashMap, HashSet};
+
+	use crate::prelude::*;
+
+	/// The children of a blueprint that is being expanded, see [Blueprint::stamp_children].
+	///
+	/// Children are identified by a key that is unique among their siblings, like a block's ID.
+	/// Expanding a blueprint again only spawns, updates or despawns the children
+	/// that were added, changed or removed, the rest keep their [Entity] and components.
+	pub struct BlueprintChildren<'a, 'w, 's> {
+		parent: Entity,
+		commands: &'a mut Commands<'w, 's>,
+
+		/// Children of the last expansion that haven't been stamped again yet, by key
+		previous: HashMap<u64, (Entity, ExpandedChild)>,
+		stamped: HashSet<u64>,
+	}
+
+	fn hash_of(value: &impl Hash) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		value.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	impl<'a, 'w, 's> BlueprintChildren<'a, 'w, 's> {
+		pub(crate) fn new(
+			parent: Entity,
+			commands: &'a mut Commands<'w, 's>,
+			previous: impl IntoIterator<Item = (Entity, ExpandedChild)>,
+		) -> Self {
+			Self {
+				parent,
+				commands,
+				previous: previous
+					.into_iter()
+					.map(|(child, expanded)| (expanded.key, (child, expanded)))
+					.collect(),
+				stamped: HashSet::new(),
+			}
+		}
+
+		/// Spawns the bundle from `stamp` as the child identified by `key`, stamped from `blueprint`.
+		///
+		/// If the last expansion stamped the same `key` from an identical `blueprint`,
+		/// that child is kept as it is and `stamp` isn't called.
+		/// If the blueprint differs, the new bundle is inserted on the existing child.
+		/// Either way, new and updated children are [FreshlyExpanded].
+		pub fn stamp<B: Bundle>(
+			&mut self,
+			key: impl Hash + std::fmt::Debug,
+			blueprint: &impl Serialize,
+			stamp: impl FnOnce() -> B,
+		) {
+			let expanded = ExpandedChild {
+				key: hash_of(&key),
+				fingerprint: hash_of(&bincode::serialize(blueprint).expect("Blueprints can be serialized")),
+			};
+			if !self.stamped.insert(expanded.key) {
+				warn!(
+					"Not stamping a second child with key {:?} of {:?}",
+					key, self.parent
+				);
+				return;
+			}
+
+			match self.previous.remove(&expanded.key) {
+				Some((_, previous)) if previous == expanded => {}
+				Some((child, _)) => {
+					self
+						.commands
+						.entity(child)
+						.insert((stamp(), expanded, FreshlyExpanded));
+				}
+				None => {
+					let child = self
+						.commands
+						.spawn((stamp(), expanded, FreshlyExpanded))
+						.id();
+					self.commands.entity(self.parent).add_child(child);
+				}
+			}
+		}
+
+		/// Despawns the children that weren't stamped again
+		pub(crate) fn finish(self) {
+			for (child, _) in self.previous.into_values() {
+				self.commands.entity(child).despawn_recursive();
+			}
+		}
+	}
+}
+
+mod registration {
+	use crate::prelude::*;
+
+	/// Every blueprint registered with [BlueprintsAppExt::register_blueprint], in order
+	#[derive(Resource, Debug)]
+	pub(crate) struct RegisteredBlueprints {
+		schedule: InternedScheduleLabel,
+		expansions: Vec<ExpandBlueprint>,
+	}
+
+	impl RegisteredBlueprints {
+		pub(crate) fn new(schedule: InternedScheduleLabel) -> Self {
+			Self {
+				schedule,
+				expansions: Vec::new(),
+			}
+		}
+	}
+
+	pub trait BlueprintsAppExt {
+		/// Expands `B` whenever it is added or changed,
+		/// replicates it with [bevy_replicon] and registers it for reflection.
+		///
+		/// Blueprints are expanded in the order they are registered.
+		///
+		/// # Panics
+		/// If the [BlueprintsPlugin] hasn't been added, or `B` is already registered.
+		fn register_blueprint<B>(&mut self) -> &mut Self
+		where
+			B: Component + Blueprint + Serialize + DeserializeOwned + GetTypeRegistration;
+	}
+
+	impl BlueprintsAppExt for App {
+		fn register_blueprint<B>(&mut self) -> &mut Self
+		where
+			B: Component + Blueprint + Serialize + DeserializeOwned + GetTypeRegistration,
+		{
+			let expansion = ExpandBlueprint::of::<B>();
+			let mut registered = self
+				.world
+				.get_resource_mut::<RegisteredBlueprints>()
+				.expect("Add the BlueprintsPlugin before registering blueprints");
+			assert!(
+				!registered.expansions.contains(&expansion),
+				"Blueprint {} is already registered",
+				std::any::type_name::<B>()
+			);
+			let schedule = registered.schedule;
+			let previous = registered.expansions.last().copied();
+			registered.expansions.push(expansion);
+			debug!("Registering blueprint {}", std::any::type_name::<B>());
+
+			let mut expansion_set = expansion.in_set(BlueprintsSet::ExpandBlueprints);
+			if let Some(set) = B::expansion_set() {
+				expansion_set = expansion_set.in_set(set);
+			}
+			match previous {
+				Some(previous) => self.configure_sets(schedule, expansion_set.after(previous)),
+				None => self.configure_sets(schedule, expansion_set),
+			};
+
+			self.register_type::<B>().replicate::<B>().add_systems(
+				schedule,
+				(
+					mark_changed::<B>.in_set(BlueprintsSet::MarkChanged),
+					expand::<B>.in_set(expansion),
+				),
+			)
+		}
+	}
+
+	fn mark_changed<B: Component>(changed: Query<Entity, Changed<B>>, mut commands: Commands) {
+		for entity in changed.iter() {
+			commands.entity(entity).insert(BlueprintNeedsUpdating);
+		}
+	}
+
+	/// Stamps the blueprint again, and only the children that changed
+	fn expand<B: Component + Blueprint>(
+		instances: Query<(Entity, &B), With<BlueprintNeedsUpdating>>,
+		children: Query<&Children>,
+		expanded_children: Query<&ExpandedChild>,
+		mut commands: Commands,
+		mut system_param: StaticSystemParam<B::StampSystemParam>,
+	) {
+		for (entity, blueprint) in instances.iter() {
+			trace!("Expanding blueprint: {:?}", blueprint);
+			let bundle = blueprint.stamp(&mut system_param);
+			commands
+				.entity(entity)
+				.remove::<BlueprintNeedsUpdating>()
+				.insert((bundle, FreshlyExpanded));
+
+			let previous = children
+				.get(entity)
+				.into_iter()
+				.flat_map(|children| children.iter())
+				.filter_map(|&child| Some((child, *expanded_children.get(child).ok()?)));
+			let mut blueprint_children = BlueprintChildren::new(entity, &mut commands, previous);
+			blueprint.stamp_children(&mut system_param, &mut blueprint_children);
+			blueprint_children.finish();
+		}
+	}
 }
 
 #[cfg(test)]
 mod tests {
+	use bevy_replicon::prelude::ReplicationPlugins;
+	use serde::Deserialize;
+
 	use crate::prelude::*;
 
+	#[derive(ScheduleLabel, Hash, Clone, Copy, PartialEq, Eq, Debug)]
+	struct BlueprintSchedule;
+
+	/// How many times a [Tower] was stamped
+	#[derive(Resource, Default)]
+	struct Stamps(usize);
+
+	#[derive(Component, Reflect, Serialize, Deserialize, Debug)]
+	struct Tower {
+		floors: Vec<FloorBlueprint>,
+	}
+
+	#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy)]
+	struct FloorBlueprint {
+		id: u32,
+		height: u32,
+	}
+
+	#[derive(Component, Debug, PartialEq)]
+	struct Floor {
+		height: u32,
+	}
+
+	impl Tower {
+		fn new(heights: &[u32]) -> Self {
+			Tower {
+				floors: heights
+					.iter()
+					.enumerate()
+					.map(|(id, &height)| FloorBlueprint {
+						id: id as u32,
+						height,
+					})
+					.collect(),
+			}
+		}
+	}
+
+	impl Blueprint for Tower {
+		type Bundle = Name;
+		type StampSystemParam = ResMut<'static, Stamps>;
+
+		fn stamp(&self, stamps: &mut SystemParamItem<'_, '_, Self::StampSystemParam>) -> Name {
+			stamps.0 += 1;
+			Name::new(format!("Tower with {} floors", self.floors.len()))
+		}
+
+		fn stamp_children(
+			&self,
+			_stamps: &mut SystemParamItem<'_, '_, Self::StampSystemParam>,
+			children: &mut BlueprintChildren,
+		) {
+			for floor in &self.floors {
+				children.stamp(floor.id, floor, || Floor {
+					height: floor.height,
+				});
+			}
+		}
+	}
+
 	fn test_app() -> App {
-		App::new()
+		let mut app = App::new();
+		app
+			.add_plugins((
+				MinimalPlugins,
+				ReplicationPlugins,
+				BlueprintsPlugin::new(BlueprintSchedule),
+			))
+			.init_resource::<Stamps>()
+			.register_blueprint::<Tower>();
+		app
 	}
 
-	#[derive(ScheduleLabel, Hash, Clone, Copy, PartialEq, Eq, Debug)]
-	struct BlueprintSchedule;
+	/// Every floor's entity and height, ordered by height
+	fn floors(app: &mut App) -> Vec<(Entity, u32)> {
+		let mut floors: Vec<_> = app
+			.world
+			.query::<(Entity, &Floor)>()
+			.iter(&app.world)
+			.map(|(entity, floor)| (entity, floor.height))
+			.collect();
+		floors.sort_by_key(|(_, height)| *height);
+		floors
+	}
 
 	#[test]
 	fn plugin_initializes() {
-		let mut app = test_app();
+		let mut app = App::new();
 
 		app.add_plugins((MinimalPlugins, BlueprintsPlugin::new(BlueprintSchedule)));
 	}
+
+	#[test]
+	fn expands_added_blueprints() {
+		let mut app = test_app();
+		let tower = app.world.spawn(Tower::new(&[1, 2, 3])).id();
+
+		app.world.run_schedule(BlueprintSchedule);
+		let expanded = app.world.entity(tower);
+		assert_eq!(
+			expanded.get::<Name>().unwrap().as_str(),
+			"Tower with 3 floors"
+		);
+		assert!(expanded.contains::<FreshlyExpanded>());
+		assert!(!expanded.contains::<BlueprintNeedsUpdating>());
+		assert_eq!(expanded.get::<Children>().unwrap().len(), 3);
+		assert_eq!(floors(&mut app).len(), 3);
+
+		app.world.run_schedule(BlueprintSchedule);
+		assert_eq!(
+			app.world.resource::<Stamps>().0,
+			1,
+			"Unchanged blueprints stay expanded"
+		);
+		assert!(!app.world.entity(tower).contains::<FreshlyExpanded>());
+	}
+
+	#[test]
+	fn only_changed_children_are_stamped() {
+		let mut app = test_app();
+		let tower = app.world.spawn(Tower::new(&[1, 2, 3])).id();
+		app.world.run_schedule(BlueprintSchedule);
+		let before = floors(&mut app);
+
+		{
+			let mut blueprint = app.world.get_mut::<Tower>(tower).unwrap();
+			// changes the second floor, and removes the third
+			blueprint.floors[1].height = 20;
+			blueprint.floors.pop();
+			blueprint.floors.push(FloorBlueprint { id: 7, height: 70 });
+		}
+		app.world.run_schedule(BlueprintSchedule);
+		let after = floors(&mut app);
+
+		assert_eq!(app.world.resource::<Stamps>().0, 2);
+		assert_eq!(after.len(), 3);
+		assert_eq!(after[0], before[0], "Unchanged children keep their entity");
+		assert_eq!(
+			after[1],
+			(before[1].0, 20),
+			"Changed children are updated in place"
+		);
+		assert!(
+			app.world.get_entity(before[2].0).is_none(),
+			"Removed children are despawned"
+		);
+		assert_eq!(after[2].1, 70);
+
+		assert!(!app.world.entity(after[0].0).contains::<FreshlyExpanded>());
+		assert!(app.world.entity(after[1].0).contains::<FreshlyExpanded>());
+		assert!(app.world.entity(after[2].0).contains::<FreshlyExpanded>());
+		assert_eq!(app.world.entity(tower).get::<Children>().unwrap().len(), 3);
+	}
+
+	#[derive(SystemSet, Hash, Clone, Copy, PartialEq, Eq, Debug)]
+	struct LampSet;
+
+	#[derive(Component, Debug, PartialEq)]
+	struct Brightness(u32);
+
+	#[derive(Bundle)]
+	struct LampBundle {
+		name: Name,
+		brightness: Brightness,
+	}
+
+	#[derive(Component, Reflect, Serialize, Deserialize, Debug, Blueprint)]
+	#[blueprint(
+		bundle = LampBundle,
+		param(stamps: ResMut<'static, Stamps>),
+		stamp(
+			name = {
+				stamps.0 += 1;
+				Name::new("Lamp")
+			},
+			brightness = Brightness(self.brightness),
+		),
+		expansion_set = LampSet,
+	)]
+	struct Lamp {
+		brightness: u32,
+	}
+
+	#[derive(Bundle, NetworkedBlueprintBundle)]
+	struct LampBlueprintBundle {
+		transform: Transform,
+		#[blueprint]
+		lamp: Lamp,
+	}
+
+	#[test]
+	fn derived_blueprints_expand() {
+		let mut app = test_app();
+		app.register_blueprint::<Lamp>();
+		assert_eq!(Lamp::expansion_set(), Some(LampSet.intern()));
+
+		let bundle = LampBlueprintBundle {
+			transform: Transform::default(),
+			lamp: Lamp { brightness: 3 },
+		};
+		assert_eq!(bundle.brightness, 3, "Derefs to the blueprint");
+		let lamp = app.world.spawn(bundle).id();
+
+		app.world.run_schedule(BlueprintSchedule);
+		assert_eq!(app.world.get::<Brightness>(lamp), Some(&Brightness(3)));
+		assert_eq!(app.world.get::<Name>(lamp).unwrap().as_str(), "Lamp");
+		assert_eq!(app.world.resource::<Stamps>().0, 1);
+	}
+
+	#[test]
+	#[should_panic(expected = "already registered")]
+	fn registering_twice_panics() {
+		test_app().register_blueprint::<Tower>();
+	}
 }
@@ -0,0 +1,22 @@
+//! Replays a session recorded with `space_craft server --record <FILE>`, e.g.
+//! `space_craft_replay session.bin --paused`.
+//!
+//! Space pauses, `.` steps one tick while paused, Up and Down change the speed,
+//! Left and Right seek and Tab follows the next player.
+
+use bevy::prelude::*;
+
+fn main() {
+	App::new()
+		.add_plugins(DefaultPlugins.set(WindowPlugin {
+			primary_window: Some(Window {
+				title: "Creativity Game Replay".to_string(),
+				..default()
+			}),
+			..default()
+		}))
+		// configures the game before [space_craft::MainPlugin] looks at the CLI
+		.add_plugins(space_craft::ReplayPlugin::from_args())
+		.add_plugins(space_craft::MainPlugin)
+		.run();
+}
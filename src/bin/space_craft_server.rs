@@ -0,0 +1,16 @@
+//! Dedicated server without a window or any rendering.
+//!
+//! Takes the same arguments as `space_craft server`, e.g.
+//! `space_craft_server --ip 0.0.0.0 --config server.toml`.
+//! Stops gracefully on SIGTERM or Ctrl-C.
+
+use bevy::prelude::*;
+
+fn main() {
+	App::new()
+		.add_plugins((
+			space_craft::DedicatedServerPlugin::from_args(),
+			space_craft::TerminalPlugin,
+		))
+		.run();
+}
@@ -2,6 +2,8 @@ use crate::prelude::*;
 
 mod worldgen;
 
+pub use worldgen::TerrainStructureBlueprint;
+
 pub struct BlockPlugins;
 
 impl PluginGroup for BlockPlugins {
@@ -23,8 +25,9 @@ impl Default for BlockId {
 }
 
 impl BlockId {
-	pub fn random() -> Self {
-		Self(random())
+	/// Draw from [GameRng::block_ids] when simulating, so that IDs are the same every run
+	pub fn random(rng: &mut impl Rng) -> Self {
+		Self(rng.gen())
 	}
 }
 
@@ -42,9 +45,23 @@ pub struct BlockBlueprint<T> {
 	pub specific_marker: T,
 }
 
+impl<T> BlockBlueprint<T> {
+	/// The mesh and material of this block, placed relative to its parent
+	pub fn pbr_bundle(&self, mma: &mut MMA) -> PbrBundle {
+		PbrBundle {
+			transform: self.transform,
+			mesh: self.mesh.clone().into_mesh(mma),
+			material: self.material.clone().into_material(&mut mma.mats),
+			..default()
+		}
+	}
+}
+
 pub mod manual_builder {
 	use crate::prelude::*;
 
+	/// Which way a block points, as saved in ship designs
+	#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
 	pub enum Facing {
 		Up,
 		Down,
@@ -65,6 +82,11 @@ pub mod manual_builder {
 				Self::Down => Quat::from_rotation_x(-TAU / 4.),
 			}
 		}
+
+		/// The [Facing] that `rotation` is, if it is one of them
+		pub fn from_quat(rotation: Quat) -> Option<Self> {
+			Self::iter().find(|facing| facing.into_quat().angle_between(rotation) < 0.001)
+		}
 	}
 
 	impl From<Facing> for Quat {
@@ -74,6 +96,7 @@ pub mod manual_builder {
 	}
 
 	#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy, Reflect)]
+	#[serde(transparent)]
 	pub struct RelativePixel(pub IVec3);
 
 	impl From<IVec3> for RelativePixel {
@@ -83,7 +106,6 @@ pub mod manual_builder {
 	}
 
 	impl RelativePixel {
-		#[cfg_attr(not(test), allow(dead_code))]
 		pub fn new(x: i32, y: i32, z: i32) -> Self {
 			Self::from(IVec3::new(x, y, z))
 		}
@@ -91,6 +113,34 @@ pub mod manual_builder {
 		pub fn into_world_offset(self) -> Vec3 {
 			self.0.as_vec3().mul(PIXEL_SIZE)
 		}
+
+		/// The 6 pixels sharing a face with this one
+		pub fn neighbours(self) -> impl Iterator<Item = Self> {
+			[
+				IVec3::X,
+				IVec3::NEG_X,
+				IVec3::Y,
+				IVec3::NEG_Y,
+				IVec3::Z,
+				IVec3::NEG_Z,
+			]
+			.into_iter()
+			.filter_map(move |offset| {
+				Some(Self::new(
+					self.0.x.checked_add(offset.x)?,
+					self.0.y.checked_add(offset.y)?,
+					self.0.z.checked_add(offset.z)?,
+				))
+			})
+		}
+
+		/// The pixel at `offset`, if it is (almost) exactly on one
+		pub fn from_world_offset(offset: Vec3) -> Option<Self> {
+			let pixels = offset.div(PIXEL_SIZE);
+			let rounded = pixels.round();
+			(rounded.abs_diff_eq(pixels, 0.001) && rounded.abs().max_element() <= i32::MAX as f32)
+				.then(|| Self(rounded.as_ivec3()))
+		}
 	}
 
 	#[cfg(test)]
@@ -126,10 +176,32 @@ pub mod manual_builder {
 
 			assert_eq!(forwards, Vec3::Y);
 		}
+
+		#[test]
+		fn facing_from_quat() {
+			for facing in Facing::iter() {
+				assert_eq!(Facing::from_quat(facing.into_quat()), Some(facing));
+			}
+			assert_eq!(Facing::from_quat(Quat::from_rotation_z(0.3)), None);
+		}
+
+		#[test]
+		fn pixel_from_world_offset() {
+			let pixel = RelativePixel::new(-1, 2, 3);
+			assert_eq!(
+				RelativePixel::from_world_offset(pixel.into_world_offset()),
+				Some(pixel)
+			);
+			assert_eq!(
+				RelativePixel::from_world_offset(Vec3::splat(PIXEL_SIZE / 2.)),
+				None
+			);
+			assert_eq!(RelativePixel::from_world_offset(Vec3::NAN), None);
+		}
 	}
 }
 
-pub use structure_block::{StructureBlockBlueprint, StructureBlockBundle};
+pub use structure_block::{StructureBlockBlueprint, StructureBlockBundle, StructureBlockKind};
 mod structure_block;
 
 /// Since raw [Mesh] cannot be serialized
@@ -4,58 +4,63 @@ use super::manual_builder;
 use super::BlockBlueprint;
 
 /// Used for building structures
-#[derive(Debug, Reflect, Serialize, Deserialize, Clone, IntoStaticStr)]
-pub enum StructureBlockBlueprint {
+#[derive(Debug, Reflect, Serialize, Deserialize, Clone, Blueprint)]
+#[blueprint(
+	for = BlockBlueprint<StructureBlockBlueprint>,
+	bundle = StructureBlockBundle,
+	param(mma: MMA<'static>),
+	stamp(
+		pbr = self.pbr_bundle(mma),
+		collider = AsyncCollider(ComputedCollider::ConvexHull),
+		name = Name::new(format!("StructureBlock {}", self.kind.name())),
+		id = self.id,
+	)
+)]
+pub struct StructureBlockBlueprint {
+	pub id: BlockId,
+	pub kind: StructureBlockKind,
+}
+
+/// What a [StructureBlockBlueprint] is made of
+#[derive(Debug, Reflect, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, IntoStaticStr)]
+pub enum StructureBlockKind {
 	Aluminum,
 }
 
-impl StructureBlockBlueprint {
+impl StructureBlockKind {
 	pub fn name(&self) -> &'static str {
 		self.into()
 	}
 }
 
+impl GetBlockId for StructureBlockBlueprint {
+	fn get_block_id(&self) -> BlockId {
+		self.id
+	}
+}
+
 #[derive(Bundle)]
 pub struct StructureBlockBundle {
 	pbr: PbrBundle,
 	collider: AsyncCollider,
 	name: Name,
-}
-
-impl Blueprint for BlockBlueprint<StructureBlockBlueprint> {
-	type Bundle = StructureBlockBundle;
-	type StampSystemParam<'w, 's> = MMA<'w>;
-
-	fn stamp(&self, mma: &mut Self::StampSystemParam<'_, '_>) -> Self::Bundle {
-		let BlockBlueprint {
-			transform,
-			mesh,
-			material,
-			specific_marker,
-		} = self;
-		Self::Bundle {
-			pbr: PbrBundle {
-				transform: *transform,
-				mesh: mesh.clone().into_mesh(mma),
-				material: material.clone().into_material(&mut mma.mats),
-				..default()
-			},
-			collider: AsyncCollider(ComputedCollider::ConvexHull),
-			name: Name::new(format!("StructureBlock {}", specific_marker.name())),
-		}
-	}
+	id: BlockId,
 }
 
 impl BlockBlueprint<StructureBlockBlueprint> {
 	pub fn new_structure(
-		block: StructureBlockBlueprint,
+		kind: StructureBlockKind,
 		location: impl Into<manual_builder::RelativePixel>,
+		rng: &mut impl Rng,
 	) -> Self {
 		BlockBlueprint {
 			transform: Transform::from_translation(location.into().into_world_offset()),
 			mesh: super::OptimizableMesh::StandardBlock,
 			material: super::OptimizableMaterial::OpaqueColour(Color::SILVER),
-			specific_marker: block,
+			specific_marker: StructureBlockBlueprint {
+				id: BlockId::random(rng),
+				kind,
+			},
 		}
 	}
 }
@@ -1,18 +1,20 @@
 use crate::prelude::*;
 
+pub use terrain_blueprint::TerrainStructureBlueprint;
+
 pub struct WorldGenPlugin;
 
 impl Plugin for WorldGenPlugin {
 	fn build(&self, app: &mut App) {
+		app.depends_on::<RepliconCorePlugin, _>(ReplicationPlugins);
+
 		app
-			.add_systems(
-				Blueprints,
-				Self::expand_terrain_structure.in_set(BlueprintExpansion::Terrain),
-			)
+			.register_networked_blueprint::<TerrainStructureBlueprint>("TerrainStructureBlueprint")
 			.add_systems(
 				WorldCreation,
 				Self::creation_spawn_random_world.in_set(WorldCreationSet::Asteroids),
-			);
+			)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::despawn_terrain);
 	}
 }
 
@@ -28,31 +30,31 @@ mod systems {
 	};
 
 	impl WorldGenPlugin {
-		pub(super) fn expand_terrain_structure(
-			mut blueprints: Query<(Entity, &TerrainStructureBlueprint), Added<TerrainStructureBlueprint>>,
+		/// Replicated structures are already despawned with the rest of the replicated world
+		pub(super) fn despawn_terrain(
+			structures: Query<Entity, (With<TerrainStructureBlueprint>, Without<Replication>)>,
 			mut commands: Commands,
-			mut mma: MMA,
 		) {
-			for (entity, blueprint) in blueprints.iter_mut() {
-				commands
-					.entity(entity)
-					.insert(blueprint.stamp(&mut mma))
-					.with_children(|parent| {
-						for child in blueprint.clone().into_children().iter() {
-							parent.spawn(child.stamp(&mut mma));
-						}
-					});
+			for structure in structures.iter() {
+				commands.entity(structure).despawn_recursive();
 			}
 		}
 
-		pub(super) fn creation_spawn_random_world(mut commands: Commands) {
+		/// The same asteroids every time for the same world seed
+		pub(super) fn creation_spawn_random_world(mut commands: Commands, rng: Res<GameRng>) {
 			debug!("Spawning initial asteroids");
 
-			let mut rng = rand::thread_rng();
 			const NUM: usize = 0;
-			let structures: Vec<TerrainStructureBlueprint> = (0..NUM)
+			commands.spawn_batch(Self::random_asteroids(&mut rng.worldgen(), NUM));
+		}
+
+		pub(super) fn random_asteroids(
+			rng: &mut impl Rng,
+			count: usize,
+		) -> Vec<TerrainStructureBlueprint> {
+			(0..count)
 				.map(|_| {
-					let pos = vec3_polar_random(&mut rng);
+					let pos = vec3_polar_random(rng);
 					let distance = 20.0..30.0;
 					let pos = pos * rng.gen_range(distance);
 
@@ -78,12 +80,20 @@ mod systems {
 						terrain_type: TerrainType::SilicateRock,
 					}
 				})
-				.collect();
-
-			commands.spawn_batch(structures);
+				.collect()
 		}
 	}
 
+	#[test]
+	fn same_seed_same_asteroids() {
+		let asteroids = |seed: u64| {
+			let asteroids = WorldGenPlugin::random_asteroids(&mut GameRng::new(seed).worldgen(), 10);
+			format!("{:?}", asteroids)
+		};
+		assert_eq!(asteroids(1), asteroids(1));
+		assert_ne!(asteroids(1), asteroids(2));
+	}
+
 	#[test]
 	fn test_world_gen_expands() {
 		let mut app = test_app();
@@ -141,6 +151,16 @@ mod terrain_blueprint {
 				})
 				.collect()
 		}
+
+		/// Radius of a sphere, centered on [Self::transform], that contains every
+		/// [TerrainItemBlueprint] of this structure.
+		pub fn bounding_radius(&self) -> f32 {
+			let pixels = match &self.shape {
+				OptimizableDiscreteShape::Dot => 1.0,
+				OptimizableDiscreteShape::Sphere(sphere) => sphere.radius.get() as f32 + 1.0,
+			};
+			pixels * PIXEL_SIZE * self.transform.scale.max_element()
+		}
 	}
 }
 
@@ -210,9 +230,9 @@ mod terrain_bundle {
 
 	impl Blueprint for TerrainItemBlueprint {
 		type Bundle = TerrainItemBundle;
-		type StampSystemParam<'w, 's> = MMA<'w>;
+		type StampSystemParam = MMA<'static>;
 
-		fn stamp(&self, mma: &mut Self::StampSystemParam<'_, '_>) -> Self::Bundle {
+		fn stamp(&self, mma: &mut SystemParamItem<'_, '_, Self::StampSystemParam>) -> Self::Bundle {
 			let TerrainItemBlueprint {
 				terrain_type,
 				location,
@@ -239,13 +259,17 @@ mod terrain_bundle {
 		linvel: LinearVelocity,
 		angvel: AngularVelocity,
 		mass_properties: MassPropertiesBundle,
+		replication: Replication,
 	}
 
 	impl Blueprint for TerrainStructureBlueprint {
 		type Bundle = TerrainStructureBundle;
-		type StampSystemParam<'w, 's> = MMA<'w>;
+		type StampSystemParam = MMA<'static>;
 
-		fn stamp(&self, _system_param: &mut Self::StampSystemParam<'_, '_>) -> Self::Bundle {
+		fn stamp(
+			&self,
+			_system_param: &mut SystemParamItem<'_, '_, Self::StampSystemParam>,
+		) -> Self::Bundle {
 			let TerrainStructureBlueprint {
 				transform,
 				initial_velocity,
@@ -269,6 +293,17 @@ mod terrain_bundle {
 				linvel,
 				angvel,
 				mass_properties: MassPropertiesBundle::new_computed(&Collider::ball(1.0), 1.0),
+				replication: Replication,
+			}
+		}
+
+		fn stamp_children(
+			&self,
+			mma: &mut SystemParamItem<'_, '_, Self::StampSystemParam>,
+			children: &mut BlueprintChildren,
+		) {
+			for child in self.clone().into_children().iter() {
+				children.stamp(child.location, child, || child.stamp(mma));
 			}
 		}
 	}
@@ -23,8 +23,10 @@ impl Plugin for CameraPlugin {
 					Dolly::<camera_bundle::CameraMarker>::update_active,
 				)
 					.chain()
-					.in_set(Client),
-			);
+					.in_set(Client)
+					.run_if(in_state(GlobalGameStates::InGame)),
+			)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::despawn_cameras);
 	}
 }
 
@@ -160,6 +162,14 @@ mod systems {
 			}
 		}
 
+		/// The next game spawns its own cameras
+		pub(super) fn despawn_cameras(
+			mut commands: Commands,
+			mut config: ResMut<resources::CamerasConfig>,
+		) {
+			config.despawn_cameras(&mut commands);
+		}
+
 		pub(super) fn handle_change_camera_events(
 			mut commands: Commands,
 			mut events: EventReader<ChangeCameraConfig>,
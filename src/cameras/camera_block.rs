@@ -10,11 +10,28 @@ pub struct CameraBlockBundle {
 }
 
 /// Blueprint for [CameraBlockBundle]
-#[derive(Debug, Reflect, Serialize, Deserialize, Clone)]
+#[derive(Debug, Reflect, Serialize, Deserialize, Clone, Blueprint)]
+#[blueprint(
+	for = BlockBlueprint<CameraBlockBlueprint>,
+	bundle = CameraBlockBundle,
+	param(mma: MMA<'static>),
+	stamp(
+		pbr = self.pbr_bundle(mma),
+		name = Name::new("CameraBlock"),
+		id = self.id,
+		marker = CameraBlockMarker,
+	)
+)]
 pub struct CameraBlockBlueprint {
 	pub id: BlockId,
 }
 
+impl GetBlockId for CameraBlockBlueprint {
+	fn get_block_id(&self) -> BlockId {
+		self.id
+	}
+}
+
 /// Marker for [BlockBlueprint]s that are [CameraBlockBlueprint]s,
 /// which spawn [CameraBlockBundle]s.
 #[derive(Component)]
@@ -24,6 +41,7 @@ impl BlockBlueprint<CameraBlockBlueprint> {
 	pub fn new_camera(
 		position: impl Into<manual_builder::RelativePixel>,
 		facing: impl Into<Quat>,
+		rng: &mut impl Rng,
 	) -> Self {
 		Self {
 			transform: Transform::from_rotation(facing.into())
@@ -33,33 +51,8 @@ impl BlockBlueprint<CameraBlockBlueprint> {
 			},
 			material: OptimizableMaterial::OpaqueColour(Color::BLACK),
 			specific_marker: CameraBlockBlueprint {
-				id: BlockId::random(),
-			},
-		}
-	}
-}
-
-impl Blueprint for BlockBlueprint<CameraBlockBlueprint> {
-	type Bundle = CameraBlockBundle;
-	type StampSystemParam<'w, 's> = MMA<'w>;
-
-	fn stamp(&self, mma: &mut Self::StampSystemParam<'_, '_>) -> Self::Bundle {
-		let BlockBlueprint {
-			transform,
-			mesh,
-			material,
-			specific_marker,
-		} = self;
-		Self::Bundle {
-			pbr: PbrBundle {
-				transform: *transform,
-				mesh: mesh.clone().into_mesh(mma),
-				material: material.clone().into_material(&mut mma.mats),
-				..default()
+				id: BlockId::random(rng),
 			},
-			name: Name::new("CameraBlock"),
-			id: specific_marker.id,
-			marker: CameraBlockMarker,
 		}
 	}
 }
@@ -42,6 +42,17 @@ impl CamerasConfig {
 		}
 	}
 
+	/// Despawns whichever camera is spawned, going back to [CamerasConfig::None]
+	pub(super) fn despawn_cameras(&mut self, commands: &mut Commands) {
+		match self {
+			CamerasConfig::None => {}
+			CamerasConfig::Fallback { cam } | CamerasConfig::PrimaryCamera { cam, .. } => {
+				commands.entity(**cam).despawn_recursive();
+			}
+		}
+		*self = CamerasConfig::None;
+	}
+
 	pub(super) fn requires_fallback(&self) -> bool {
 		matches!(self, CamerasConfig::None)
 	}
@@ -0,0 +1,210 @@
+//! Runs a headless server without a window, rendering, particles or the editor,
+//! so that it can be hosted on a machine without a GPU.
+//!
+//! Used by the `space_craft_server` binary. SIGTERM and Ctrl-C disconnect every client
+//! before exiting, so that they are told the server went away instead of timing out.
+//! Those and the admin commands on stdin are only hooked up by [TerminalPlugin],
+//! so that tests can run a dedicated server without taking over the test runner's.
+
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Arc,
+};
+
+use bevy::{
+	app::{AppExit, ScheduleRunnerPlugin},
+	asset::AssetPlugin,
+	input::InputPlugin,
+	log::LogPlugin,
+	transform::TransformPlugin,
+};
+
+use crate::prelude::*;
+
+/// Replaces [crate::MainPlugin] and [DefaultPlugins] for dedicated servers
+pub struct DedicatedServerPlugin {
+	/// Always a [NetcodeConfig::Server], forced to be headless
+	config: NetcodeConfig,
+}
+
+impl DedicatedServerPlugin {
+	/// Takes the same arguments as `space_craft server`,
+	/// i.e. `space_craft_server --port 5069` is parsed as `space_craft server --port 5069`.
+	pub fn from_args() -> Self {
+		let mut args = std::env::args_os();
+		let bin = args.next().unwrap_or_else(|| "space_craft_server".into());
+		let config = NetcodeConfig::parse_from([bin, "server".into()].into_iter().chain(args));
+		Self::new(config)
+	}
+
+	/// Panics if `config` isn't a [NetcodeConfig::Server]
+	pub fn new(mut config: NetcodeConfig) -> Self {
+		let NetcodeConfig::Server { headless, .. } = &mut config else {
+			panic!(
+				"A dedicated server can only be configured as a server, not {:?}",
+				config
+			);
+		};
+		*headless = true;
+		Self { config }
+	}
+}
+
+impl Plugin for DedicatedServerPlugin {
+	fn build(&self, app: &mut App) {
+		let NetcodeConfig::Server { settings, .. } = &self.config else {
+			unreachable!("checked in DedicatedServerPlugin::new")
+		};
+		let settings = settings.resolve().unwrap_or_else(|err| panic!("{}", err));
+
+		app.add_plugins((
+			MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+				1. / settings.tick_rate,
+			))),
+			LogPlugin {
+				level: bevy::log::Level::INFO,
+				filter: "space_craft=debug,bevy_replicon=info,renet=info".into(),
+			},
+			TransformPlugin,
+			HierarchyPlugin,
+			InputPlugin,
+			AssetPlugin::default(),
+		));
+
+		// blueprints still stamp meshes and particles, they just aren't rendered
+		app
+			.init_asset::<Mesh>()
+			.init_asset::<StandardMaterial>()
+			.init_asset::<Image>()
+			.init_asset::<EffectAsset>();
+
+		app
+			.insert_resource(settings)
+			.insert_resource(self.config.clone())
+			.add_state::<GlobalGameStates>()
+			.insert_resource(NextState(Some(GlobalGameStates::InGame)));
+
+		app.add_plugins(crate::SimulationPlugin);
+
+		app
+			.init_resource::<ShutdownSignal>()
+			.add_systems(Update, Self::shut_down_gracefully);
+	}
+}
+
+/// Reads admin commands from stdin, and requests a [ShutdownSignal] on SIGTERM or Ctrl-C.
+///
+/// Added after [DedicatedServerPlugin] by the `space_craft_server` binary,
+/// tests leave it out and request shutdowns directly.
+pub struct TerminalPlugin;
+
+impl Plugin for TerminalPlugin {
+	fn build(&self, app: &mut App) {
+		app.world.resource::<AdminConsole>().read_stdin();
+
+		let shutdown = app.world.resource::<ShutdownSignal>().clone();
+		if let Err(err) = ctrlc::set_handler(move || shutdown.request()) {
+			warn!(
+				"Couldn't handle SIGTERM, clients won't be notified when the server stops: {}",
+				err
+			);
+		}
+	}
+}
+
+/// Set from the signal handler once the server should stop
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ShutdownSignal(Arc<AtomicBool>);
+
+impl ShutdownSignal {
+	pub fn request(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_requested(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+}
+
+impl DedicatedServerPlugin {
+	/// Leaves [GlobalGameStates::InGame] first, which disconnects every client,
+	/// then exits on the next update.
+	fn shut_down_gracefully(
+		signal: Res<ShutdownSignal>,
+		state: Res<State<GlobalGameStates>>,
+		mut next_state: ResMut<NextState<GlobalGameStates>>,
+		mut app_exit: EventWriter<AppExit>,
+	) {
+		if !signal.is_requested() {
+			return;
+		}
+		match state.get() {
+			GlobalGameStates::InGame => {
+				info!("Shutting down, disconnecting every client");
+				next_state.set(GlobalGameStates::StartMenu);
+			}
+			_ => {
+				info!("Shut down");
+				app_exit.send(AppExit);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use bevy::{app::AppExit, time::TimeUpdateStrategy};
+
+	use crate::prelude::*;
+
+	use super::{DedicatedServerPlugin, ShutdownSignal};
+
+	#[test]
+	fn shutdown_disconnects_clients() {
+		let network = LoopbackNetwork::new(LinkConditions::PERFECT, 0);
+		let mut server = App::new();
+		server.add_plugins(DedicatedServerPlugin::new(loopback_server_config(
+			&network,
+			ServerSettingsArgs::NONE,
+		)));
+		let mut client = test_netcode_app(loopback_client_config(&network));
+		// in lockstep with the client, like [test_netcode_app]
+		server.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+			1. / 64.,
+		)));
+
+		update_all(60, &mut [&mut server, &mut client]);
+		assert!(
+			server.world.resource::<NetcodeConfig>().get_headless(),
+			"Dedicated servers never have a player of their own"
+		);
+		assert_eq!(
+			client
+				.world
+				.query_filtered::<(), With<crate::players::PlayerBlueprintComponent>>()
+				.iter(&client.world)
+				.count(),
+			1,
+			"The world should have been created for the client to spawn in"
+		);
+		assert_eq!(
+			*client
+				.world
+				.resource::<State<ClientConnectionState>>()
+				.get(),
+			ClientConnectionState::Connected
+		);
+
+		server.world.resource::<ShutdownSignal>().request();
+		update_all(4, &mut [&mut server, &mut client]);
+
+		assert_eq!(
+			*client
+				.world
+				.resource::<State<ClientConnectionState>>()
+				.get(),
+			ClientConnectionState::Disconnected
+		);
+		assert!(!server.world.resource::<Events<AppExit>>().is_empty());
+	}
+}
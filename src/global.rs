@@ -8,8 +8,20 @@ use crate::prelude::*;
 
 pub mod assets;
 pub mod blueprints;
+pub mod rng;
 
 pub const DEFAULT_PORT: u16 = 5069;
+/// Port that the [crate::netcode] token issuer listens on, when hosting securely.
+pub const DEFAULT_TOKEN_PORT: u16 = DEFAULT_PORT + 1;
+/// UDP port that servers announce themselves to the LAN on, see [crate::netcode].
+pub const DISCOVERY_PORT: u16 = DEFAULT_PORT + 2;
+/// `netcode`'s protocol ID, only changed if the connection handshake itself changes.
+///
+/// It deliberately isn't derived from [ProtocolVersion::protocol_id], nor configurable:
+/// `netcode` silently drops connection requests with another protocol ID, so an incompatible
+/// client would just time out. The handshake has to reach the server for it to send a
+/// [HandshakeRejection], so the derived ID is compared in the connection's user data instead,
+/// see [ProtocolVersion::is_compatible_with].
 pub const PROTOCOL_ID: u64 = 0;
 pub const PIXEL_SIZE: f32 = 1.; // how many pixels per block
 
@@ -20,6 +32,9 @@ pub struct GameLogic;
 /// Configured for [FixedUpdate] ONLY!
 #[derive(SystemSet, Hash, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum GlobalSystemSet {
+	/// Advances the [GameClock], then reseeds the [rng::GameRng] from it.
+	TickClocks,
+
 	/// Runs the [WorldCreation] [Schedule].
 	WorldCreation,
 
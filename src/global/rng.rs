@@ -0,0 +1,213 @@
+//! Randomness for the simulation, which has to come out the same
+//! every time the same world is simulated, see [GameRng].
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::prelude::*;
+
+/// The only source of randomness for systems in [FixedUpdate] and the schedules it runs,
+/// like [WorldCreation] and [GameLogic]. Never use [rand::thread_rng] there.
+///
+/// Everything is derived from the world seed, chosen with `--seed` when hosting,
+/// so the same seed creates the same world. Each [RngStream] is independent,
+/// so e.g. drawing more for gameplay doesn't change the asteroids.
+#[derive(Resource, Debug)]
+pub struct GameRng {
+	seed: u64,
+
+	/// Reseeded at the start of every tick, see [GameRng::gameplay]
+	gameplay: StdRng,
+}
+
+/// What the numbers drawn from a [GameRng] are used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RngStream {
+	WorldGen,
+	BlockIds,
+	Gameplay,
+}
+
+impl Default for GameRng {
+	fn default() -> Self {
+		Self::new(Self::DEFAULT_SEED)
+	}
+}
+
+impl GameRng {
+	/// Used by clients, and servers until they start hosting
+	pub const DEFAULT_SEED: u64 = 0x5eed;
+
+	pub fn new(seed: u64) -> Self {
+		Self {
+			seed,
+			gameplay: Self::derive_from(seed, RngStream::Gameplay, 0),
+		}
+	}
+
+	pub fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	/// A fresh sequence that only depends on the seed, `stream` and `key`
+	pub fn derive(&self, stream: RngStream, key: u64) -> StdRng {
+		Self::derive_from(self.seed, stream, key)
+	}
+
+	fn derive_from(seed: u64, stream: RngStream, key: u64) -> StdRng {
+		// splitmix64, so that neighbouring keys give unrelated seeds
+		fn mix(mut x: u64) -> u64 {
+			x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+			x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+			x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+			x ^ (x >> 31)
+		}
+		StdRng::seed_from_u64(mix(mix(seed ^ stream as u64) ^ key))
+	}
+
+	/// The same every time the world is created
+	pub fn worldgen(&self) -> StdRng {
+		self.derive(RngStream::WorldGen, 0)
+	}
+
+	/// For the blocks of `owner`'s ship, which get the same IDs every time it is built
+	pub fn block_ids(&self, owner: ClientId) -> StdRng {
+		self.derive(RngStream::BlockIds, owner.raw())
+	}
+
+	/// For anything random that happens while playing.
+	///
+	/// Restarted from the [GameClock] frame at the start of every tick,
+	/// in [GlobalSystemSet::TickClocks], so what a tick draws doesn't depend on
+	/// how much was drawn in the ticks before it.
+	pub fn gameplay(&mut self) -> &mut StdRng {
+		&mut self.gameplay
+	}
+
+	/// Starts the [GameRng::gameplay] sequence for `tick` from the beginning
+	pub fn reseed(&mut self, tick: u32) {
+		self.gameplay = self.derive(RngStream::Gameplay, u64::from(tick));
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::players::PlayerBlueprintComponent;
+	use crate::prelude::*;
+
+	use super::{GameRng, RngStream};
+
+	#[test]
+	fn reseeding_repeats_the_tick() {
+		let mut rng = GameRng::new(42);
+		rng.reseed(7);
+		let first: [u64; 4] = rng.gameplay().gen();
+
+		rng.reseed(8);
+		let other: [u64; 4] = rng.gameplay().gen();
+		assert_ne!(first, other, "Every tick draws different numbers");
+
+		rng.reseed(7);
+		assert_eq!(rng.gameplay().gen::<[u64; 4]>(), first);
+
+		let mut reseeded = GameRng::new(43);
+		reseeded.reseed(7);
+		assert_ne!(
+			reseeded.gameplay().gen::<[u64; 4]>(),
+			first,
+			"The seed matters too"
+		);
+	}
+
+	#[test]
+	fn streams_are_independent() {
+		let rng = GameRng::new(42);
+		let draw = |stream: RngStream, key: u64| rng.derive(stream, key).gen::<[u64; 4]>();
+
+		assert_eq!(draw(RngStream::WorldGen, 0), draw(RngStream::WorldGen, 0));
+		assert_ne!(draw(RngStream::WorldGen, 0), draw(RngStream::BlockIds, 0));
+		assert_ne!(draw(RngStream::BlockIds, 1), draw(RngStream::BlockIds, 2));
+		assert_eq!(
+			GameRng::new(42).worldgen().gen::<[u64; 4]>(),
+			draw(RngStream::WorldGen, 0),
+			"Only depends on the seed"
+		);
+	}
+
+	/// Everything simulated, as bits so that even the last digit has to match
+	fn snapshot(app: &mut App) -> Vec<(Option<BlockId>, [u32; 3], [u32; 4])> {
+		app
+			.world
+			.query_filtered::<(Option<&BlockId>, &Transform), Or<(With<Position>, With<BlockId>)>>()
+			.iter(&app.world)
+			.map(|(id, transform)| {
+				(
+					id.copied(),
+					transform.translation.to_array().map(f32::to_bits),
+					transform.rotation.to_array().map(f32::to_bits),
+				)
+			})
+			.collect()
+	}
+
+	fn simulate(seed: u64) -> Vec<(Option<BlockId>, [u32; 3], [u32; 4])> {
+		let mut app = test_netcode_app(loopback_server_config(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			ServerSettingsArgs {
+				seed: Some(seed),
+				..ServerSettingsArgs::NONE
+			},
+		));
+		for frame in 0..180 {
+			let mut keys = app.world.resource_mut::<Input<KeyCode>>();
+			match frame {
+				30 => keys.press(KeyCode::W),
+				90 => keys.press(KeyCode::A),
+				120 => keys.release_all(),
+				_ => {}
+			}
+			app.update();
+		}
+
+		assert_eq!(app.world.resource::<GameRng>().seed(), seed);
+		let players = app
+			.world
+			.query::<&PlayerBlueprintComponent>()
+			.iter(&app.world)
+			.count();
+		assert_eq!(players, 1, "The host is playing");
+		snapshot(&mut app)
+	}
+
+	#[test]
+	fn random_seed_is_kept_with_the_settings() {
+		let mut app = test_netcode_app(loopback_server_config(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			ServerSettingsArgs::NONE,
+		));
+		app.update();
+		app.update();
+
+		let seed = app.world.resource::<GameRng>().seed();
+		assert_eq!(app.world.resource::<ServerSettings>().seed, Some(seed));
+	}
+
+	#[test]
+	fn same_inputs_same_simulation() {
+		let first = simulate(1);
+		assert!(!first.is_empty());
+		assert!(
+			first.iter().any(|(id, _, _)| id.is_some()),
+			"The player's blocks are simulated"
+		);
+		assert_eq!(first, simulate(1));
+
+		let block_ids = |snapshot: &[(Option<BlockId>, [u32; 3], [u32; 4])]| -> Vec<BlockId> {
+			snapshot.iter().filter_map(|(id, _, _)| *id).collect()
+		};
+		assert_ne!(
+			block_ids(&first),
+			block_ids(&simulate(2)),
+			"Another seed builds the same ship with other block IDs"
+		);
+	}
+}
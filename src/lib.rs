@@ -18,11 +18,13 @@
 
 mod blocks;
 mod cameras;
+mod dedicated_server;
 mod global;
 mod netcode;
 mod physics;
 mod players;
 mod prelude;
+mod replay;
 mod states;
 mod ui;
 mod utils;
@@ -38,6 +40,10 @@ use bevy_replicon::ReplicationPlugins;
 
 use crate::prelude::*;
 
+pub use dedicated_server::{DedicatedServerPlugin, TerminalPlugin};
+pub use players::{ShipDesign, ShipDesignError};
+pub use replay::{ReplayPlugin, SessionRecording};
+
 pub struct MainPlugin;
 
 impl Plugin for MainPlugin {
@@ -47,54 +53,6 @@ impl Plugin for MainPlugin {
 		info!("MainPlugin initializing ...");
 		app.add_systems(Startup, || info!("Startup running"));
 
-		// global system set configuration
-		app.configure_sets(
-			FixedUpdate,
-			(
-				(
-					GlobalSystemSet::WorldCreation,
-					GlobalSystemSet::PlayerMovement,
-					GlobalSystemSet::RawPhysics,
-					GlobalSystemSet::ExecuteGameLogic,
-					GlobalSystemSet::BlueprintExpansion,
-				)
-					.chain(),
-				(
-					PhysicsSet::Prepare,
-					PhysicsSet::StepSimulation,
-					PhysicsSet::Sync,
-				)
-					.in_set(GlobalSystemSet::RawPhysics),
-				ServerSet::Send.after(GlobalSystemSet::ExecuteGameLogic),
-			),
-		);
-		// Set up the physics schedule, the schedule that advances the physics simulation
-		app.edit_schedule(GameLogic, |schedule| {
-			schedule
-				// .set_executor_kind(ExecutorKind::SingleThreaded)
-				.set_build_settings(ScheduleBuildSettings {
-					ambiguity_detection: LogLevel::Error,
-					..default()
-				});
-		});
-		// todo: make deterministic
-		// app.edit_schedule(FixedUpdate, |schedule| {
-		// 	schedule
-		// 		// .set_executor_kind(ExecutorKind::SingleThreaded)
-		// 		.set_build_settings(ScheduleBuildSettings {
-		// 			ambiguity_detection: LogLevel::Error,
-		// 			..default()
-		// 		});
-		// });
-		app.add_systems(
-			FixedUpdate,
-			run_game_logic.in_set(GlobalSystemSet::ExecuteGameLogic),
-		);
-		fn run_game_logic(world: &mut World) {
-			// trace!("Running Game Logic");
-			world.try_run_schedule(GameLogic).ok();
-		}
-
 		// spawn initial light
 		app.add_systems(Startup, |mut commands: Commands| {
 			// commands.spawn(DirectionalLightBundle {
@@ -114,10 +72,19 @@ impl Plugin for MainPlugin {
 		// will take cli inputs, or default to start menu
 		// app.add_state::<GlobalGameStates>();
 		let state;
-		if std::env::args().len() > 1 {
+		if app.world.contains_resource::<NetcodeConfig>() {
+			// configured by the embedding binary, e.g. the replay viewer
+			state = GlobalGameStates::InGame;
+		} else if std::env::args().len() > 1 {
 			info!("Using options provided by CLI");
 			state = GlobalGameStates::InGame;
-			app.insert_resource(self::netcode::NetcodeConfig::parse());
+			let config = self::netcode::NetcodeConfig::parse();
+			if let self::netcode::NetcodeConfig::Server { settings, .. } = &config {
+				// fail before opening a window if the settings are invalid
+				let settings = settings.resolve().unwrap_or_else(|err| panic!("{}", err));
+				app.insert_resource(settings);
+			}
+			app.insert_resource(config);
 		} else {
 			state = GlobalGameStates::StartMenu;
 		}
@@ -147,16 +114,7 @@ impl Plugin for MainPlugin {
 			ScreenDiagnosticsPlugin::default(),
 			ScreenFrameDiagnosticsPlugin,
 			picking_plugins,
-			physics::PhysicsPlugin,
 			HanabiPlugin,
-			ReplicationPlugins.build().set(ServerPlugin {
-				tick_policy: TickPolicy::Manual,
-				update_timeout: Duration::from_secs(5),
-			}),
-			TimewarpPlugin::new(TimewarpConfig::new(
-				GlobalSystemSet::ExecuteGameLogic,
-				GlobalSystemSet::ExecuteGameLogic,
-			)),
 			// crate::utils::scenes::HelperScene,
 		));
 
@@ -167,19 +125,102 @@ impl Plugin for MainPlugin {
 		#[cfg(feature = "editor")]
 		app.insert_resource(editor_controls());
 
+		app.add_plugins((SimulationPlugin, self::ui::UiPlugins));
+	}
+}
+
+/// Everything needed to simulate the game and sync it over the network,
+/// without deciding the initial [GlobalGameStates] or adding any menus.
+///
+/// Used by [MainPlugin], and by tests that run a server and clients in one process.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+	fn build(&self, app: &mut App) {
+		// global system set configuration
+		app.configure_sets(
+			FixedUpdate,
+			(
+				(
+					GlobalSystemSet::TickClocks,
+					GlobalSystemSet::WorldCreation,
+					GlobalSystemSet::PlayerMovement,
+					GlobalSystemSet::RawPhysics,
+					GlobalSystemSet::ExecuteGameLogic,
+					GlobalSystemSet::BlueprintExpansion,
+				)
+					.chain(),
+				(
+					PhysicsSet::Prepare,
+					PhysicsSet::StepSimulation,
+					PhysicsSet::Sync,
+				)
+					.in_set(GlobalSystemSet::RawPhysics),
+			),
+		);
+		// Set up the physics schedule, the schedule that advances the physics simulation
+		app.edit_schedule(GameLogic, |schedule| {
+			schedule
+				// .set_executor_kind(ExecutorKind::SingleThreaded)
+				.set_build_settings(ScheduleBuildSettings {
+					ambiguity_detection: LogLevel::Error,
+					..default()
+				});
+		});
+		// every tick has to simulate the same way each time it is run, e.g. when replaying,
+		// so no two systems touching the same data may run in either order
+		app.edit_schedule(FixedUpdate, |schedule| {
+			schedule.set_build_settings(ScheduleBuildSettings {
+				ambiguity_detection: LogLevel::Error,
+				..default()
+			});
+		});
+		app.init_resource::<GameRng>();
+		app.add_systems(
+			FixedUpdate,
+			run_game_logic.in_set(GlobalSystemSet::ExecuteGameLogic),
+		);
+		fn run_game_logic(world: &mut World) {
+			// trace!("Running Game Logic");
+			world.try_run_schedule(GameLogic).ok();
+		}
+
+		// only known this early if the server was configured from the CLI,
+		// [NetcodePlugin] sets the tick rate again when hosting from the start menu,
+		// but the update timeout can't be changed once replicon is built
+		let server_settings = app.world.get_resource::<ServerSettings>().cloned();
+		if let Some(settings) = &server_settings {
+			app.insert_resource(Time::<Fixed>::from_hz(settings.tick_rate));
+		}
+
+		// dep plugins
+		app.add_plugins((
+			physics::PhysicsPlugin,
+			ReplicationPlugins.build().set(ServerPlugin {
+				tick_policy: TickPolicy::Manual,
+				update_timeout: server_settings.unwrap_or_default().update_timeout,
+				// clients are only sent what is near them, see [ServerSettings::relevancy_radius]
+				visibility_policy: VisibilityPolicy::Whitelist,
+			}),
+			TimewarpPlugin::new(TimewarpConfig::new(
+				GlobalSystemSet::ExecuteGameLogic,
+				GlobalSystemSet::ExecuteGameLogic,
+			)),
+		));
+
 		// game logic plugins
 		app.add_plugins((
-			global::blueprints::BlueprintsPlugin,
+			global::blueprints::BlueprintExpansionPlugin,
 			self::netcode::NetcodePlugin,
 			self::cameras::CameraPlugin,
-			self::ui::UiPlugins,
 			self::players::PlayerPlugins,
 			self::blocks::BlockPlugins,
+			replay::RecordingPlugin,
 		));
 		app.register_type::<BlockId>();
 
-		// general network replication
-		replicate_marked!(app, Transform);
+		// [Transform], [LinearVelocity] and [AngularVelocity] are replicated
+		// in [crate::netcode], so that the local player can be predicted
 	}
 }
 
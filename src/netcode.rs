@@ -16,6 +16,18 @@ pub use api::*;
 
 pub struct NetcodePlugin;
 
+mod admin;
+mod authentication;
+mod bans;
+mod chat;
+mod connection;
+mod discovery;
+mod loopback;
+mod prediction;
+mod protocol;
+mod relevancy;
+mod server_settings;
+mod stats;
 mod world_creation;
 
 impl Plugin for NetcodePlugin {
@@ -26,25 +38,86 @@ impl Plugin for NetcodePlugin {
 			.register_type::<NetworkId>()
 			.add_systems(OnEnter(GlobalGameStates::InGame), Self::add_netcode)
 			.add_systems(OnExit(GlobalGameStates::InGame), Self::disconnect_netcode)
-			.add_systems(Update, Self::server_event_system.in_set(Server))
+			.init_resource::<ReplicatedTypes>()
+			.init_resource::<protocol::RejectedClients>()
+			.add_systems(
+				Update,
+				(Self::server_event_system, Self::disconnect_rejected_clients)
+					.in_set(Server)
+					.run_if(resource_exists::<RenetServer>()),
+			)
+			.add_systems(
+				PreUpdate,
+				Self::receive_handshake_rejection
+					.after(bevy_replicon::RenetReceive)
+					.before(ClientSet::Receive)
+					.run_if(resource_exists::<RenetClient>()),
+			)
 			.add_systems(
 				FixedUpdate,
-				Self::frame_inc_and_replicon_tick_sync,
+				(
+					Self::frame_inc,
+					Self::replicon_tick_sync.run_if(NetcodeConfig::has_authority()),
+					Self::reseed_game_rng,
+				)
+					.chain()
+					.in_set(GlobalSystemSet::TickClocks),
 			)
 			.configure_sets(GameLogic, Client.run_if(NetcodeConfig::not_headless()))
 			.configure_sets(Update, Client.run_if(NetcodeConfig::not_headless()))
 			.configure_sets(GameLogic, Server.run_if(NetcodeConfig::has_authority()))
 			.add_event::<PlayerJoin>()
 			.add_event::<PlayerLeave>()
-			.add_plugins(self::world_creation::WorldCreationPlugin);
+			.add_event::<PlayerRespawn>()
+			.register_replicated_type("PlayerDeparted")
+			.add_server_event::<PlayerDeparted>(EventType::Ordered)
+			.register_replicated_type("ServerBroadcast")
+			.add_server_event::<ServerBroadcast>(EventType::Ordered)
+			.register_replicated_type("ServerTickRate")
+			.add_server_event::<ServerTickRate>(EventType::Ordered)
+			.add_systems(
+				Update,
+				(
+					Self::log_player_departures,
+					Self::log_server_broadcasts,
+					Self::apply_server_tick_rate,
+				)
+					.in_set(Client),
+			)
+			.add_plugins((
+				self::world_creation::WorldCreationPlugin,
+				self::prediction::PredictionPlugin,
+				self::discovery::DiscoveryPlugin,
+				self::connection::ConnectionPlugin,
+				self::loopback::LoopbackPlugin,
+				self::relevancy::RelevancyPlugin,
+				self::stats::NetworkStatsPlugin,
+				self::admin::AdminPlugin,
+				self::chat::ChatPlugin,
+			));
 	}
 }
 
 mod api {
 	use crate::prelude::*;
 
+	pub use super::admin::{AdminConsole, AdminSocket};
+	pub use super::authentication::Authentication;
+	pub use super::bans::BanList;
+	pub use super::chat::{ChatHistory, ChatMessage, SendChatMessage, MAX_CHAT_MESSAGE_LEN};
+	pub use super::connection::ConnectionError;
+	pub use super::discovery::{DiscoveredServers, LanDiscovery, ServerBeacon};
+	pub use super::loopback::{
+		LinkConditions, LoopbackClientTransport, LoopbackNetwork, LoopbackServerTransport,
+		LoopbackTraffic, NetcodeTransport,
+	};
+	pub use super::prediction::PREDICTION_HISTORY_FRAMES;
+	pub use super::protocol::{HandshakeRejection, ProtocolVersion, ReplicatedTypes};
+	pub use super::relevancy::{AlwaysRelevant, RelevancySensor};
 	pub use super::resources::NetcodeConfig;
-	pub use super::world_creation::{WorldCreation, WorldCreationSet};
+	pub use super::server_settings::{ServerSettings, ServerSettingsArgs, ServerSettingsError};
+	pub use super::stats::{ConnectionStats, NetworkStats};
+	pub use super::world_creation::{CreateWorldEvent, WorldCreation, WorldCreationSet};
 
 	/// Contains only systems that are relevant to controlling a player.
 	///
@@ -64,7 +137,25 @@ mod api {
 	#[derive(Event, Debug)]
 	pub struct PlayerLeave(pub ClientId);
 
-	#[derive(Component, Reflect, Debug, Serialize, Deserialize)]
+	/// Replaces a player's ship with a fresh one at a spawn point, server only
+	#[derive(Event, Debug)]
+	pub struct PlayerRespawn(pub ClientId);
+
+	/// Broadcast by the server to all clients once a player that left
+	/// has been cleaned up.
+	#[derive(Event, Debug, Clone, Serialize, Deserialize)]
+	pub struct PlayerDeparted(pub NetworkId);
+
+	/// A message from the server's admin to every player
+	#[derive(Event, Debug, Clone, Serialize, Deserialize)]
+	pub struct ServerBroadcast(pub String);
+
+	/// Sent to every client that joins, so that it steps [FixedUpdate] as often as the server,
+	/// see [ServerSettings::tick_rate]
+	#[derive(Event, Debug, Clone, Copy, Serialize, Deserialize)]
+	pub struct ServerTickRate(pub f64);
+
+	#[derive(Component, Reflect, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 	pub struct NetworkId(u64);
 
 	impl GetNetworkId for NetworkId {
@@ -83,6 +174,7 @@ mod api {
 	#[derive(SystemParam)]
 	pub struct ClientID<'w> {
 		res: Option<Res<'w, NetcodeClientTransport>>,
+		loopback: Option<Res<'w, LoopbackClientTransport>>,
 		is_headless: Res<'w, NetcodeConfig>,
 	}
 
@@ -99,6 +191,7 @@ mod api {
 						.res
 						.as_ref()
 						.map(|client| ClientId::from_raw(client.client_id()))
+						.or_else(|| self.loopback.as_ref().map(|client| client.client_id()))
 						.unwrap_or(SERVER_ID),
 				)
 			}
@@ -117,21 +210,38 @@ mod api {
 }
 
 impl NetcodePlugin {
-	// todo add tests
-	fn frame_inc_and_replicon_tick_sync(
-		mut game_clock: ResMut<GameClock>, // your own tick counter
-		mut replicon_tick: ResMut<RepliconTick>,
+	fn frame_inc(mut game_clock: ResMut<GameClock>, // your own tick counter
 	) {
-		// advance your tick and replicon's tick in lockstep
 		game_clock.advance(1);
+	}
+
+	// todo add tests
+	/// Only the server drives [RepliconTick], on clients it is the
+	/// latest tick received from the server.
+	fn replicon_tick_sync(game_clock: Res<GameClock>, mut replicon_tick: ResMut<RepliconTick>) {
+		// advance replicon's tick in lockstep with your own
 		let delta = game_clock.frame().saturating_sub(replicon_tick.get());
 		replicon_tick.increment_by(delta);
 	}
+
+	/// After [Self::replicon_tick_sync], so that clients leading the server
+	/// draw for the frame they are about to simulate
+	fn reseed_game_rng(game_clock: Res<GameClock>, mut rng: ResMut<GameRng>) {
+		rng.reseed(game_clock.frame());
+	}
 }
 
 mod systems {
 	use crate::prelude::*;
 
+	use super::admin::AdminSocket;
+	use super::authentication::{
+		public_addresses, request_connect_token, Authentication, PrivateKey, TokenIssuer,
+		TokenIssuerService, DEFAULT_PRIVATE_KEY_PATH,
+	};
+	use super::bans::BanList;
+	use super::discovery::{BeaconBroadcaster, ServerBeacon};
+	use super::protocol::{handshake_channel_config, RejectedClients, HANDSHAKE_CHANNEL_ID};
 	use super::world_creation::CreateWorldEvent;
 
 	impl NetcodePlugin {
@@ -142,11 +252,117 @@ mod systems {
 			config: Res<NetcodeConfig>,
 			mut creation_event: EventWriter<CreateWorldEvent>,
 			mut server_non_headless_join: EventWriter<PlayerJoin>,
+			server_settings: Option<Res<ServerSettings>>,
+			mut fixed_time: ResMut<Time<Fixed>>,
+			replicated_types: Res<ReplicatedTypes>,
+			mut next_connection_state: ResMut<NextState<ClientConnectionState>>,
+			mut next_game_state: ResMut<NextState<GlobalGameStates>>,
+			admin_console: Res<AdminConsole>,
 		) {
+			let protocol_version = ProtocolVersion::current(&replicated_types);
+			// cleared once the player tries again
+			commands.remove_resource::<ConnectionError>();
+
 			match config.into_inner() {
-				NetcodeConfig::Server { ip, port, headless } => {
-					info!("Setting up as server, hosting on {}:{}", ip, port);
-					let server_channels_config = network_channels.get_server_configs();
+				NetcodeConfig::Server {
+					ip,
+					port,
+					headless,
+					auth,
+					token_port,
+					private_key,
+					token_secret,
+					spawn_points,
+					settings: settings_args,
+					ship: _,
+					transport,
+				} => {
+					info!(
+						"Setting up as server, hosting on {}:{} with {:?} authentication",
+						ip, port, auth
+					);
+					// already resolved if passed on the CLI
+					let settings = match server_settings {
+						Some(settings) => settings.clone(),
+						None => match settings_args.resolve().and_then(|settings| {
+							settings.validate_after_startup()?;
+							Ok(settings)
+						}) {
+							Ok(settings) => {
+								commands.insert_resource(settings.clone());
+								settings
+							}
+							Err(err) => {
+								let error = ConnectionError::CouldNotHost(err.to_string());
+								Self::fail_hosting(error, &mut commands, &mut next_game_state);
+								return;
+							}
+						},
+					};
+					settings.log();
+					info!("Hosting protocol {}", protocol_version);
+					fixed_time.set_timestep_hz(settings.tick_rate);
+
+					match transport {
+						NetcodeTransport::Loopback(network) => {
+							info!("Hosting on an in-memory loopback network");
+							commands.insert_resource(LoopbackServerTransport::new(
+								network.clone(),
+								settings.max_clients,
+							));
+						}
+						NetcodeTransport::Udp => {
+							match Self::server_transport(
+								*ip,
+								*port,
+								*auth,
+								*token_port,
+								private_key.as_deref(),
+								token_secret.as_deref(),
+								&settings,
+							) {
+								Ok((transport, issuer_service)) => {
+									commands.insert_resource(transport);
+									if let Some(issuer_service) = issuer_service {
+										commands.insert_resource(issuer_service);
+									}
+								}
+								Err(error) => {
+									Self::fail_hosting(error, &mut commands, &mut next_game_state);
+									return;
+								}
+							}
+
+							let beacon = ServerBeacon {
+								name: settings.name.to_string(),
+								players: 0,
+								max_players: settings.max_clients,
+								version: protocol_version.clone(),
+								port: *port,
+								auth: *auth,
+								token_port: *token_port,
+							};
+							match BeaconBroadcaster::new(*ip, DISCOVERY_PORT, beacon) {
+								Ok(broadcaster) => commands.insert_resource(broadcaster),
+								Err(err) => warn!("Couldn't announce the server on the LAN: {}", err),
+							}
+						}
+					}
+
+					let seed = settings.seed.unwrap_or_else(random);
+					info!(
+						"World seed is {}, host with `--seed {}` for the same world",
+						seed, seed
+					);
+					// kept with the settings, so that the world can be recreated
+					commands.insert_resource(ServerSettings {
+						seed: Some(seed),
+						..settings.clone()
+					});
+					commands.insert_resource(GameRng::new(seed));
+
+					let mut server_channels_config = network_channels.get_server_configs();
+					server_channels_config.push(handshake_channel_config());
 					let client_channels_config = network_channels.get_client_configs();
 
 					let server = RenetServer::new(ConnectionConfig {
@@ -155,38 +371,45 @@ mod systems {
 						..Default::default()
 					});
 
-					let current_time = SystemTime::now()
-						.duration_since(SystemTime::UNIX_EPOCH)
-						.unwrap();
-					let public_addr = SocketAddr::new(*ip, *port);
-
-					let socket = UdpSocket::bind(public_addr).expect("Couldn't bind to UdpSocket");
-
-					let server_config = ServerConfig {
-						current_time,
-						max_clients: 10,
-						protocol_id: PROTOCOL_ID,
-						public_addresses: vec![public_addr],
-						authentication: ServerAuthentication::Unsecure,
-					};
-					let transport = NetcodeServerTransport::new(server_config, socket).unwrap();
-
 					commands.insert_resource(server);
-					commands.insert_resource(transport);
+					commands.insert_resource(spawn_points.clone());
+					next_connection_state.set(ClientConnectionState::Connected);
+
+					let bans = BanList::load(settings_args.bans_file())
+						.unwrap_or_else(|err| panic!("Couldn't set up server: {}", err));
+					commands.insert_resource(bans);
+					if let Some(admin_port) = settings.admin_port {
+						match AdminSocket::start(&admin_console, admin_port) {
+							Ok(socket) => commands.insert_resource(socket),
+							Err(err) => warn!(
+								"Couldn't accept admin commands on port {}: {}",
+								admin_port, err
+							),
+						}
+					}
 
+					trace!("Sending CreateWorldEvent");
+					creation_event.send(CreateWorldEvent);
 					if !headless {
-						trace!("Sending CreateWorldEvent");
-						creation_event.send(CreateWorldEvent);
 						trace!("Sending PlayerJoin(0)");
 						server_non_headless_join.send(PlayerJoin(SERVER_ID));
 					}
 				}
-				NetcodeConfig::Client { ip, port } => {
+				NetcodeConfig::Client {
+					ip,
+					port,
+					auth,
+					token_port,
+					token_secret,
+					ship: _,
+					transport,
+				} => {
 					info!(
-						"Setting up as client, connecting to {:?} on port {}",
-						ip, port
+						"Setting up as client, connecting to {:?} on port {} with {:?} authentication and protocol {}",
+						ip, port, auth, protocol_version
 					);
-					let server_channels_config = network_channels.get_server_configs();
+					let mut server_channels_config = network_channels.get_server_configs();
+					server_channels_config.push(handshake_channel_config());
 					let client_channels_config = network_channels.get_client_configs();
 
 					let client = RenetClient::new(ConnectionConfig {
@@ -195,64 +418,329 @@ mod systems {
 						..Default::default()
 					});
 
-					let current_time = SystemTime::now()
-						.duration_since(SystemTime::UNIX_EPOCH)
-						.unwrap();
-					let client_id = ClientId::from_raw(current_time.as_millis() as u64);
-					let server_addr = SocketAddr::new(*ip, *port);
-					let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
-						.expect("Couldn't bind to (unspecified) socket");
-					let authentication = ClientAuthentication::Unsecure {
-						client_id: client_id.raw(),
-						protocol_id: PROTOCOL_ID,
-						server_addr,
-						user_data: None,
-					};
-					let transport = NetcodeClientTransport::new(current_time, authentication, socket)
-						.expect("Couldn't join to server");
+					if let NetcodeTransport::Loopback(network) = transport {
+						commands.insert_resource(client);
+						commands.insert_resource(LoopbackClientTransport::connect(
+							network.clone(),
+							protocol_version.to_user_data(),
+						));
+						next_connection_state.set(ClientConnectionState::Connecting);
+						return;
+					}
 
-					commands.insert_resource(client);
-					commands.insert_resource(transport);
+					match Self::client_transport(
+						*ip,
+						*port,
+						*auth,
+						*token_port,
+						token_secret.as_deref(),
+						&protocol_version,
+					) {
+						Ok(transport) => {
+							commands.insert_resource(client);
+							commands.insert_resource(transport);
+							next_connection_state.set(ClientConnectionState::Connecting);
+						}
+						Err(error) => {
+							error!("{}", error);
+							commands.insert_resource(error);
+							next_connection_state.set(ClientConnectionState::Disconnected);
+						}
+					}
 				}
 			}
 		}
 
+		/// Goes back to the start menu to show the `error`,
+		/// [Self::disconnect_netcode] tears down whatever was set up on the way out
+		fn fail_hosting(
+			error: ConnectionError,
+			commands: &mut Commands,
+			next_game_state: &mut NextState<GlobalGameStates>,
+		) {
+			error!("{}", error);
+			commands.insert_resource(error);
+			next_game_state.set(GlobalGameStates::StartMenu);
+		}
+
+		/// Everything that can go wrong before the server can accept its first client
+		fn server_transport(
+			ip: IpAddr,
+			port: u16,
+			auth: Authentication,
+			token_port: u16,
+			private_key: Option<&std::path::Path>,
+			token_secret: Option<&str>,
+			settings: &ServerSettings,
+		) -> Result<(NetcodeServerTransport, Option<TokenIssuerService>), ConnectionError> {
+			let current_time = SystemTime::now()
+				.duration_since(SystemTime::UNIX_EPOCH)
+				.unwrap();
+			let bind_addr = SocketAddr::new(ip, port);
+
+			let socket = UdpSocket::bind(bind_addr).map_err(|err| {
+				ConnectionError::CouldNotHost(format!("couldn't bind to {}: {}", bind_addr, err))
+			})?;
+			let public_addresses = public_addresses(bind_addr);
+
+			let mut issuer_service = None;
+			let authentication = match auth {
+				Authentication::Unsecure => ServerAuthentication::Unsecure,
+				Authentication::Secure => {
+					let token_secret = token_secret.ok_or_else(|| {
+						ConnectionError::CouldNotHost(
+							"`--auth secure` needs a `--token-secret` for clients to request connect tokens with"
+								.into(),
+						)
+					})?;
+					let key_path = private_key.unwrap_or(std::path::Path::new(DEFAULT_PRIVATE_KEY_PATH));
+					let private_key = PrivateKey::load_or_generate(key_path).map_err(|err| {
+						ConnectionError::CouldNotHost(format!(
+							"couldn't load or generate the private key {:?}: {}",
+							key_path, err
+						))
+					})?;
+
+					let issuer = TokenIssuer::new(
+						private_key,
+						PROTOCOL_ID,
+						public_addresses.clone(),
+						token_secret.to_owned(),
+					);
+					let token_addr = SocketAddr::new(ip, token_port);
+					issuer_service = Some(
+						TokenIssuerService::start(issuer, token_addr).map_err(|err| {
+							ConnectionError::CouldNotHost(format!(
+								"couldn't issue connect tokens on {}: {}",
+								token_addr, err
+							))
+						})?,
+					);
+
+					ServerAuthentication::Secure {
+						private_key: private_key.into_bytes(),
+					}
+				}
+			};
+
+			let server_config = ServerConfig {
+				current_time,
+				max_clients: settings.max_clients,
+				protocol_id: PROTOCOL_ID,
+				public_addresses,
+				authentication,
+			};
+			let transport = NetcodeServerTransport::new(server_config, socket)
+				.map_err(|err| ConnectionError::CouldNotHost(err.to_string()))?;
+
+			Ok((transport, issuer_service))
+		}
+
+		/// Everything that can go wrong before the client even sends its first packet
+		fn client_transport(
+			ip: IpAddr,
+			port: u16,
+			auth: Authentication,
+			token_port: u16,
+			token_secret: Option<&str>,
+			protocol_version: &ProtocolVersion,
+		) -> Result<NetcodeClientTransport, ConnectionError> {
+			let current_time = SystemTime::now()
+				.duration_since(SystemTime::UNIX_EPOCH)
+				.unwrap();
+			let server_addr = SocketAddr::new(ip, port);
+			let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(|err| {
+				ConnectionError::CouldNotConnect(format!("couldn't bind a UDP socket: {}", err))
+			})?;
+			let authentication = match auth {
+				Authentication::Unsecure => ClientAuthentication::Unsecure {
+					// random rather than time based, so that two clients starting
+					// at the same time don't collide
+					client_id: random(),
+					protocol_id: PROTOCOL_ID,
+					server_addr,
+					user_data: Some(protocol_version.to_user_data()),
+				},
+				Authentication::Secure => {
+					let token_addr = SocketAddr::new(ip, token_port);
+					let token_secret = token_secret.ok_or_else(|| {
+						ConnectionError::CouldNotConnect(
+							"`--auth secure` needs the server's `--token-secret`".into(),
+						)
+					})?;
+					let connect_token =
+						request_connect_token(token_addr, &protocol_version.to_user_data(), token_secret)
+							.map_err(|err| {
+								ConnectionError::CouldNotConnect(format!(
+									"no connect token from {}: {}",
+									token_addr, err
+								))
+							})?;
+					debug!(
+						"Received connect token for client {}",
+						connect_token.client_id
+					);
+					ClientAuthentication::Secure { connect_token }
+				}
+			};
+			NetcodeClientTransport::new(current_time, authentication, socket)
+				.map_err(|err| ConnectionError::CouldNotConnect(err.to_string()))
+		}
+
+		/// Tears down everything [NetcodePlugin::add_netcode] set up, so that
+		/// another game can be hosted or joined from the start menu.
+		///
+		/// The transports send their disconnect packets straight away,
+		/// since they won't be updated again.
 		pub(super) fn disconnect_netcode(
 			config: Res<NetcodeConfig>,
-			mut client: ResMut<RenetClient>,
-			mut server: ResMut<RenetServer>,
+			client: Option<ResMut<RenetClient>>,
+			client_transport: Option<ResMut<NetcodeClientTransport>>,
+			server: Option<ResMut<RenetServer>>,
+			server_transport: Option<ResMut<NetcodeServerTransport>>,
+			mut rejected: ResMut<RejectedClients>,
+			mut game_clock: ResMut<GameClock>,
+			mut fixed_time: ResMut<Time<Fixed>>,
 			mut commands: Commands,
 		) {
 			match config.into_inner() {
 				NetcodeConfig::Server { .. } => {
 					info!("Disconnecting as server");
-					server.disconnect_all();
+					if let Some(mut server) = server {
+						match server_transport {
+							Some(mut transport) => transport.disconnect_all(&mut server),
+							None => server.disconnect_all(),
+						}
+					}
 					commands.remove_resource::<RenetServer>();
-					commands.remove_resource::<NetcodeClientTransport>();
+					commands.remove_resource::<NetcodeServerTransport>();
+					commands.remove_resource::<LoopbackServerTransport>();
+					commands.remove_resource::<TokenIssuerService>();
+					commands.remove_resource::<BeaconBroadcaster>();
+					commands.remove_resource::<BanList>();
+					commands.remove_resource::<AdminSocket>();
+					// resolved again from the next NetcodeConfig
+					commands.remove_resource::<ServerSettings>();
+					*rejected = RejectedClients::default();
 				}
 				NetcodeConfig::Client { .. } => {
 					info!("Disconnecting client");
-					client.disconnect();
+					if let Some(mut client) = client {
+						match client_transport {
+							Some(mut transport) => transport.disconnect(),
+							None => client.disconnect(),
+						}
+					}
 					commands.remove_resource::<RenetClient>();
 					commands.remove_resource::<NetcodeClientTransport>();
+					commands.remove_resource::<LoopbackClientTransport>();
 				}
 			}
+			// the next server starts counting from 0 again, at its own tick rate
+			*game_clock = GameClock::new();
+			fixed_time.set_timestep_hz(ServerSettings::DEFAULT_TICK_RATE);
+		}
+
+		pub(super) fn log_player_departures(mut departures: EventReader<PlayerDeparted>) {
+			for PlayerDeparted(id) in departures.read() {
+				info!("Player {} left the game", id.get_network_id());
+			}
 		}
 
-		/// Logs server events and spawns a new player whenever a client connects.
+		pub(super) fn log_server_broadcasts(mut broadcasts: EventReader<ServerBroadcast>) {
+			for ServerBroadcast(message) in broadcasts.read() {
+				info!("Server says: {}", message);
+			}
+		}
+
+		/// Otherwise the [GameClock] would drift away from the server's [RepliconTick]
+		pub(super) fn apply_server_tick_rate(
+			mut tick_rates: EventReader<ServerTickRate>,
+			mut fixed_time: ResMut<Time<Fixed>>,
+		) {
+			for ServerTickRate(tick_rate) in tick_rates.read() {
+				if !(tick_rate.is_finite() && *tick_rate > 0.0) {
+					warn!("Ignoring the server's tick rate of {} Hz", tick_rate);
+					continue;
+				}
+				info!("Server runs at {} Hz", tick_rate);
+				fixed_time.set_timestep_hz(*tick_rate);
+			}
+		}
+
+		/// Logs server events and spawns a new player whenever a compatible client connects.
+		///
+		/// Clients with a different [ProtocolVersion] are sent a [HandshakeRejection] instead,
+		/// and banned clients are disconnected straight away.
 		pub(super) fn server_event_system(
 			mut server_event: EventReader<ServerEvent>,
 			mut player_join: EventWriter<PlayerJoin>,
 			mut player_leave: EventWriter<PlayerLeave>,
+			mut tick_rates: EventWriter<ToClients<ServerTickRate>>,
+			settings: Res<ServerSettings>,
+			mut server: ResMut<RenetServer>,
+			transport: Option<Res<NetcodeServerTransport>>,
+			loopback: Option<Res<LoopbackServerTransport>>,
+			replicated_types: Res<ReplicatedTypes>,
+			mut rejected: ResMut<RejectedClients>,
+			bans: Res<BanList>,
+			time: Res<Time<Real>>,
 		) {
+			let server_version = ProtocolVersion::current(&replicated_types);
 			for event in server_event.read() {
 				match event {
 					ServerEvent::ClientConnected { client_id } => {
+						if bans.is_banned(*client_id) {
+							warn!("Disconnecting banned client {client_id}");
+							rejected.reject(*client_id, time.elapsed());
+							server.disconnect(*client_id);
+							continue;
+						}
+
+						let client_version = transport
+							.as_ref()
+							.and_then(|transport| transport.user_data(*client_id))
+							.or_else(|| {
+								loopback
+									.as_ref()
+									.and_then(|loopback| loopback.user_data(*client_id))
+							})
+							.and_then(|user_data| ProtocolVersion::from_user_data(&user_data));
+						if !client_version
+							.as_ref()
+							.is_some_and(|client_version| client_version.is_compatible_with(&server_version))
+						{
+							warn!(
+								"Rejecting client {client_id}, its protocol {:?} doesn't match {}",
+								client_version, server_version
+							);
+							let rejection = HandshakeRejection {
+								server: server_version.clone(),
+								client: client_version,
+							};
+							server.send_message(
+								*client_id,
+								HANDSHAKE_CHANNEL_ID,
+								bincode::serialize(&rejection).expect("Couldn't serialize HandshakeRejection"),
+							);
+							rejected.reject(*client_id, time.elapsed());
+							continue;
+						}
+
 						info!("New player with id {client_id} connected");
 
+						tick_rates.send(ToClients {
+							mode: SendMode::Direct(*client_id),
+							event: ServerTickRate(settings.tick_rate),
+						});
 						player_join.send(PlayerJoin(*client_id));
 					}
 					ServerEvent::ClientDisconnected { client_id, reason } => {
+						if rejected.forget(*client_id) {
+							debug!("Rejected client {client_id} disconnected because: {reason}");
+							continue;
+						}
+
 						info!("Client {client_id} disconnected because: {reason}");
 
 						player_leave.send(PlayerLeave(*client_id));
@@ -260,14 +748,59 @@ mod systems {
 				}
 			}
 		}
+
+		/// Disconnects rejected clients that haven't left on their own
+		pub(super) fn disconnect_rejected_clients(
+			mut server: ResMut<RenetServer>,
+			rejected: Res<RejectedClients>,
+			time: Res<Time<Real>>,
+		) {
+			for client_id in rejected.expired(time.elapsed()) {
+				if server.is_connected(client_id) {
+					server.disconnect(client_id);
+				}
+			}
+		}
+
+		/// Runs before [bevy_replicon] reads anything, so that nothing replicated
+		/// by an incompatible server is deserialized.
+		pub(super) fn receive_handshake_rejection(
+			mut client: ResMut<RenetClient>,
+			network_channels: Res<NetworkChannels>,
+			mut next_state: ResMut<NextState<ClientConnectionState>>,
+			mut commands: Commands,
+		) {
+			let Some(message) = client.receive_message(HANDSHAKE_CHANNEL_ID) else {
+				return;
+			};
+			let error = match bincode::deserialize::<HandshakeRejection>(&message) {
+				Ok(rejection) => ConnectionError::ProtocolMismatch(rejection),
+				Err(err) => {
+					// the server's handshake is at least as incompatible as its protocol
+					warn!(
+						"Couldn't deserialize the server's handshake rejection: {}",
+						err
+					);
+					return;
+				}
+			};
+			error!("{}", error);
+
+			for channel in network_channels.get_server_configs() {
+				while client.receive_message(channel.channel_id).is_some() {}
+			}
+			client.disconnect();
+			commands.insert_resource(error);
+			next_state.set(ClientConnectionState::Disconnected);
+		}
 	}
 }
 
 mod resources {
-	use crate::prelude::*;
+	use crate::{players::SpawnPointsConfig, prelude::*};
 
 	/// Holds information about what ip and port to connect to, or host on.
-	#[derive(Resource, Debug, clap::Parser)]
+	#[derive(Resource, Debug, Clone, clap::Parser)]
 	pub enum NetcodeConfig {
 		Server {
 			#[arg(long, default_value_t = Ipv4Addr::LOCALHOST.into())]
@@ -279,6 +812,37 @@ mod resources {
 			/// Whether or not to run the server in headless mode.
 			#[arg(long, default_value_t = false)]
 			headless: bool,
+
+			/// Whether clients need a connect token issued by this server to join.
+			#[arg(long, value_enum, default_value_t = Authentication::Unsecure)]
+			auth: Authentication,
+
+			/// TCP port that connect tokens are issued on, only used with `--auth secure`.
+			#[arg(long, default_value_t = DEFAULT_TOKEN_PORT)]
+			token_port: u16,
+
+			/// Where the private key is loaded from (or generated to), only used with `--auth secure`.
+			/// Defaults to `server.key` in the working directory.
+			#[arg(long)]
+			private_key: Option<std::path::PathBuf>,
+
+			/// Clients must send this to be issued a connect token, required with `--auth secure`.
+			/// Sent in plain text, so only keeps out those that don't know it.
+			#[arg(long, required_if_eq("auth", "secure"))]
+			token_secret: Option<String>,
+
+			#[command(flatten)]
+			spawn_points: SpawnPointsConfig,
+
+			#[command(flatten)]
+			settings: ServerSettingsArgs,
+
+			/// Ship design to fly, a `.ron` or binary file like `assets/ships/default.ship.ron`
+			#[arg(long)]
+			ship: Option<std::path::PathBuf>,
+
+			#[arg(skip)]
+			transport: NetcodeTransport,
 		},
 		Client {
 			#[arg(short, long, default_value_t = Ipv4Addr::LOCALHOST.into())]
@@ -286,6 +850,25 @@ mod resources {
 
 			#[arg(short, long, default_value_t = DEFAULT_PORT)]
 			port: u16,
+
+			/// Must match the server's `--auth`.
+			#[arg(long, value_enum, default_value_t = Authentication::Unsecure)]
+			auth: Authentication,
+
+			/// TCP port to request a connect token from, only used with `--auth secure`.
+			#[arg(long, default_value_t = DEFAULT_TOKEN_PORT)]
+			token_port: u16,
+
+			/// Must match the server's `--token-secret`, only used with `--auth secure`.
+			#[arg(long)]
+			token_secret: Option<String>,
+
+			/// Ship design to fly, a `.ron` or binary file like `assets/ships/default.ship.ron`
+			#[arg(long)]
+			ship: Option<std::path::PathBuf>,
+
+			#[arg(skip)]
+			transport: NetcodeTransport,
 		},
 	}
 
@@ -295,6 +878,14 @@ mod resources {
 				ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
 				port: DEFAULT_PORT,
 				headless,
+				auth: Authentication::Unsecure,
+				token_port: DEFAULT_TOKEN_PORT,
+				private_key: None,
+				token_secret: None,
+				spawn_points: SpawnPointsConfig::DEFAULT,
+				settings: ServerSettingsArgs::NONE,
+				ship: None,
+				transport: NetcodeTransport::Udp,
 			}
 		}
 
@@ -303,6 +894,14 @@ mod resources {
 				ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
 				port: DEFAULT_PORT,
 				headless,
+				auth: Authentication::Unsecure,
+				token_port: DEFAULT_TOKEN_PORT,
+				private_key: None,
+				token_secret: None,
+				spawn_points: SpawnPointsConfig::DEFAULT,
+				settings: ServerSettingsArgs::NONE,
+				ship: None,
+				transport: NetcodeTransport::Udp,
 			}
 		}
 
@@ -310,6 +909,18 @@ mod resources {
 			NetcodeConfig::Client {
 				ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
 				port: DEFAULT_PORT,
+				auth: Authentication::Unsecure,
+				token_port: DEFAULT_TOKEN_PORT,
+				token_secret: None,
+				ship: None,
+				transport: NetcodeTransport::Udp,
+			}
+		}
+
+		/// Where the local player's [crate::players::ShipDesign] is loaded from, if not the default
+		pub fn ship_design(&self) -> Option<&std::path::Path> {
+			match self {
+				NetcodeConfig::Server { ship, .. } | NetcodeConfig::Client { ship, .. } => ship.as_deref(),
 			}
 		}
 
@@ -321,9 +932,11 @@ mod resources {
 		}
 
 		/// Used in a `.run_if` to signify a system that should only run if
-		/// a client/player is being controlled by the current instance
-		pub fn not_headless() -> impl Fn(Res<NetcodeConfig>) -> bool {
-			|config| !config.into_inner().get_headless()
+		/// a client/player is being controlled by the current instance.
+		///
+		/// Is `false` while there is no [NetcodeConfig], e.g. in the start menu
+		pub fn not_headless() -> impl Fn(Option<Res<NetcodeConfig>>) -> bool {
+			|config| config.is_some_and(|config| !config.get_headless())
 		}
 
 		pub fn is_authoritative(&self) -> bool {
@@ -334,9 +947,11 @@ mod resources {
 		}
 
 		/// Used in a `.run_if` to signify a system that should only run if
-		/// the current instance is the authoritative server
-		pub fn has_authority() -> impl Fn(Res<NetcodeConfig>) -> bool {
-			|config| config.is_authoritative()
+		/// the current instance is the authoritative server.
+		///
+		/// Is `false` while there is no [NetcodeConfig], e.g. in the start menu
+		pub fn has_authority() -> impl Fn(Option<Res<NetcodeConfig>>) -> bool {
+			|config| config.is_some_and(|config| config.is_authoritative())
 		}
 	}
 }
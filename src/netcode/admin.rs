@@ -0,0 +1,558 @@
+//! Console for administering a running server.
+//!
+//! Dedicated servers read commands from stdin, and any server with
+//! [ServerSettings::admin_port] set accepts them on that port on localhost,
+//! e.g. with `nc localhost 5071`. Every command gets a reply, `help` lists them.
+
+use std::io::{BufRead, BufReader, Write};
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	mpsc::{channel, Receiver, Sender},
+	Arc, Mutex,
+};
+use std::thread::JoinHandle;
+
+use crate::players::{ControllablePlayer, SpawnPointBlueprintComponent};
+use crate::prelude::*;
+
+use super::bans::BanList;
+use super::world_creation::CreateWorldEvent;
+
+pub(super) struct AdminPlugin;
+
+impl Plugin for AdminPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<AdminConsole>().add_systems(
+			Update,
+			(
+				Self::run_admin_commands
+					.run_if(resource_exists::<RenetServer>())
+					.run_if(resource_exists::<BanList>()),
+				Self::refuse_admin_commands.run_if(not(resource_exists::<RenetServer>())),
+			),
+		);
+	}
+}
+
+/// Commands typed into the admin console
+#[derive(Debug, clap::Parser)]
+#[command(no_binary_name = true)]
+enum AdminCommand {
+	/// Lists every player by their ID
+	List,
+
+	/// Disconnects a player, who is free to join again
+	Kick { client_id: u64 },
+
+	/// Disconnects a player and refuses them from now on, even after restarting
+	Ban { client_id: u64 },
+
+	/// Lets a banned player join again
+	Unban { client_id: u64 },
+
+	/// Lists every banned ID
+	Bans,
+
+	/// Shows a message to every player
+	Broadcast {
+		#[arg(required = true, trailing_var_arg = true)]
+		message: Vec<String>,
+	},
+
+	/// Gives a player a fresh ship at a spawn point
+	Respawn { client_id: u64 },
+
+	/// Replaces the terrain and spawn points, and respawns every player
+	RecreateWorld,
+}
+
+/// A line typed into the console, and where to send the reply
+struct AdminRequest {
+	line: String,
+	reply: Sender<String>,
+}
+
+/// Submits commands from any thread, cheap to clone
+#[derive(Clone)]
+struct CommandSender(Sender<AdminRequest>);
+
+impl CommandSender {
+	/// Returns where the reply will arrive
+	fn submit(&self, line: impl Into<String>) -> Receiver<String> {
+		let (reply, replies) = channel();
+		let request = AdminRequest {
+			line: line.into(),
+			reply,
+		};
+		// nothing is listening anymore once the app exits, and the reply never comes
+		let _ = self.0.send(request);
+		replies
+	}
+}
+
+/// Queue of commands from stdin and the [AdminSocket], run on the next update.
+#[derive(Resource)]
+pub struct AdminConsole {
+	sender: CommandSender,
+	requests: Mutex<Receiver<AdminRequest>>,
+}
+
+impl Default for AdminConsole {
+	fn default() -> Self {
+		let (sender, requests) = channel();
+		Self {
+			sender: CommandSender(sender),
+			requests: Mutex::new(requests),
+		}
+	}
+}
+
+impl AdminConsole {
+	/// Reads commands from stdin on a background thread, printing the replies to stdout.
+	///
+	/// The thread blocks on stdin until it is closed, so only call this once.
+	pub fn read_stdin(&self) {
+		let sender = self.sender.clone();
+		let spawned = std::thread::Builder::new()
+			.name("admin-stdin".into())
+			.spawn(move || {
+				for line in std::io::stdin().lines() {
+					let Ok(line) = line else {
+						break;
+					};
+					if line.trim().is_empty() {
+						continue;
+					}
+					match sender.submit(line).recv() {
+						Ok(reply) => println!("{}", reply),
+						Err(_) => break,
+					}
+				}
+				debug!("Stopped reading admin commands from stdin");
+			});
+		if let Err(err) = spawned {
+			warn!("Couldn't read admin commands from stdin: {}", err);
+		}
+	}
+
+	fn take_requests(&self) -> Vec<AdminRequest> {
+		let requests = self
+			.requests
+			.lock()
+			.unwrap_or_else(|poisoned| poisoned.into_inner());
+		requests.try_iter().collect()
+	}
+}
+
+/// Accepts admin commands over TCP on localhost, one per line,
+/// answering each with its reply and an empty line.
+///
+/// Stops accepting connections when removed from the world.
+#[derive(Resource, Debug)]
+pub struct AdminSocket {
+	addr: SocketAddr,
+	shutdown: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl AdminSocket {
+	/// How long a connection waits for the server to run its command
+	const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+	pub fn start(console: &AdminConsole, port: u16) -> std::io::Result<Self> {
+		// only reachable from the same machine, since there is no authentication
+		let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+		let addr = listener.local_addr()?;
+		let shutdown = Arc::new(AtomicBool::new(false));
+
+		info!("Accepting admin commands on {}", addr);
+
+		let thread = std::thread::Builder::new()
+			.name("admin-socket".into())
+			.spawn({
+				let shutdown = shutdown.clone();
+				let sender = console.sender.clone();
+				move || {
+					for stream in listener.incoming() {
+						if shutdown.load(Ordering::Relaxed) {
+							break;
+						}
+						let stream = match stream {
+							Ok(stream) => stream,
+							Err(err) => {
+								warn!("Couldn't accept admin connection: {}", err);
+								continue;
+							}
+						};
+						let sender = sender.clone();
+						let spawned = std::thread::Builder::new()
+							.name("admin-connection".into())
+							.spawn(move || Self::serve(stream, sender));
+						if let Err(err) = spawned {
+							warn!("Couldn't handle admin connection: {}", err);
+						}
+					}
+					debug!("Admin socket stopped");
+				}
+			})?;
+
+		Ok(Self {
+			addr,
+			shutdown,
+			thread: Some(thread),
+		})
+	}
+
+	fn serve(stream: std::net::TcpStream, sender: CommandSender) {
+		let peer = stream.peer_addr().ok();
+		debug!("Admin connected from {:?}", peer);
+		let mut writer = match stream.try_clone() {
+			Ok(writer) => writer,
+			Err(err) => {
+				warn!("Couldn't reply to admin connection: {}", err);
+				return;
+			}
+		};
+		for line in BufReader::new(stream).lines() {
+			let Ok(line) = line else {
+				break;
+			};
+			if line.trim().is_empty() {
+				continue;
+			}
+			let reply = sender
+				.submit(line)
+				.recv_timeout(Self::REPLY_TIMEOUT)
+				.unwrap_or_else(|_| "The server didn't run the command in time".into());
+			if writeln!(writer, "{}\n", reply).is_err() {
+				break;
+			}
+		}
+		debug!("Admin disconnected from {:?}", peer);
+	}
+}
+
+impl Drop for AdminSocket {
+	fn drop(&mut self) {
+		self.shutdown.store(true, Ordering::Relaxed);
+		// wakes up the blocking `accept` so that the thread sees the shutdown flag
+		let _ = std::net::TcpStream::connect(self.addr);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+/// Everything the [AdminCommand]s act on
+#[derive(SystemParam)]
+struct Admin<'w, 's> {
+	server: ResMut<'w, RenetServer>,
+	bans: ResMut<'w, BanList>,
+	players:
+		Query<'w, 's, (Entity, &'static NetworkId, &'static Transform), With<ControllablePlayer>>,
+	world: Query<
+		'w,
+		's,
+		Entity,
+		Or<(
+			With<TerrainStructureBlueprint>,
+			With<SpawnPointBlueprintComponent>,
+		)>,
+	>,
+	broadcasts: EventWriter<'w, ToClients<ServerBroadcast>>,
+	respawns: EventWriter<'w, PlayerRespawn>,
+	create_world: EventWriter<'w, CreateWorldEvent>,
+	commands: Commands<'w, 's>,
+}
+
+impl Admin<'_, '_> {
+	fn execute(&mut self, command: AdminCommand) -> String {
+		match command {
+			AdminCommand::List => {
+				let mut players: Vec<_> = self
+					.players
+					.iter()
+					.map(|(_, id, transform)| {
+						let id = id.get_network_id();
+						let host = if id == SERVER_ID { " (host)" } else { "" };
+						let Vec3 { x, y, z } = transform.translation;
+						format!("{}{} at ({:.0}, {:.0}, {:.0})", id, host, x, y, z)
+					})
+					.collect();
+				players.sort();
+				format!("{} players:\n{}", players.len(), players.join("\n"))
+			}
+			AdminCommand::Kick { client_id } => {
+				let client_id = ClientId::from_raw(client_id);
+				match self.disconnect(client_id) {
+					Ok(()) => format!("Kicked {}", client_id),
+					Err(reason) => reason,
+				}
+			}
+			AdminCommand::Ban { client_id } => {
+				let client_id = ClientId::from_raw(client_id);
+				if client_id == SERVER_ID {
+					return "Can't ban the host".into();
+				}
+				let saved = match self.bans.ban(client_id) {
+					Ok(_) => format!("Banned {}", client_id),
+					Err(err) => format!(
+						"Banned {} until the server stops, couldn't save {:?}: {}",
+						client_id,
+						self.bans.path(),
+						err
+					),
+				};
+				match self.disconnect(client_id) {
+					Ok(()) => format!("{}, and disconnected them", saved),
+					Err(_) => saved,
+				}
+			}
+			AdminCommand::Unban { client_id } => {
+				let client_id = ClientId::from_raw(client_id);
+				match self.bans.unban(client_id) {
+					Ok(true) => format!("Unbanned {}", client_id),
+					Ok(false) => format!("{} isn't banned", client_id),
+					Err(err) => format!(
+						"Unbanned {}, but couldn't save {:?}: {}",
+						client_id,
+						self.bans.path(),
+						err
+					),
+				}
+			}
+			AdminCommand::Bans => {
+				let banned: Vec<_> = self.bans.iter().map(|id| id.to_string()).collect();
+				format!("{} bans:\n{}", banned.len(), banned.join("\n"))
+			}
+			AdminCommand::Broadcast { message } => {
+				let message = message.join(" ");
+				info!("Broadcasting {:?}", message);
+				self.broadcasts.send(ToClients {
+					mode: SendMode::Broadcast,
+					event: ServerBroadcast(message),
+				});
+				"Broadcast sent".into()
+			}
+			AdminCommand::Respawn { client_id } => {
+				let client_id = ClientId::from_raw(client_id);
+				if !self
+					.players
+					.iter()
+					.any(|(_, id, _)| id.get_network_id() == client_id)
+				{
+					return format!("{} has no player to respawn", client_id);
+				}
+				self.respawns.send(PlayerRespawn(client_id));
+				format!("Respawning {}", client_id)
+			}
+			AdminCommand::RecreateWorld => {
+				for entity in self.world.iter() {
+					self.commands.entity(entity).despawn_recursive();
+				}
+				self.create_world.send(CreateWorldEvent);
+				let mut respawned = 0;
+				for (_, id, _) in self.players.iter() {
+					self.respawns.send(PlayerRespawn(id.get_network_id()));
+					respawned += 1;
+				}
+				format!("Recreating the world and respawning {} players", respawned)
+			}
+		}
+	}
+
+	fn disconnect(&mut self, client_id: ClientId) -> Result<(), String> {
+		if client_id == SERVER_ID {
+			return Err("Can't disconnect the host".into());
+		}
+		if !self.server.is_connected(client_id) {
+			return Err(format!("{} isn't connected", client_id));
+		}
+		self.server.disconnect(client_id);
+		Ok(())
+	}
+}
+
+impl AdminPlugin {
+	fn run_admin_commands(console: Res<AdminConsole>, mut admin: Admin) {
+		for AdminRequest { line, reply } in console.take_requests() {
+			let result = match AdminCommand::try_parse_from(line.split_whitespace()) {
+				// also covers `help`
+				Err(err) => err.render().to_string(),
+				Ok(command) => {
+					info!("Running admin command {:?}", line);
+					admin.execute(command)
+				}
+			};
+			let _ = reply.send(result);
+		}
+	}
+
+	fn refuse_admin_commands(console: Res<AdminConsole>) {
+		for AdminRequest { reply, .. } in console.take_requests() {
+			let _ = reply.send("Not hosting a game right now".into());
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use crate::players::PlayerBlueprintComponent;
+	use crate::prelude::*;
+
+	use super::AdminConsole;
+
+	fn config_dir() -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("space_craft-admin-{}", random::<u64>()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("server.toml"), "").unwrap();
+		dir
+	}
+
+	/// Reads and writes its `server.toml` in `config_dir`
+	fn settings(config_dir: &std::path::Path) -> ServerSettingsArgs {
+		ServerSettingsArgs {
+			config: Some(config_dir.join("server.toml")),
+			..ServerSettingsArgs::NONE
+		}
+	}
+
+	/// Runs `line` on the server, updating everything until it replies
+	fn run(line: &str, apps: &mut [App]) -> String {
+		let replies = apps[0].world.resource::<AdminConsole>().sender.submit(line);
+		update_all(1, apps);
+		replies
+			.try_recv()
+			.expect("Every command should be replied to")
+	}
+
+	fn connection_state(app: &App) -> ClientConnectionState {
+		*app.world.resource::<State<ClientConnectionState>>().get()
+	}
+
+	#[test]
+	fn kick_and_ban() {
+		let config_dir = config_dir();
+		let network = LoopbackNetwork::new(LinkConditions::PERFECT, 0);
+		let mut apps = loopback_game(&network, settings(&config_dir), 2);
+
+		// loopback clients are numbered in the order they connect
+		let list = run("list", &mut apps);
+		assert!(list.starts_with("3 players"), "{}", list);
+		assert!(list.contains("(host)"), "{}", list);
+
+		assert_eq!(run("kick 1", &mut apps), "Kicked 1");
+		assert!(run("ban 2", &mut apps).starts_with("Banned 2"));
+		assert!(run("kick 0", &mut apps).contains("host"));
+		assert!(run("explode", &mut apps).contains("error"));
+		update_all(10, &mut apps);
+
+		assert_eq!(
+			connection_state(&apps[1]),
+			ClientConnectionState::Disconnected
+		);
+		assert_eq!(
+			connection_state(&apps[2]),
+			ClientConnectionState::Disconnected
+		);
+		let saved = std::fs::read_to_string(config_dir.join("bans.toml")).unwrap();
+		assert!(
+			saved.contains('2'),
+			"Ban should be saved next to the config"
+		);
+
+		// the ban outlasts hosting again
+		apps[0]
+			.world
+			.insert_resource(NextState(Some(GlobalGameStates::StartMenu)));
+		update_all(2, &mut apps[..1]);
+		apps[0]
+			.world
+			.insert_resource(NextState(Some(GlobalGameStates::InGame)));
+		let mut apps = vec![
+			apps.swap_remove(0),
+			// the network hands out 3 and 4 next, ban 4 before it connects
+			test_netcode_app(loopback_client_config(&network)),
+		];
+		update_all(2, &mut apps);
+		assert!(run("ban 4", &mut apps).starts_with("Banned 4"));
+		assert_eq!(run("bans", &mut apps), "2 bans:\n2\n4");
+		apps.push(test_netcode_app(loopback_client_config(&network)));
+		update_all(60, &mut apps);
+
+		assert_eq!(connection_state(&apps[1]), ClientConnectionState::Connected);
+		assert_eq!(
+			connection_state(&apps[2]),
+			ClientConnectionState::Disconnected
+		);
+		assert!(run("list", &mut apps).starts_with("2 players"));
+
+		std::fs::remove_dir_all(&config_dir).unwrap();
+	}
+
+	#[test]
+	fn broadcast_and_respawn() {
+		let config_dir = config_dir();
+		let mut apps = loopback_game(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			settings(&config_dir),
+			1,
+		);
+
+		fn players(world: &mut World) -> Vec<Entity> {
+			world
+				.query_filtered::<Entity, With<PlayerBlueprintComponent>>()
+				.iter(world)
+				.collect()
+		}
+
+		#[derive(Resource, Default)]
+		struct Received(Vec<String>);
+		apps[1].init_resource::<Received>().add_systems(
+			Update,
+			|mut broadcasts: EventReader<ServerBroadcast>, mut received: ResMut<Received>| {
+				received.0.extend(
+					broadcasts
+						.read()
+						.map(|ServerBroadcast(message)| message.clone()),
+				);
+			},
+		);
+
+		assert_eq!(run("broadcast hello  there", &mut apps), "Broadcast sent");
+		update_all(4, &mut apps);
+		assert_eq!(
+			apps[1].world.resource::<Received>().0,
+			vec!["hello there".to_string()]
+		);
+
+		let before = players(&mut apps[0].world);
+		assert_eq!(run("respawn 1", &mut apps), "Respawning 1");
+		assert!(run("respawn 99", &mut apps).contains("no player"));
+		update_all(10, &mut apps);
+		let after = players(&mut apps[0].world);
+		assert_eq!(after.len(), 2, "Still one player each");
+		assert_ne!(before, after, "Respawned player should be a new entity");
+
+		let spawn_points = |world: &mut World| {
+			world
+				.query_filtered::<(), With<crate::players::SpawnPointBlueprintComponent>>()
+				.iter(world)
+				.count()
+		};
+		let spawn_points_before = spawn_points(&mut apps[0].world);
+		assert!(run("recreate-world", &mut apps).contains("respawning 2 players"));
+		update_all(10, &mut apps);
+		assert_eq!(
+			spawn_points(&mut apps[0].world),
+			spawn_points_before,
+			"Old spawn points should be replaced, not added to"
+		);
+		assert_eq!(players(&mut apps[0].world).len(), 2);
+		assert_eq!(players(&mut apps[1].world).len(), 2);
+
+		std::fs::remove_dir_all(&config_dir).unwrap();
+	}
+}
@@ -0,0 +1,451 @@
+//! Secure authentication using `netcode` connect tokens.
+//!
+//! In [Authentication::Secure] mode the server loads (or generates) a [PrivateKey],
+//! and runs a small [TokenIssuerService] next to the game socket.
+//! Clients ask the issuer for a [ConnectToken] over TCP, and the issuer picks
+//! their [ClientId] for them, so a client can no longer impersonate another player.
+//! The request holds the client's `netcode` user data, which is signed into the token,
+//! followed by the server's token secret. Without the secret no token is issued,
+//! since the issuer has nothing else to tell a banned client from a new one.
+//! The secret is sent in plain text, so it only keeps out those that don't know it.
+
+use std::{
+	io::{Read as _, Write as _},
+	path::Path,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	thread::JoinHandle,
+};
+
+use bevy_replicon::renet::transport::{ConnectToken, NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES};
+
+use crate::prelude::*;
+
+/// Whether clients have to present a [ConnectToken] signed by the server's [PrivateKey].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum, Serialize, Deserialize)]
+pub enum Authentication {
+	/// Anybody that can reach the server's UDP port can join, with any [ClientId].
+	#[default]
+	Unsecure,
+
+	/// Clients must request a [ConnectToken] from the server's [TokenIssuerService].
+	Secure,
+}
+
+/// Where the server's private key is stored, relative to the working directory,
+/// if not specified on the CLI.
+pub const DEFAULT_PRIVATE_KEY_PATH: &str = "server.key";
+
+/// How long a freshly issued [ConnectToken] can be used to connect.
+const TOKEN_EXPIRE_SECONDS: u64 = 30;
+
+/// How long a connection made with a [ConnectToken] may go silent before timing out.
+const TOKEN_TIMEOUT_SECONDS: i32 = 15;
+
+/// Longer token secrets are cut off by the [TokenIssuerService], so can never match.
+const MAX_TOKEN_SECRET_BYTES: u64 = 1024;
+
+/// The key used to sign and encrypt [ConnectToken]s.
+/// Only ever lives on the server.
+#[derive(Clone, Copy)]
+pub struct PrivateKey([u8; NETCODE_KEY_BYTES]);
+
+impl std::fmt::Debug for PrivateKey {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str("PrivateKey(..)")
+	}
+}
+
+impl PrivateKey {
+	pub fn generate() -> Self {
+		Self(random())
+	}
+
+	/// Reads the key stored at `path`, or generates a new key and saves it there
+	/// so that restarting the server doesn't invalidate tokens handed out already.
+	pub fn load_or_generate(path: impl AsRef<Path>) -> std::io::Result<Self> {
+		let path = path.as_ref();
+		if path.exists() {
+			let bytes = std::fs::read(path)?;
+			let key = <[u8; NETCODE_KEY_BYTES]>::try_from(bytes.as_slice()).map_err(|_| {
+				std::io::Error::new(
+					std::io::ErrorKind::InvalidData,
+					format!(
+						"Private key at {:?} is {} bytes long, expected {}",
+						path,
+						bytes.len(),
+						NETCODE_KEY_BYTES
+					),
+				)
+			})?;
+			info!("Loaded private key from {:?}", path);
+			Ok(Self(key))
+		} else {
+			let key = Self::generate();
+			std::fs::write(path, key.0)?;
+			info!("Generated a new private key and saved it to {:?}", path);
+			Ok(key)
+		}
+	}
+
+	pub fn into_bytes(self) -> [u8; NETCODE_KEY_BYTES] {
+		self.0
+	}
+}
+
+/// The addresses clients can reach a server bound to `bound` at.
+///
+/// A [ConnectToken] pointing at an unspecified address like `0.0.0.0` can't be connected to,
+/// so it is replaced by this machine's LAN address and loopback.
+/// `netcode` servers only accept tokens naming one of their public addresses,
+/// so these are used for the server's config too.
+pub fn public_addresses(bound: SocketAddr) -> Vec<SocketAddr> {
+	if !bound.ip().is_unspecified() {
+		return vec![bound];
+	}
+	let loopback: IpAddr = match bound {
+		SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+		SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+	};
+
+	let mut addresses = Vec::with_capacity(2);
+	match lan_ip(bound.ip()) {
+		Ok(ip) if !ip.is_loopback() => addresses.push(SocketAddr::new(ip, bound.port())),
+		Ok(_) => {}
+		Err(err) => warn!(
+			"Couldn't find this machine's LAN address, clients can only connect over {}: {}",
+			loopback, err
+		),
+	}
+	addresses.push(SocketAddr::new(loopback, bound.port()));
+	addresses
+}
+
+/// The address packets leave this machine from, found by connecting a UDP socket
+/// to a documentation address, which doesn't send anything.
+fn lan_ip(unspecified: IpAddr) -> std::io::Result<IpAddr> {
+	let target: IpAddr = match unspecified {
+		IpAddr::V4(_) => Ipv4Addr::new(192, 0, 2, 1).into(),
+		IpAddr::V6(_) => Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).into(),
+	};
+	let socket = UdpSocket::bind((unspecified, 0))?;
+	socket.connect((target, DEFAULT_PORT))?;
+	Ok(socket.local_addr()?.ip())
+}
+
+/// Hands out [ConnectToken]s to clients that know the secret,
+/// choosing a unique [ClientId] for every request.
+#[derive(Debug, Clone)]
+pub struct TokenIssuer {
+	private_key: PrivateKey,
+	protocol_id: u64,
+	server_addresses: Vec<SocketAddr>,
+	secret: String,
+}
+
+impl TokenIssuer {
+	/// `server_addresses` shouldn't be unspecified, see [public_addresses]
+	pub fn new(
+		private_key: PrivateKey,
+		protocol_id: u64,
+		server_addresses: Vec<SocketAddr>,
+		secret: String,
+	) -> Self {
+		Self {
+			private_key,
+			protocol_id,
+			server_addresses,
+			secret,
+		}
+	}
+
+	/// Issues a token for a new, random [ClientId], or [None] if `secret` is wrong.
+	///
+	/// `reached_at` is the address the requesting client used to reach the issuer,
+	/// server addresses with that IP are put first since the client can already reach it.
+	pub fn issue(
+		&self,
+		secret: &[u8],
+		reached_at: IpAddr,
+		user_data: Option<&[u8; NETCODE_USER_DATA_BYTES]>,
+	) -> Option<ConnectToken> {
+		if !constant_time_eq(secret, self.secret.as_bytes()) {
+			return None;
+		}
+
+		let current_time = SystemTime::now()
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.unwrap();
+		let client_id = ClientId::from_raw(random());
+
+		let mut server_addresses = self.server_addresses.clone();
+		server_addresses.sort();
+		server_addresses.dedup();
+		// stable, so otherwise still sorted
+		server_addresses.sort_by_key(|addr| addr.ip() != reached_at);
+
+		let token = ConnectToken::generate(
+			current_time,
+			self.protocol_id,
+			TOKEN_EXPIRE_SECONDS,
+			client_id.raw(),
+			TOKEN_TIMEOUT_SECONDS,
+			server_addresses,
+			user_data,
+			&self.private_key.into_bytes(),
+		)
+		.expect("Couldn't generate a connect token");
+		Some(token)
+	}
+}
+
+/// So that how long the comparison takes doesn't give away how much of the secret was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Runs a [TokenIssuer] on a background thread, answering every TCP
+/// connection with a freshly issued [ConnectToken].
+///
+/// Every request is the client's user data and secret, see [request_connect_token].
+/// Requests with the wrong secret are closed without an answer.
+///
+/// Stops the thread when removed from the world.
+#[derive(Resource, Debug)]
+pub struct TokenIssuerService {
+	addr: SocketAddr,
+	shutdown: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl TokenIssuerService {
+	pub fn start(issuer: TokenIssuer, addr: SocketAddr) -> std::io::Result<Self> {
+		let listener = std::net::TcpListener::bind(addr)?;
+		let addr = listener.local_addr()?;
+		let shutdown = Arc::new(AtomicBool::new(false));
+
+		info!("Issuing connect tokens on {}", addr);
+
+		let thread = std::thread::Builder::new()
+			.name("token-issuer".into())
+			.spawn({
+				let shutdown = shutdown.clone();
+				move || {
+					for stream in listener.incoming() {
+						if shutdown.load(Ordering::Relaxed) {
+							break;
+						}
+						let mut stream = match stream {
+							Ok(stream) => stream,
+							Err(err) => {
+								warn!("Couldn't accept token request: {}", err);
+								continue;
+							}
+						};
+						let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+						let mut secret = Vec::new();
+						let reached_at = match stream
+							.set_read_timeout(Some(Duration::from_secs(5)))
+							.and_then(|_| stream.read_exact(&mut user_data))
+							.and_then(|_| {
+								(&mut stream)
+									.take(MAX_TOKEN_SECRET_BYTES)
+									.read_to_end(&mut secret)
+							})
+							.and_then(|_| stream.local_addr())
+						{
+							Ok(addr) => addr.ip(),
+							Err(err) => {
+								warn!("Couldn't read token request: {}", err);
+								continue;
+							}
+						};
+						let Some(token) = issuer.issue(&secret, reached_at, Some(&user_data)) else {
+							warn!(
+								"Refusing a connect token to {:?}, it sent the wrong secret",
+								stream.peer_addr()
+							);
+							continue;
+						};
+						debug!(
+							"Issuing connect token for client {} to {:?}",
+							token.client_id,
+							stream.peer_addr()
+						);
+						if let Err(err) = token.write(&mut stream).and_then(|_| stream.flush()) {
+							warn!("Couldn't send connect token: {}", err);
+						}
+					}
+					debug!("Token issuer stopped");
+				}
+			})?;
+
+		Ok(Self {
+			addr,
+			shutdown,
+			thread: Some(thread),
+		})
+	}
+
+	pub fn local_addr(&self) -> SocketAddr {
+		self.addr
+	}
+}
+
+impl Drop for TokenIssuerService {
+	fn drop(&mut self) {
+		self.shutdown.store(true, Ordering::Relaxed);
+		// wakes up the blocking `accept` so that the thread sees the shutdown flag
+		let wake_addr = if self.addr.ip().is_unspecified() {
+			SocketAddr::new(Ipv4Addr::LOCALHOST.into(), self.addr.port())
+		} else {
+			self.addr
+		};
+		let _ = std::net::TcpStream::connect(wake_addr);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+/// Asks the [TokenIssuerService] at `issuer_addr` for a [ConnectToken]
+/// carrying `user_data`, proving that the client may join with `secret`.
+pub fn request_connect_token(
+	issuer_addr: SocketAddr,
+	user_data: &[u8; NETCODE_USER_DATA_BYTES],
+	secret: &str,
+) -> std::io::Result<ConnectToken> {
+	let mut stream = std::net::TcpStream::connect_timeout(&issuer_addr, Duration::from_secs(5))?;
+	stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+	stream.write_all(user_data)?;
+	stream.write_all(secret.as_bytes())?;
+	stream.flush()?;
+	// the issuer reads the secret until the end of the stream
+	stream.shutdown(std::net::Shutdown::Write)?;
+
+	let mut bytes = Vec::new();
+	stream.read_to_end(&mut bytes)?;
+	if bytes.is_empty() {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::PermissionDenied,
+			"the server refused to issue a connect token, is the token secret right?",
+		));
+	}
+
+	ConnectToken::read(&mut bytes.as_slice())
+		.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn private_key_persists() {
+		let path = std::env::temp_dir().join(format!("space_craft-test-{}.key", random::<u64>()));
+
+		let generated = PrivateKey::load_or_generate(&path).unwrap();
+		let loaded = PrivateKey::load_or_generate(&path).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(generated.into_bytes(), loaded.into_bytes());
+	}
+
+	const SECRET: &str = "hunter2";
+
+	fn issuer(server_addresses: Vec<SocketAddr>) -> TokenIssuer {
+		TokenIssuer::new(
+			PrivateKey::generate(),
+			PROTOCOL_ID,
+			server_addresses,
+			SECRET.into(),
+		)
+	}
+
+	#[test]
+	fn issued_tokens_have_unique_ids() {
+		let issuer = issuer(vec![SocketAddr::new(
+			Ipv4Addr::LOCALHOST.into(),
+			DEFAULT_PORT,
+		)]);
+
+		let first = issuer
+			.issue(SECRET.as_bytes(), Ipv4Addr::LOCALHOST.into(), None)
+			.unwrap();
+		let second = issuer
+			.issue(SECRET.as_bytes(), Ipv4Addr::LOCALHOST.into(), None)
+			.unwrap();
+
+		assert_ne!(first.client_id, second.client_id);
+	}
+
+	#[test]
+	fn tokens_need_the_secret() {
+		let issuer = issuer(vec![SocketAddr::new(
+			Ipv4Addr::LOCALHOST.into(),
+			DEFAULT_PORT,
+		)]);
+
+		assert!(issuer
+			.issue(b"hunter3", Ipv4Addr::LOCALHOST.into(), None)
+			.is_none());
+		assert!(issuer
+			.issue(b"", Ipv4Addr::LOCALHOST.into(), None)
+			.is_none());
+	}
+
+	#[test]
+	fn tokens_never_point_at_unspecified_addresses() {
+		let addresses = public_addresses(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), DEFAULT_PORT));
+		assert!(addresses
+			.iter()
+			.all(|addr| !addr.ip().is_unspecified() && addr.port() == DEFAULT_PORT));
+		assert!(addresses.contains(&SocketAddr::new(Ipv4Addr::LOCALHOST.into(), DEFAULT_PORT)));
+
+		let bound = SocketAddr::new(Ipv4Addr::new(10, 0, 0, 2).into(), DEFAULT_PORT);
+		assert_eq!(public_addresses(bound), vec![bound]);
+	}
+
+	#[test]
+	fn tokens_list_the_reached_address_first() {
+		let lan = SocketAddr::new(Ipv4Addr::new(192, 168, 1, 2).into(), DEFAULT_PORT);
+		let loopback = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), DEFAULT_PORT);
+		let issuer = issuer(vec![loopback, lan, loopback]);
+
+		let addresses = |reached_at: SocketAddr| -> Vec<SocketAddr> {
+			let token = issuer
+				.issue(SECRET.as_bytes(), reached_at.ip(), None)
+				.unwrap();
+			token.server_addresses.into_iter().flatten().collect()
+		};
+
+		assert_eq!(addresses(lan), vec![lan, loopback]);
+		assert_eq!(
+			addresses(loopback),
+			vec![loopback, lan],
+			"Without duplicates"
+		);
+	}
+
+	#[test]
+	fn service_round_trip() {
+		let issuer = issuer(vec![SocketAddr::new(
+			Ipv4Addr::LOCALHOST.into(),
+			DEFAULT_PORT,
+		)]);
+		let service =
+			TokenIssuerService::start(issuer, SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+
+		let user_data = [7; NETCODE_USER_DATA_BYTES];
+		let token = request_connect_token(service.local_addr(), &user_data, SECRET).unwrap();
+		assert_eq!(token.protocol_id, PROTOCOL_ID);
+
+		let refused = request_connect_token(service.local_addr(), &user_data, "hunter3");
+		assert_eq!(
+			refused.unwrap_err().kind(),
+			std::io::ErrorKind::PermissionDenied
+		);
+	}
+}
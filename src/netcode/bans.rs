@@ -0,0 +1,106 @@
+//! Client IDs the server refuses, saved to a TOML file so that bans outlast restarts.
+//!
+//! Without `--auth secure` clients choose their own ID,
+//! so a ban only keeps them out until they pick another one.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::prelude::*;
+
+pub const DEFAULT_BANS_FILE: &str = "bans.toml";
+
+/// Inserted while hosting, banned clients are disconnected as soon as they connect.
+#[derive(Resource, Debug)]
+pub struct BanList {
+	path: PathBuf,
+	banned: BTreeSet<u64>,
+}
+
+/// The contents of a bans file
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BansFile {
+	banned: BTreeSet<u64>,
+}
+
+impl BanList {
+	/// Starts out empty if there is no file at `path` yet
+	pub fn load(path: PathBuf) -> std::io::Result<Self> {
+		let file = match std::fs::read_to_string(&path) {
+			Ok(text) => toml::from_str::<BansFile>(&text).map_err(|err| {
+				std::io::Error::new(
+					std::io::ErrorKind::InvalidData,
+					format!("couldn't parse bans file {:?}: {}", path, err),
+				)
+			})?,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => BansFile::default(),
+			Err(err) => return Err(err),
+		};
+		if !file.banned.is_empty() {
+			info!("Loaded {} bans from {:?}", file.banned.len(), path);
+		}
+		Ok(Self {
+			path,
+			banned: file.banned,
+		})
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn is_banned(&self, client_id: ClientId) -> bool {
+		self.banned.contains(&client_id.raw())
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = ClientId> + '_ {
+		self.banned.iter().map(|id| ClientId::from_raw(*id))
+	}
+
+	/// Saves straight away, returns whether `client_id` wasn't banned already
+	pub fn ban(&mut self, client_id: ClientId) -> std::io::Result<bool> {
+		let newly_banned = self.banned.insert(client_id.raw());
+		self.save()?;
+		Ok(newly_banned)
+	}
+
+	/// Saves straight away, returns whether `client_id` was banned
+	pub fn unban(&mut self, client_id: ClientId) -> std::io::Result<bool> {
+		let was_banned = self.banned.remove(&client_id.raw());
+		self.save()?;
+		Ok(was_banned)
+	}
+
+	fn save(&self) -> std::io::Result<()> {
+		let file = BansFile {
+			banned: self.banned.clone(),
+		};
+		let text = toml::to_string(&file)
+			.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+		std::fs::write(&self.path, text)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn bans_persist() {
+		let path = std::env::temp_dir().join(format!("space_craft-bans-{}.toml", random::<u64>()));
+
+		let mut bans = BanList::load(path.clone()).unwrap();
+		assert_eq!(bans.iter().count(), 0, "No file yet");
+		assert!(bans.ban(ClientId::from_raw(42)).unwrap());
+		assert!(!bans.ban(ClientId::from_raw(42)).unwrap());
+		assert!(bans.ban(ClientId::from_raw(7)).unwrap());
+		assert!(bans.unban(ClientId::from_raw(7)).unwrap());
+
+		let reloaded = BanList::load(path.clone());
+		std::fs::remove_file(&path).unwrap();
+		let reloaded = reloaded.unwrap();
+		assert!(reloaded.is_banned(ClientId::from_raw(42)));
+		assert!(!reloaded.is_banned(ClientId::from_raw(7)));
+	}
+}
@@ -0,0 +1,259 @@
+//! Text chat between players.
+//!
+//! Clients send a [SendChatMessage], which the server cleans up, caps at
+//! [MAX_CHAT_MESSAGE_LEN] characters and rate limits before relaying it to
+//! everyone as a [ChatMessage] tagged with the sender's [NetworkId].
+//! Every instance that plays keeps the latest messages in its [ChatHistory].
+
+use std::collections::VecDeque;
+
+use crate::prelude::*;
+
+pub(super) struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<ChatHistory>()
+			.init_resource::<ChatRateLimiter>()
+			.register_replicated_type("SendChatMessage")
+			.add_client_event::<SendChatMessage>(EventType::Ordered)
+			.register_replicated_type("ChatMessage")
+			.add_server_event::<ChatMessage>(EventType::Ordered)
+			.add_systems(Update, Self::relay_chat_messages.in_set(Server))
+			.add_systems(Update, Self::record_chat_history.in_set(Client))
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::forget_chat);
+	}
+}
+
+/// Longer messages are cut short by the server
+pub const MAX_CHAT_MESSAGE_LEN: usize = 200;
+
+/// Sent by clients, and by the host for its own player
+#[derive(Event, Debug, Clone, Serialize, Deserialize)]
+pub struct SendChatMessage(pub String);
+
+/// Relayed by the server to every client
+#[derive(Event, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+	/// [None] for messages from the server itself
+	pub sender: Option<NetworkId>,
+	pub text: String,
+}
+
+impl std::fmt::Display for ChatMessage {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.sender {
+			Some(sender) => write!(f, "[Player {}] {}", sender.get_network_id(), self.text),
+			None => write!(f, "[Server] {}", self.text),
+		}
+	}
+}
+
+/// The latest [ChatMessage]s and [ServerBroadcast]s received, oldest first
+#[derive(Resource, Debug, Default)]
+pub struct ChatHistory(VecDeque<ChatMessage>);
+
+impl ChatHistory {
+	/// Older messages are forgotten
+	pub const CAPACITY: usize = 50;
+
+	pub fn iter(&self) -> impl DoubleEndedIterator<Item = &ChatMessage> {
+		self.0.iter()
+	}
+
+	fn push(&mut self, message: ChatMessage) {
+		if self.0.len() == Self::CAPACITY {
+			self.0.pop_front();
+		}
+		self.0.push_back(message);
+	}
+}
+
+/// When each client's recent messages were sent, server only
+#[derive(Resource, Debug, Default)]
+struct ChatRateLimiter(HashMap<ClientId, VecDeque<Duration>>);
+
+impl ChatRateLimiter {
+	/// At most this many messages per [Self::WINDOW] from each client
+	const MAX_MESSAGES: usize = 5;
+
+	const WINDOW: Duration = Duration::from_secs(10);
+
+	/// Returns whether `client_id` may send another message `now`, and records it if so
+	fn try_send(&mut self, client_id: ClientId, now: Duration) -> bool {
+		let sent = self.0.entry(client_id).or_default();
+		while sent
+			.front()
+			.is_some_and(|sent_at| now.saturating_sub(*sent_at) >= Self::WINDOW)
+		{
+			sent.pop_front();
+		}
+		if sent.len() >= Self::MAX_MESSAGES {
+			return false;
+		}
+		sent.push_back(now);
+		true
+	}
+}
+
+/// Removes control characters like newlines, trims whitespace
+/// and cuts the message at [MAX_CHAT_MESSAGE_LEN] characters
+fn clean_chat_message(text: &str) -> String {
+	let text: String = text
+		.chars()
+		.filter(|c| !c.is_control())
+		.take(MAX_CHAT_MESSAGE_LEN)
+		.collect();
+	text.trim().to_string()
+}
+
+impl ChatPlugin {
+	fn relay_chat_messages(
+		mut received: EventReader<FromClient<SendChatMessage>>,
+		mut relayed: EventWriter<ToClients<ChatMessage>>,
+		mut limiter: ResMut<ChatRateLimiter>,
+		time: Res<Time<Real>>,
+	) {
+		for FromClient { client_id, event } in received.read() {
+			let text = clean_chat_message(&event.0);
+			if text.is_empty() {
+				continue;
+			}
+			if !limiter.try_send(*client_id, time.elapsed()) {
+				debug!("Dropping chat message from client {client_id}, they are sending too many");
+				relayed.send(ToClients {
+					mode: SendMode::Direct(*client_id),
+					event: ChatMessage {
+						sender: None,
+						text: "You are sending messages too quickly".into(),
+					},
+				});
+				continue;
+			}
+
+			info!("Player {} says: {}", client_id, text);
+			relayed.send(ToClients {
+				mode: SendMode::Broadcast,
+				event: ChatMessage {
+					sender: Some(NetworkId::from_raw(client_id.raw())),
+					text,
+				},
+			});
+		}
+	}
+
+	fn record_chat_history(
+		mut messages: EventReader<ChatMessage>,
+		mut broadcasts: EventReader<ServerBroadcast>,
+		mut history: ResMut<ChatHistory>,
+	) {
+		for message in messages.read() {
+			history.push(message.clone());
+		}
+		for ServerBroadcast(text) in broadcasts.read() {
+			history.push(ChatMessage {
+				sender: None,
+				text: text.clone(),
+			});
+		}
+	}
+
+	fn forget_chat(mut history: ResMut<ChatHistory>, mut limiter: ResMut<ChatRateLimiter>) {
+		*history = ChatHistory::default();
+		*limiter = ChatRateLimiter::default();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::prelude::*;
+
+	use super::{ChatRateLimiter, MAX_CHAT_MESSAGE_LEN};
+
+	#[test]
+	fn rate_limit_recovers() {
+		let mut limiter = ChatRateLimiter::default();
+		let client = ClientId::from_raw(1);
+		let start = Duration::from_secs(100);
+		for _ in 0..ChatRateLimiter::MAX_MESSAGES {
+			assert!(limiter.try_send(client, start));
+		}
+		assert!(!limiter.try_send(client, start));
+		assert!(
+			limiter.try_send(ClientId::from_raw(2), start),
+			"Other clients aren't limited"
+		);
+		assert!(limiter.try_send(client, start + ChatRateLimiter::WINDOW));
+	}
+
+	#[test]
+	fn chat_reaches_everyone() {
+		// the server, then two clients
+		let mut apps = loopback_game(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			ServerSettingsArgs::NONE,
+			2,
+		);
+
+		let long = "a".repeat(MAX_CHAT_MESSAGE_LEN * 2);
+		apps[1]
+			.world
+			.send_event(SendChatMessage("  hello\nthere ".into()));
+		apps[1].world.send_event(SendChatMessage(long));
+		apps[0].world.send_event(SendChatMessage("welcome".into()));
+		update_all(10, &mut apps);
+
+		let sender = Some(NetworkId::from_raw(1));
+		for app in &apps {
+			let history: Vec<_> = app
+				.world
+				.resource::<ChatHistory>()
+				.iter()
+				.cloned()
+				.collect();
+			assert_eq!(history.len(), 3, "{:?}", history);
+			assert!(history.contains(&ChatMessage {
+				sender,
+				text: "hellothere".into()
+			}));
+			assert!(history.contains(&ChatMessage {
+				sender,
+				text: "a".repeat(MAX_CHAT_MESSAGE_LEN)
+			}));
+			assert!(history.contains(&ChatMessage {
+				sender: Some(NetworkId::from_raw(SERVER_ID.raw())),
+				text: "welcome".into()
+			}));
+		}
+
+		// spamming client 1 gets it told off, without bothering anyone else
+		for i in 0..10 {
+			apps[2]
+				.world
+				.send_event(SendChatMessage(format!("spam {i}")));
+		}
+		update_all(10, &mut apps);
+
+		let spam = |app: &App| {
+			app
+				.world
+				.resource::<ChatHistory>()
+				.iter()
+				.filter(|message| message.text.starts_with("spam"))
+				.count()
+		};
+		let told_off = |app: &App| {
+			app
+				.world
+				.resource::<ChatHistory>()
+				.iter()
+				.filter(|message| message.sender.is_none())
+				.count()
+		};
+		assert_eq!(spam(&apps[1]), ChatRateLimiter::MAX_MESSAGES);
+		assert_eq!(spam(&apps[2]), ChatRateLimiter::MAX_MESSAGES);
+		assert_eq!(told_off(&apps[1]), 0);
+		assert!(told_off(&apps[2]) > 0);
+	}
+}
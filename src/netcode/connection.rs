@@ -0,0 +1,294 @@
+//! Tracks a client's connection to the server as a [ClientConnectionState].
+//!
+//! Clients start [ClientConnectionState::Connecting] when entering [GlobalGameStates::InGame].
+//! If the connection can't be set up, is refused, times out or is lost, they end up
+//! [ClientConnectionState::Disconnected] with the reason in a [ConnectionError],
+//! and everything the server replicated is despawned.
+//! It is then up to the player to return to the start menu, which shows the error again.
+//! Servers that can't start hosting go straight back to the start menu with their [ConnectionError].
+//!
+//! Leaving [GlobalGameStates::InGame] for any reason despawns everything replicated,
+//! so that the next game starts from an empty world.
+
+use bevy_replicon::renet::transport::NetcodeTransportError;
+
+use crate::prelude::*;
+
+use super::protocol::HandshakeRejection;
+
+pub(super) struct ConnectionPlugin;
+
+impl Plugin for ConnectionPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_state::<ClientConnectionState>()
+			.add_systems(
+				Update,
+				Self::update_connection_state
+					.run_if(resource_exists::<RenetClient>())
+					.run_if(not(in_state(ClientConnectionState::Disconnected))),
+			)
+			.add_systems(
+				OnEnter(ClientConnectionState::Disconnected),
+				Self::despawn_replicated_world,
+			)
+			.add_systems(
+				OnExit(GlobalGameStates::InGame),
+				(Self::despawn_replicated_world, Self::reset_connection_state),
+			);
+	}
+}
+
+/// Why the last connection attempt or game ended unexpectedly,
+/// shown while [ClientConnectionState::Disconnected] and on the start screen.
+#[derive(Resource, Debug, Clone)]
+pub enum ConnectionError {
+	ProtocolMismatch(HandshakeRejection),
+
+	/// The client couldn't even start connecting, e.g. because no connect token was issued
+	CouldNotConnect(String),
+
+	/// The server couldn't start hosting, e.g. because its port is already in use
+	CouldNotHost(String),
+
+	/// The server refused the connection, stopped responding or kicked the client
+	Disconnected(String),
+}
+
+impl std::fmt::Display for ConnectionError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::ProtocolMismatch(HandshakeRejection {
+				server,
+				client: Some(client),
+			}) => write!(
+				f,
+				"Incompatible server: it runs {}, but this game is {}",
+				server, client
+			),
+			Self::ProtocolMismatch(HandshakeRejection {
+				server,
+				client: None,
+			}) => write!(f, "Incompatible server: it runs {}", server),
+			Self::CouldNotConnect(reason) => write!(f, "Couldn't connect to the server: {}", reason),
+			Self::CouldNotHost(reason) => write!(f, "Couldn't host the game: {}", reason),
+			Self::Disconnected(reason) => write!(f, "Disconnected from the server: {}", reason),
+		}
+	}
+}
+
+impl ConnectionPlugin {
+	/// Follows the [RenetClient] from connecting to connected to disconnected.
+	///
+	/// A [ConnectionError] that was already inserted, e.g. by a [HandshakeRejection],
+	/// is kept since it is more specific than what `renet` reports.
+	fn update_connection_state(
+		client: Res<RenetClient>,
+		mut transport_errors: EventReader<NetcodeTransportError>,
+		existing_error: Option<Res<ConnectionError>>,
+		state: Res<State<ClientConnectionState>>,
+		mut next_state: ResMut<NextState<ClientConnectionState>>,
+		mut commands: Commands,
+	) {
+		let transport_error = transport_errors.read().last().map(ToString::to_string);
+
+		if client.is_disconnected() {
+			let reason = transport_error
+				.or_else(|| client.disconnect_reason().map(|reason| reason.to_string()))
+				.unwrap_or_else(|| "unknown reason".into());
+			if existing_error.is_none() {
+				let error = ConnectionError::Disconnected(reason);
+				warn!("{}", error);
+				commands.insert_resource(error);
+			}
+			next_state.set(ClientConnectionState::Disconnected);
+		} else if client.is_connected() && *state.get() == ClientConnectionState::Connecting {
+			info!("Connected to the server");
+			next_state.set(ClientConnectionState::Connected);
+		}
+	}
+
+	/// Nothing the server replicated can be trusted anymore,
+	/// and keeping it would leave a frozen world behind.
+	///
+	/// Also runs when leaving the game, on servers too.
+	fn despawn_replicated_world(
+		replicated: Query<Entity, With<Replication>>,
+		mut commands: Commands,
+	) {
+		debug!(
+			"Despawning {} replicated entities",
+			replicated.iter().count()
+		);
+		for entity in replicated.iter() {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+
+	fn reset_connection_state(mut next_state: ResMut<NextState<ClientConnectionState>>) {
+		next_state.set(ClientConnectionState::Offline);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::prelude::*;
+
+	/// Nothing is listening on the port, so connecting must time out
+	/// instead of hanging or panicking.
+	#[test]
+	fn unreachable_server_disconnects() {
+		let mut client = test_netcode_app(udp_client_config(103));
+
+		client.update();
+		client.update();
+		assert_eq!(
+			*client
+				.world
+				.resource::<State<ClientConnectionState>>()
+				.get(),
+			ClientConnectionState::Connecting
+		);
+
+		// the netcode timeout is 15 seconds, and every update is one 64th of a second
+		for _ in 0..(64 * 20) {
+			client.update();
+			if *client
+				.world
+				.resource::<State<ClientConnectionState>>()
+				.get() == ClientConnectionState::Disconnected
+			{
+				break;
+			}
+		}
+
+		assert_eq!(
+			*client
+				.world
+				.resource::<State<ClientConnectionState>>()
+				.get(),
+			ClientConnectionState::Disconnected
+		);
+		assert!(
+			client.world.get_resource::<ConnectionError>().is_some(),
+			"Client should know why it disconnected"
+		);
+		assert_eq!(
+			*client.world.resource::<State<GlobalGameStates>>().get(),
+			GlobalGameStates::InGame,
+			"Returning to the menu is up to the player"
+		);
+	}
+
+	/// Leaving a hosted game must tear down everything,
+	/// so that a new game can be hosted on the same port straight away.
+	#[test]
+	fn leave_and_host_again() {
+		let mut server = test_netcode_app(udp_server_config(104));
+		fn count<F: bevy::ecs::query::ReadOnlyWorldQuery>(world: &mut World) -> usize {
+			world.query_filtered::<(), F>().iter(world).count()
+		}
+
+		for _ in 0..10 {
+			server.update();
+		}
+		assert_eq!(
+			count::<With<NetworkId>>(&mut server.world),
+			1,
+			"Host should have a player"
+		);
+
+		server
+			.world
+			.insert_resource(NextState(Some(GlobalGameStates::StartMenu)));
+		server.update();
+		server.update();
+
+		assert!(!server.world.contains_resource::<RenetServer>());
+		assert!(!server.world.contains_resource::<NetcodeServerTransport>());
+		assert!(!server.world.contains_resource::<ServerSettings>());
+		assert_eq!(count::<With<Replication>>(&mut server.world), 0);
+		assert_eq!(
+			count::<With<TerrainStructureBlueprint>>(&mut server.world),
+			0
+		);
+		assert_eq!(
+			*server
+				.world
+				.resource::<State<ClientConnectionState>>()
+				.get(),
+			ClientConnectionState::Offline
+		);
+
+		server
+			.world
+			.insert_resource(NextState(Some(GlobalGameStates::InGame)));
+		for _ in 0..10 {
+			server.update();
+		}
+
+		assert!(server.world.contains_resource::<RenetServer>());
+		assert_eq!(
+			count::<With<NetworkId>>(&mut server.world),
+			1,
+			"Host should have exactly one player again"
+		);
+	}
+
+	/// Hosting on a port that is already taken must go back to the menu instead of panicking
+	#[test]
+	fn port_in_use_returns_to_menu() {
+		let mut server = test_netcode_app(udp_server_config(105));
+		let _taken = UdpSocket::bind((Ipv4Addr::LOCALHOST, DEFAULT_PORT + 105)).unwrap();
+
+		for _ in 0..4 {
+			server.update();
+		}
+
+		assert!(matches!(
+			server.world.get_resource::<ConnectionError>(),
+			Some(ConnectionError::CouldNotHost(_))
+		));
+		assert_eq!(
+			*server.world.resource::<State<GlobalGameStates>>().get(),
+			GlobalGameStates::StartMenu
+		);
+		assert!(!server.world.contains_resource::<RenetServer>());
+	}
+
+	/// A token port that is already taken must go back to the menu too, freeing the game's port again
+	#[test]
+	fn token_port_in_use_returns_to_menu() {
+		let key = std::env::temp_dir().join(format!("space_craft-{}.key", random::<u64>()));
+		let mut config = udp_server_config(106);
+		if let NetcodeConfig::Server {
+			auth,
+			private_key,
+			token_secret,
+			..
+		} = &mut config
+		{
+			*auth = Authentication::Secure;
+			*private_key = Some(key.clone());
+			*token_secret = Some("hunter2".into());
+		}
+		let _taken =
+			std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, DEFAULT_TOKEN_PORT + 106)).unwrap();
+		let mut server = test_netcode_app(config);
+
+		for _ in 0..4 {
+			server.update();
+		}
+		std::fs::remove_file(&key).ok();
+
+		assert!(matches!(
+			server.world.get_resource::<ConnectionError>(),
+			Some(ConnectionError::CouldNotHost(_))
+		));
+		assert_eq!(
+			*server.world.resource::<State<GlobalGameStates>>().get(),
+			GlobalGameStates::StartMenu
+		);
+		assert!(UdpSocket::bind((Ipv4Addr::LOCALHOST, DEFAULT_PORT + 106)).is_ok());
+	}
+}
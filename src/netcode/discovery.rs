@@ -0,0 +1,260 @@
+//! Finding servers on the local network.
+//!
+//! Servers send a [ServerBeacon] over UDP every [BEACON_INTERVAL]
+//! to [DISCOVERY_PORT], broadcast to the whole LAN if hosting publicly.
+//! Clients looking for a game [LanDiscovery::listen] on that port,
+//! and collect every beacon heard recently in [DiscoveredServers].
+
+use crate::prelude::*;
+
+use super::{authentication::Authentication, protocol::ProtocolVersion};
+
+pub(super) struct DiscoveryPlugin;
+
+impl Plugin for DiscoveryPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<DiscoveredServers>()
+			.add_systems(
+				Update,
+				(
+					Self::send_beacons.run_if(resource_exists::<BeaconBroadcaster>()),
+					Self::receive_beacons.run_if(resource_exists::<LanDiscovery>()),
+				),
+			);
+	}
+}
+
+/// How often servers announce themselves
+pub const BEACON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a server is listed after its last [ServerBeacon]
+pub const BEACON_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Prefixed to every [ServerBeacon], so that stray packets are ignored
+const BEACON_MAGIC: &[u8; 8] = b"SPCRAFT\0";
+
+/// What a server tells the LAN about itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerBeacon {
+	pub name: String,
+	pub players: usize,
+	pub max_players: usize,
+	pub version: ProtocolVersion,
+
+	/// The UDP port of the game itself
+	pub port: u16,
+	pub auth: Authentication,
+	pub token_port: u16,
+}
+
+impl ServerBeacon {
+	fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = BEACON_MAGIC.to_vec();
+		bincode::serialize_into(&mut bytes, self).expect("Couldn't serialize ServerBeacon");
+		bytes
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Option<Self> {
+		let beacon = bytes.strip_prefix(BEACON_MAGIC.as_slice())?;
+		bincode::deserialize(beacon).ok()
+	}
+}
+
+/// Sends [ServerBeacon]s, inserted by servers when they start hosting.
+#[derive(Resource, Debug)]
+pub struct BeaconBroadcaster {
+	socket: UdpSocket,
+	targets: Vec<SocketAddr>,
+	beacon: ServerBeacon,
+	last_sent: Option<Instant>,
+}
+
+impl BeaconBroadcaster {
+	/// Servers hosting on [Ipv4Addr::LOCALHOST] only announce themselves to the same machine
+	pub fn new(hosting_on: IpAddr, discovery_port: u16, beacon: ServerBeacon) -> std::io::Result<Self> {
+		let mut targets = vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), discovery_port)];
+		if !hosting_on.is_loopback() {
+			targets.push(SocketAddr::new(Ipv4Addr::BROADCAST.into(), discovery_port));
+		}
+		Self::with_targets(targets, beacon)
+	}
+
+	pub fn with_targets(targets: Vec<SocketAddr>, beacon: ServerBeacon) -> std::io::Result<Self> {
+		let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+		socket.set_broadcast(true)?;
+		socket.set_nonblocking(true)?;
+
+		Ok(Self {
+			socket,
+			targets,
+			beacon,
+			last_sent: None,
+		})
+	}
+
+	pub fn send(&mut self) {
+		let bytes = self.beacon.to_bytes();
+		for target in self.targets.iter() {
+			if let Err(err) = self.socket.send_to(&bytes, target) {
+				trace!("Couldn't send server beacon to {}: {}", target, err);
+			}
+		}
+		self.last_sent = Some(Instant::now());
+	}
+}
+
+/// Listens for [ServerBeacon]s, inserted while the player is choosing a game to join.
+#[derive(Resource, Debug)]
+pub struct LanDiscovery {
+	socket: UdpSocket,
+}
+
+impl LanDiscovery {
+	/// Only one process per machine can listen on a port
+	pub fn listen(port: u16) -> std::io::Result<Self> {
+		let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+		socket.set_nonblocking(true)?;
+		Ok(Self { socket })
+	}
+
+	pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+		self.socket.local_addr()
+	}
+
+	/// Every beacon received since the last call, and the address of the game server
+	pub fn receive(&self) -> Vec<(SocketAddr, ServerBeacon)> {
+		let mut beacons = Vec::new();
+		let mut buf = [0; 1024];
+		loop {
+			match self.socket.recv_from(&mut buf) {
+				Ok((len, from)) => {
+					if let Some(beacon) = ServerBeacon::from_bytes(&buf[..len]) {
+						beacons.push((SocketAddr::new(from.ip(), beacon.port), beacon));
+					}
+				}
+				Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+				Err(err) => {
+					warn!("Couldn't receive server beacon: {}", err);
+					break;
+				}
+			}
+		}
+		beacons
+	}
+}
+
+/// Servers heard from in the last [BEACON_TIMEOUT], by game address
+#[derive(Resource, Debug, Default)]
+pub struct DiscoveredServers(HashMap<SocketAddr, (ServerBeacon, Instant)>);
+
+impl DiscoveredServers {
+	/// Sorted by address, so that the list doesn't jump around
+	pub fn servers(&self) -> Vec<(SocketAddr, &ServerBeacon)> {
+		let mut servers: Vec<_> = self
+			.0
+			.iter()
+			.map(|(addr, (beacon, _))| (*addr, beacon))
+			.collect();
+		servers.sort_by_key(|(addr, _)| *addr);
+		servers
+	}
+
+	pub fn clear(&mut self) {
+		self.0.clear();
+	}
+}
+
+impl DiscoveryPlugin {
+	fn send_beacons(
+		mut broadcaster: ResMut<BeaconBroadcaster>,
+		server: Option<Res<RenetServer>>,
+		config: Res<NetcodeConfig>,
+	) {
+		if broadcaster
+			.last_sent
+			.is_some_and(|last_sent| last_sent.elapsed() < BEACON_INTERVAL)
+		{
+			return;
+		}
+		let host = usize::from(!config.get_headless());
+		broadcaster.beacon.players = host + server.map_or(0, |server| server.connected_clients());
+		broadcaster.send();
+	}
+
+	/// Only marks [DiscoveredServers] as changed if a server appeared, disappeared
+	/// or changed its [ServerBeacon]
+	fn receive_beacons(lan_discovery: Res<LanDiscovery>, mut discovered: ResMut<DiscoveredServers>) {
+		let now = Instant::now();
+		for (addr, beacon) in lan_discovery.receive() {
+			if let Some((known, last_seen)) = discovered.bypass_change_detection().0.get_mut(&addr) {
+				if *known == beacon {
+					*last_seen = now;
+					continue;
+				}
+			}
+			debug!("Discovered server {:?} at {}", beacon.name, addr);
+			discovered.0.insert(addr, (beacon, now));
+		}
+
+		if discovered
+			.0
+			.values()
+			.any(|(_, last_seen)| now.duration_since(*last_seen) > BEACON_TIMEOUT)
+		{
+			discovered
+				.0
+				.retain(|_, (_, last_seen)| now.duration_since(*last_seen) <= BEACON_TIMEOUT);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// The same as a server and a client in two processes on one machine
+	#[test]
+	fn discovers_beacon_on_loopback() {
+		let lan_discovery = LanDiscovery::listen(0).unwrap();
+		let listening_on = lan_discovery.local_addr().unwrap().port();
+
+		let beacon = ServerBeacon {
+			name: "Test Server".into(),
+			players: 1,
+			max_players: 10,
+			version: ProtocolVersion::current(&ReplicatedTypes::default()),
+			port: DEFAULT_PORT,
+			auth: Authentication::Unsecure,
+			token_port: DEFAULT_TOKEN_PORT,
+		};
+		let mut broadcaster = BeaconBroadcaster::with_targets(
+			vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), listening_on)],
+			beacon.clone(),
+		)
+		.unwrap();
+		broadcaster.send();
+
+		let mut received = Vec::new();
+		for _ in 0..100 {
+			received.extend(lan_discovery.receive());
+			if !received.is_empty() {
+				break;
+			}
+			std::thread::sleep(Duration::from_millis(10));
+		}
+
+		assert_eq!(
+			received,
+			vec![(
+				SocketAddr::new(Ipv4Addr::LOCALHOST.into(), DEFAULT_PORT),
+				beacon
+			)]
+		);
+	}
+
+	#[test]
+	fn ignores_stray_packets() {
+		assert_eq!(ServerBeacon::from_bytes(b"hello there"), None);
+	}
+}
@@ -0,0 +1,616 @@
+//! In-memory transport, so that a server [App] and any number of client [App]s
+//! can play together in one process without binding any sockets.
+//!
+//! Create one [LoopbackNetwork] and pass a clone of it to every [App] through
+//! [NetcodeTransport::Loopback]. Packets are handed straight to the [RenetServer]
+//! and [RenetClient]s, after the simulated [LinkConditions].
+//!
+//! Packets are delivered once the receiving [App]'s [Time<Real>] reaches the sender's
+//! plus the latency, so the apps must be updated in lockstep with the same
+//! [bevy::time::TimeUpdateStrategy] for the latency to mean anything.
+//! With a fixed seed, the same updates always drop and delay the same packets.
+//!
+//! Everything sent is counted in [LoopbackTraffic], which makes bandwidth measurable in tests.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bevy_replicon::renet::transport::NETCODE_USER_DATA_BYTES;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::prelude::*;
+
+pub(super) struct LoopbackPlugin;
+
+impl Plugin for LoopbackPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(
+				PreUpdate,
+				(
+					Self::server_receive
+						.run_if(resource_exists::<LoopbackServerTransport>())
+						.run_if(resource_exists::<RenetServer>()),
+					Self::client_receive
+						.run_if(resource_exists::<LoopbackClientTransport>())
+						.run_if(resource_exists::<RenetClient>()),
+				)
+					.in_set(bevy_replicon::RenetReceive),
+			)
+			.add_systems(
+				PostUpdate,
+				(
+					Self::server_send
+						.run_if(resource_exists::<LoopbackServerTransport>())
+						.run_if(resource_exists::<RenetServer>()),
+					Self::client_send
+						.run_if(resource_exists::<LoopbackClientTransport>())
+						.run_if(resource_exists::<RenetClient>()),
+				)
+					.in_set(bevy_replicon::RenetSend),
+			);
+	}
+}
+
+/// Which transport carries `renet`'s packets, only selectable in code.
+#[derive(Debug, Clone, Default)]
+pub enum NetcodeTransport {
+	/// `netcode` over real UDP sockets
+	#[default]
+	Udp,
+
+	Loopback(LoopbackNetwork),
+}
+
+/// How packets are mistreated on their way through a [LoopbackNetwork],
+/// the same in both directions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConditions {
+	/// Added to every packet
+	pub latency: Duration,
+
+	/// Up to this much extra latency is added to every packet, uniformly at random.
+	/// Packets can overtake each other.
+	pub jitter: Duration,
+
+	/// Chance of any packet being dropped, from 0 to 1
+	pub packet_loss: f64,
+}
+
+impl LinkConditions {
+	/// Every packet arrives on the next update
+	pub const PERFECT: Self = Self {
+		latency: Duration::ZERO,
+		jitter: Duration::ZERO,
+		packet_loss: 0.,
+	};
+}
+
+impl Default for LinkConditions {
+	fn default() -> Self {
+		Self::PERFECT
+	}
+}
+
+/// Totals of everything sent through a [LoopbackNetwork] so far, lost packets included
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoopbackTraffic {
+	pub packets_to_server: u64,
+	pub bytes_to_server: u64,
+	pub packets_to_clients: u64,
+	pub bytes_to_clients: u64,
+}
+
+/// The in-memory network shared by a server and its clients, cheap to clone.
+#[derive(Clone)]
+pub struct LoopbackNetwork(Arc<Mutex<Hub>>);
+
+impl std::fmt::Debug for LoopbackNetwork {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let hub = self.hub();
+		f.debug_struct("LoopbackNetwork")
+			.field("conditions", &hub.conditions)
+			.field("clients", &hub.clients.len())
+			.finish()
+	}
+}
+
+impl LoopbackNetwork {
+	/// `seed` decides which packets are dropped and how late they are
+	pub fn new(conditions: LinkConditions, seed: u64) -> Self {
+		Self(Arc::new(Mutex::new(Hub {
+			conditions,
+			rng: StdRng::seed_from_u64(seed),
+			server_online: false,
+			next_client_id: 1,
+			clients: HashMap::new(),
+			to_server: Vec::new(),
+			traffic: LoopbackTraffic::default(),
+		})))
+	}
+
+	pub fn conditions(&self) -> LinkConditions {
+		self.hub().conditions
+	}
+
+	/// Takes effect for packets sent from now on
+	pub fn set_conditions(&self, conditions: LinkConditions) {
+		self.hub().conditions = conditions;
+	}
+
+	pub fn traffic(&self) -> LoopbackTraffic {
+		self.hub().traffic
+	}
+
+	fn hub(&self) -> MutexGuard<'_, Hub> {
+		// a panicking test shouldn't make every other app on the network panic as well
+		self
+			.0
+			.lock()
+			.unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+}
+
+struct Hub {
+	conditions: LinkConditions,
+	rng: StdRng,
+	server_online: bool,
+	next_client_id: u64,
+	clients: HashMap<ClientId, ClientLink>,
+	to_server: Vec<InFlight>,
+	traffic: LoopbackTraffic,
+}
+
+struct ClientLink {
+	state: LinkState,
+	user_data: [u8; NETCODE_USER_DATA_BYTES],
+	to_client: Vec<InFlight>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkState {
+	/// Waiting for the server to accept
+	Connecting,
+	Connected,
+	/// Left on its own, the server hasn't noticed yet
+	Leaving,
+	/// Disconnected by the server, the client hasn't noticed yet
+	Kicked,
+}
+
+struct InFlight {
+	deliver_at: Duration,
+	client_id: ClientId,
+	payload: Vec<u8>,
+}
+
+impl Hub {
+	/// [None] if the packet is lost
+	fn deliver_at(&mut self, sent_at: Duration) -> Option<Duration> {
+		let LinkConditions {
+			latency,
+			jitter,
+			packet_loss,
+		} = self.conditions;
+		if packet_loss > 0. && self.rng.gen_bool(packet_loss.min(1.)) {
+			return None;
+		}
+		let jitter = if jitter.is_zero() {
+			Duration::ZERO
+		} else {
+			jitter.mul_f64(self.rng.gen_range(0. ..=1.))
+		};
+		Some(sent_at + latency + jitter)
+	}
+
+	/// Removes the packets that are due, in the order they arrive,
+	/// so a packet delayed by jitter is overtaken by those sent after it.
+	/// Packets arriving at the same time stay in the order they were sent.
+	fn take_due(queue: &mut Vec<InFlight>, now: Duration) -> Vec<InFlight> {
+		let (mut due, later): (Vec<_>, _) = std::mem::take(queue)
+			.into_iter()
+			.partition(|packet| packet.deliver_at <= now);
+		*queue = later;
+		due.sort_by_key(|packet| packet.deliver_at);
+		due
+	}
+}
+
+/// Inserted instead of a [NetcodeServerTransport] when hosting on a [LoopbackNetwork].
+#[derive(Resource, Debug)]
+pub struct LoopbackServerTransport {
+	network: LoopbackNetwork,
+	max_clients: usize,
+}
+
+impl LoopbackServerTransport {
+	pub fn new(network: LoopbackNetwork, max_clients: usize) -> Self {
+		network.hub().server_online = true;
+		Self {
+			network,
+			max_clients,
+		}
+	}
+
+	/// What the client sent when connecting, like [NetcodeServerTransport::user_data]
+	pub fn user_data(&self, client_id: ClientId) -> Option<[u8; NETCODE_USER_DATA_BYTES]> {
+		self
+			.network
+			.hub()
+			.clients
+			.get(&client_id)
+			.map(|link| link.user_data)
+	}
+}
+
+impl Drop for LoopbackServerTransport {
+	fn drop(&mut self) {
+		let mut hub = self.network.hub();
+		hub.server_online = false;
+		for link in hub.clients.values_mut() {
+			link.state = LinkState::Kicked;
+		}
+		hub.to_server.clear();
+	}
+}
+
+/// Inserted instead of a [NetcodeClientTransport] when joining a [LoopbackNetwork].
+#[derive(Resource, Debug)]
+pub struct LoopbackClientTransport {
+	network: LoopbackNetwork,
+	client_id: ClientId,
+}
+
+impl LoopbackClientTransport {
+	/// Asks the server to accept this client, which it does on its next update
+	pub fn connect(network: LoopbackNetwork, user_data: [u8; NETCODE_USER_DATA_BYTES]) -> Self {
+		let client_id = {
+			let mut hub = network.hub();
+			let client_id = ClientId::from_raw(hub.next_client_id);
+			hub.next_client_id += 1;
+			hub.clients.insert(
+				client_id,
+				ClientLink {
+					state: LinkState::Connecting,
+					user_data,
+					to_client: Vec::new(),
+				},
+			);
+			client_id
+		};
+		Self { network, client_id }
+	}
+
+	pub fn client_id(&self) -> ClientId {
+		self.client_id
+	}
+}
+
+impl Drop for LoopbackClientTransport {
+	fn drop(&mut self) {
+		if let Some(link) = self.network.hub().clients.get_mut(&self.client_id) {
+			link.state = LinkState::Leaving;
+		}
+	}
+}
+
+impl LoopbackPlugin {
+	fn server_receive(
+		transport: Res<LoopbackServerTransport>,
+		mut server: ResMut<RenetServer>,
+		time: Res<Time<Real>>,
+	) {
+		let mut hub = transport.network.hub();
+		let hub = &mut *hub;
+
+		// the server may have disconnected clients since it last sent
+		for client_id in server.disconnections_id() {
+			server.remove_connection(client_id);
+			if let Some(link) = hub.clients.get_mut(&client_id) {
+				link.state = LinkState::Kicked;
+			}
+		}
+
+		let mut connected = server.connected_clients();
+		hub.clients.retain(|client_id, link| match link.state {
+			LinkState::Connecting if connected < transport.max_clients => {
+				server.add_connection(*client_id);
+				link.state = LinkState::Connected;
+				connected += 1;
+				true
+			}
+			LinkState::Connecting => {
+				debug!("Refusing loopback client {client_id}, the server is full");
+				link.state = LinkState::Kicked;
+				true
+			}
+			LinkState::Leaving => {
+				if server.is_connected(*client_id) {
+					server.remove_connection(*client_id);
+				}
+				false
+			}
+			LinkState::Connected | LinkState::Kicked => true,
+		});
+
+		for packet in Hub::take_due(&mut hub.to_server, time.elapsed()) {
+			if let Err(err) = server.process_packet_from(&packet.payload, packet.client_id) {
+				trace!(
+					"Dropping loopback packet from {}: {}",
+					packet.client_id,
+					err
+				);
+			}
+		}
+	}
+
+	fn server_send(
+		transport: Res<LoopbackServerTransport>,
+		mut server: ResMut<RenetServer>,
+		time: Res<Time<Real>>,
+	) {
+		let mut hub = transport.network.hub();
+		for client_id in server.clients_id() {
+			let Ok(packets) = server.get_packets_to_send(client_id) else {
+				continue;
+			};
+			for payload in packets {
+				hub.traffic.packets_to_clients += 1;
+				hub.traffic.bytes_to_clients += payload.len() as u64;
+				let Some(deliver_at) = hub.deliver_at(time.elapsed()) else {
+					continue;
+				};
+				if let Some(link) = hub.clients.get_mut(&client_id) {
+					link.to_client.push(InFlight {
+						deliver_at,
+						client_id,
+						payload,
+					});
+				}
+			}
+		}
+	}
+
+	fn client_receive(
+		transport: Res<LoopbackClientTransport>,
+		mut client: ResMut<RenetClient>,
+		time: Res<Time<Real>>,
+	) {
+		if client.is_disconnected() {
+			return;
+		}
+		let mut hub = transport.network.hub();
+		let server_online = hub.server_online;
+		let Some(link) = hub.clients.get_mut(&transport.client_id) else {
+			client.disconnect_due_to_transport();
+			return;
+		};
+
+		match link.state {
+			LinkState::Kicked | LinkState::Leaving => {
+				client.disconnect_due_to_transport();
+				return;
+			}
+			LinkState::Connecting => return,
+			LinkState::Connected if !server_online => {
+				client.disconnect_due_to_transport();
+				return;
+			}
+			LinkState::Connected => {
+				if client.is_connecting() {
+					client.set_connected();
+				}
+			}
+		}
+
+		for packet in Hub::take_due(&mut link.to_client, time.elapsed()) {
+			client.process_packet(&packet.payload);
+		}
+	}
+
+	fn client_send(
+		transport: Res<LoopbackClientTransport>,
+		mut client: ResMut<RenetClient>,
+		time: Res<Time<Real>>,
+	) {
+		let mut hub = transport.network.hub();
+		let Some(link) = hub.clients.get_mut(&transport.client_id) else {
+			return;
+		};
+		if client.is_disconnected() {
+			if link.state == LinkState::Connected {
+				link.state = LinkState::Leaving;
+			}
+			return;
+		}
+		if link.state != LinkState::Connected {
+			return;
+		}
+
+		for payload in client.get_packets_to_send() {
+			hub.traffic.packets_to_server += 1;
+			hub.traffic.bytes_to_server += payload.len() as u64;
+			let Some(deliver_at) = hub.deliver_at(time.elapsed()) else {
+				continue;
+			};
+			hub.to_server.push(InFlight {
+				deliver_at,
+				client_id: transport.client_id,
+				payload,
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::players::{PlayerBlueprintComponent, SpawnPointBlueprintComponent};
+	use crate::prelude::*;
+
+	use super::*;
+
+	fn count<F: bevy::ecs::query::ReadOnlyWorldQuery>(world: &mut World) -> usize {
+		world.query_filtered::<(), F>().iter(world).count()
+	}
+
+	fn player_translations(world: &mut World) -> Vec<(ClientId, Vec3)> {
+		let mut players =
+			world.query_filtered::<(&NetworkId, &Transform), With<PlayerBlueprintComponent>>();
+		let mut translations: Vec<_> = players
+			.iter(world)
+			.map(|(id, transform)| (id.get_network_id(), transform.translation))
+			.collect();
+		translations.sort_by_key(|(id, _)| id.raw());
+		translations
+	}
+
+	/// Runs a server and two clients over `conditions`, returning every app
+	/// once all players have been replicated everywhere.
+	fn play(conditions: LinkConditions, seed: u64) -> Vec<App> {
+		let mut apps = loopback_game(
+			&LoopbackNetwork::new(conditions, seed),
+			ServerSettingsArgs::NONE,
+			2,
+		);
+		// each client has its own player, give them time to see the other one too
+		update_all(120, &mut apps);
+		apps
+	}
+
+	fn assert_replicated(apps: &mut [App]) {
+		let (server, clients) = apps.split_first_mut().unwrap();
+		let authoritative_players = player_translations(&mut server.world);
+		assert_eq!(
+			authoritative_players.len(),
+			3,
+			"Host and both clients should have players"
+		);
+		let spawn_points = count::<With<SpawnPointBlueprintComponent>>(&mut server.world);
+
+		for client in clients {
+			assert_eq!(
+				*client
+					.world
+					.resource::<State<ClientConnectionState>>()
+					.get(),
+				ClientConnectionState::Connected
+			);
+			let players = player_translations(&mut client.world);
+			assert_eq!(
+				players.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+				authoritative_players
+					.iter()
+					.map(|(id, _)| *id)
+					.collect::<Vec<_>>(),
+				"Every player should be replicated"
+			);
+			assert_eq!(
+				count::<With<SpawnPointBlueprintComponent>>(&mut client.world),
+				spawn_points,
+				"Every spawn point should be replicated"
+			);
+		}
+	}
+
+	#[test]
+	fn jitter_reorders_packets() {
+		let packet = |deliver_at: u64, payload: u8| super::InFlight {
+			deliver_at: Duration::from_millis(deliver_at),
+			client_id: ClientId::from_raw(1),
+			payload: vec![payload],
+		};
+		let mut queue = vec![
+			packet(30, 0),
+			packet(10, 1),
+			packet(20, 2),
+			packet(10, 3),
+			packet(50, 4),
+		];
+
+		let due = super::Hub::take_due(&mut queue, Duration::from_millis(30));
+
+		assert_eq!(
+			due
+				.iter()
+				.map(|packet| packet.payload[0])
+				.collect::<Vec<_>>(),
+			vec![1, 3, 2, 0]
+		);
+		assert_eq!(queue.len(), 1, "Not due yet");
+	}
+
+	#[test]
+	fn replicates_over_perfect_link() {
+		let mut apps = play(LinkConditions::PERFECT, 0);
+
+		assert_replicated(&mut apps);
+
+		// nobody is touching the controls, so everyone agrees on where the players are
+		let (server, clients) = apps.split_first_mut().unwrap();
+		let authoritative = player_translations(&mut server.world);
+		for client in clients {
+			for ((_, replicated), (_, authoritative)) in player_translations(&mut client.world)
+				.iter()
+				.zip(authoritative.iter())
+			{
+				assert!(replicated.distance(*authoritative) < 0.01);
+			}
+		}
+	}
+
+	#[test]
+	fn replicates_over_bad_link() {
+		let mut apps = play(
+			LinkConditions {
+				latency: Duration::from_millis(100),
+				jitter: Duration::from_millis(50),
+				packet_loss: 0.1,
+			},
+			1,
+		);
+
+		assert_replicated(&mut apps);
+	}
+
+	#[test]
+	fn same_seed_drops_same_packets() {
+		let conditions = LinkConditions {
+			packet_loss: 0.5,
+			jitter: Duration::from_millis(30),
+			..LinkConditions::PERFECT
+		};
+		let sample = |seed| {
+			let mut hub = LoopbackNetwork::new(conditions, seed);
+			let hub = Arc::get_mut(&mut hub.0).unwrap().get_mut().unwrap();
+			(0..100)
+				.map(|_| hub.deliver_at(Duration::ZERO))
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(sample(7), sample(7));
+		assert_ne!(sample(7), sample(8));
+	}
+
+	#[test]
+	fn clients_notice_server_leaving() {
+		let mut apps = play(LinkConditions::PERFECT, 0);
+
+		apps[0]
+			.world
+			.insert_resource(NextState(Some(GlobalGameStates::StartMenu)));
+		update_all(4, &mut apps);
+
+		for client in &apps[1..] {
+			assert_eq!(
+				*client
+					.world
+					.resource::<State<ClientConnectionState>>()
+					.get(),
+				ClientConnectionState::Disconnected
+			);
+			assert_eq!(
+				count::<With<PlayerBlueprintComponent>>(&mut client.world),
+				0
+			);
+		}
+	}
+}
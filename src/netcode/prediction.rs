@@ -0,0 +1,228 @@
+//! Client-side prediction of the local player, using [bevy_timewarp].
+//!
+//! The local player's physics is simulated straight away from local input,
+//! and the authoritative state the server replicates is written into
+//! [ServerSnapshot]s instead of the components themselves.
+//! When a snapshot disagrees with what was predicted for that tick,
+//! [bevy_timewarp] rolls back and re-simulates up to the current frame,
+//! with the inputs recorded for each of those frames in the player's `InputBuffer`.
+
+use std::io::Cursor;
+
+use bevy_replicon::{
+	client::client_mapper::ServerEntityMap,
+	replicon_core::replication_rules::{remove_component, serialize_component},
+};
+
+use crate::{players::ThrusterStrengths, prelude::*};
+
+pub(super) struct PredictionPlugin;
+
+impl Plugin for PredictionPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.register_type::<Predicted>()
+			.register_rollback::<Transform>()
+			.register_rollback::<LinearVelocity>()
+			.register_rollback::<AngularVelocity>()
+			.register_replicated_type("Transform")
+			.register_replicated_type("LinearVelocity")
+			.register_replicated_type("AngularVelocity")
+			.register_replicated_type("ThrusterStrengths")
+			.replicate_with::<Transform>(
+				serialize_component::<Transform>,
+				deserialize_predicted::<Transform>,
+				remove_component::<Transform>,
+			)
+			.replicate_with::<LinearVelocity>(
+				serialize_component::<LinearVelocity>,
+				deserialize_predicted::<LinearVelocity>,
+				remove_component::<LinearVelocity>,
+			)
+			.replicate_with::<AngularVelocity>(
+				serialize_component::<AngularVelocity>,
+				deserialize_predicted::<AngularVelocity>,
+				remove_component::<AngularVelocity>,
+			)
+			.replicate_with::<ThrusterStrengths>(
+				serialize_component::<ThrusterStrengths>,
+				deserialize_predicted::<ThrusterStrengths>,
+				remove_component::<ThrusterStrengths>,
+			)
+			.add_systems(
+				Update,
+				Self::mark_local_player_predicted
+					.in_set(Client)
+					.run_if(not(NetcodeConfig::has_authority())),
+			)
+			.add_systems(
+				FixedUpdate,
+				Self::lead_server_clock
+					.in_set(GlobalSystemSet::TickClocks)
+					.after(NetcodePlugin::frame_inc)
+					.before(NetcodePlugin::replicon_tick_sync)
+					.run_if(not(NetcodeConfig::has_authority())),
+			);
+	}
+}
+
+/// How many frames the client simulates ahead of the last tick received from the server,
+/// so that its inputs arrive before the server simulates that tick.
+pub const PREDICTION_LEAD_FRAMES: u32 = 4;
+
+/// How many frames of history are kept for rolling back.
+pub const PREDICTION_HISTORY_FRAMES: usize = 64;
+
+/// Marks the entity as predicted on this client.
+/// Replicated components are written into [ServerSnapshot]s instead of
+/// replacing the predicted values.
+#[derive(Component, Reflect, Debug, Default)]
+#[component(storage = "SparseSet")]
+pub struct Predicted;
+
+/// Bundle of [ServerSnapshot]s for every replicated component that is predicted
+#[derive(Bundle)]
+struct PredictedBundle {
+	marker: Predicted,
+	transform: ServerSnapshot<Transform>,
+	linvel: ServerSnapshot<LinearVelocity>,
+	angvel: ServerSnapshot<AngularVelocity>,
+	thruster_strengths: ServerSnapshot<ThrusterStrengths>,
+}
+
+impl Default for PredictedBundle {
+	fn default() -> Self {
+		Self {
+			marker: Predicted,
+			transform: ServerSnapshot::with_capacity(PREDICTION_HISTORY_FRAMES),
+			linvel: ServerSnapshot::with_capacity(PREDICTION_HISTORY_FRAMES),
+			angvel: ServerSnapshot::with_capacity(PREDICTION_HISTORY_FRAMES),
+			thruster_strengths: ServerSnapshot::with_capacity(PREDICTION_HISTORY_FRAMES),
+		}
+	}
+}
+
+/// Deserializes like [bevy_replicon] usually would, except that on [Predicted]
+/// entities the value is stored as the server's snapshot for `tick`.
+fn deserialize_predicted<C>(
+	entity: &mut EntityWorldMut,
+	_entity_map: &mut ServerEntityMap,
+	cursor: &mut Cursor<&[u8]>,
+	tick: RepliconTick,
+) -> bincode::Result<()>
+where
+	C: Component + Clone + PartialEq + std::fmt::Debug + DeserializeOwned,
+{
+	let component: C = bincode::deserialize_from(cursor)?;
+
+	if entity.contains::<Predicted>() {
+		if let Some(mut snapshot) = entity.get_mut::<ServerSnapshot<C>>() {
+			snapshot.insert(tick.get(), component);
+			return Ok(());
+		}
+	}
+	entity.insert(component);
+
+	Ok(())
+}
+
+impl PredictionPlugin {
+	/// The local player is the only thing that is predicted,
+	/// every other entity follows the server.
+	fn mark_local_player_predicted(
+		players: Query<(Entity, &NetworkId), Without<Predicted>>,
+		local_id: ClientID,
+		mut commands: Commands,
+	) {
+		for (player, id) in players.iter() {
+			if local_id.get() == Some(id.get_network_id()) {
+				debug!("Predicting local player {:?}", id);
+				commands.entity(player).insert(PredictedBundle::default());
+			}
+		}
+	}
+
+	/// Keeps the client's [GameClock] [PREDICTION_LEAD_FRAMES] ahead of the
+	/// latest tick received from the server.
+	fn lead_server_clock(mut game_clock: ResMut<GameClock>, server_tick: Res<RepliconTick>) {
+		let target = server_tick.get() + PREDICTION_LEAD_FRAMES;
+		let frame = game_clock.frame();
+		if frame < target {
+			trace!(
+				"Client clock is behind the server, skipping {} frames",
+				target - frame
+			);
+			game_clock.advance(target - frame);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::prelude::*;
+
+	/// Runs a server and a client in one process over a [LoopbackNetwork].
+	/// The client flies forwards for a while, then lets go of the controls,
+	/// after which its predicted position and velocities must match the server's.
+	#[test]
+	fn prediction_converges() {
+		let mut apps = loopback_game(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			ServerSettingsArgs::NONE,
+			1,
+		);
+		let client_id = loopback_client_id(&apps[1]);
+		let mut predicted = apps[1]
+			.world
+			.query::<(&NetworkId, &Transform, Has<super::Predicted>)>();
+		assert!(
+			predicted
+				.iter(&apps[1].world)
+				.any(|(id, _, is_predicted)| id.get_network_id() == client_id && is_predicted),
+			"Local player should be predicted"
+		);
+
+		apps[1]
+			.world
+			.resource_mut::<Input<KeyCode>>()
+			.press(KeyCode::W);
+		update_all(60, &mut apps);
+		apps[1]
+			.world
+			.resource_mut::<Input<KeyCode>>()
+			.release(KeyCode::W);
+		update_all(240, &mut apps);
+
+		let find_player = |world: &mut World| -> (Transform, LinearVelocity, AngularVelocity) {
+			let mut players =
+				world.query::<(&NetworkId, &Transform, &LinearVelocity, &AngularVelocity)>();
+			let (_, transform, linvel, angvel) = players
+				.iter(world)
+				.find(|(id, ..)| id.get_network_id() == client_id)
+				.expect("Player should exist");
+			(*transform, *linvel, *angvel)
+		};
+		let (predicted, predicted_linvel, predicted_angvel) = find_player(&mut apps[1].world);
+		let (authoritative, authoritative_linvel, authoritative_angvel) =
+			find_player(&mut apps[0].world);
+
+		assert!(
+			predicted.translation.distance(authoritative.translation) < 0.1,
+			"Predicted {:?} diverged from authoritative {:?}",
+			predicted.translation,
+			authoritative.translation
+		);
+		assert!(
+			predicted_linvel.distance(authoritative_linvel.0) < 0.01,
+			"Predicted linear velocity {:?} diverged from authoritative {:?}",
+			predicted_linvel,
+			authoritative_linvel
+		);
+		assert!(
+			predicted_angvel.distance(authoritative_angvel.0) < 0.01,
+			"Predicted angular velocity {:?} diverged from authoritative {:?}",
+			predicted_angvel,
+			authoritative_angvel
+		);
+	}
+}
@@ -0,0 +1,238 @@
+//! Checks that a client and server were built with the same network protocol.
+//!
+//! Every build has a [ProtocolVersion]: the crate version, plus a hash of the names of every
+//! replicated component and networked event, registered with
+//! [AppExt::register_replicated_type] (which [replicate_marked!] and
+//! [AppExt::register_networked_blueprint] do for you).
+//! The names are spelled out in the source, so the same source always hashes the same,
+//! whichever compiler built it.
+//!
+//! Clients send their [ProtocolVersion] in the `netcode` user data when connecting.
+//! If it doesn't match the server's, the server sends a [HandshakeRejection] on the
+//! [HANDSHAKE_CHANNEL_ID], which isn't managed by [bevy_replicon] so that it works
+//! no matter how the other build's channels are laid out, and then disconnects them.
+//! The client shows it as a [ConnectionError::ProtocolMismatch].
+
+use std::collections::BTreeSet;
+
+use bevy_replicon::renet::{transport::NETCODE_USER_DATA_BYTES, ChannelConfig, SendType};
+
+use crate::prelude::*;
+
+/// The names of everything that is replicated, see [AppExt::register_replicated_type].
+#[derive(Resource, Debug, Default)]
+pub struct ReplicatedTypes(BTreeSet<&'static str>);
+
+impl ReplicatedTypes {
+	pub fn insert(&mut self, name: &'static str) {
+		self.0.insert(name);
+	}
+}
+
+/// Which network protocol a build speaks, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+	pub crate_version: String,
+	pub replication_hash: u64,
+}
+
+impl std::fmt::Display for ProtocolVersion {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"v{} ({:016x})",
+			self.crate_version, self.replication_hash
+		)
+	}
+}
+
+impl ProtocolVersion {
+	pub fn current(types: &ReplicatedTypes) -> Self {
+		let mut hash = Fnv1a::default();
+		for name in types.0.iter() {
+			hash.write(name.as_bytes());
+			// separator, so that ["ab", "c"] and ["a", "bc"] hash differently
+			hash.write(&[0]);
+		}
+
+		Self {
+			crate_version: env!("CARGO_PKG_VERSION").to_owned(),
+			replication_hash: hash.finish(),
+		}
+	}
+
+	/// Derived from both the crate version and the replicated types
+	pub fn protocol_id(&self) -> u64 {
+		let mut hash = Fnv1a::default();
+		hash.write(self.crate_version.as_bytes());
+		hash.write(&self.replication_hash.to_le_bytes());
+		hash.finish()
+	}
+
+	pub fn to_user_data(&self) -> [u8; NETCODE_USER_DATA_BYTES] {
+		let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+		bincode::serialize_into(&mut user_data[..], self)
+			.expect("ProtocolVersion should fit in the netcode user data");
+		user_data
+	}
+
+	/// [None] if the user data wasn't written by [ProtocolVersion::to_user_data],
+	/// e.g. because the client is too old to send it.
+	pub fn from_user_data(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Option<Self> {
+		bincode::deserialize::<Self>(user_data)
+			.ok()
+			.filter(|version| !version.crate_version.is_empty())
+	}
+
+	pub fn is_compatible_with(&self, other: &Self) -> bool {
+		self.protocol_id() == other.protocol_id()
+	}
+}
+
+/// FNV-1a, because [std::hash::DefaultHasher] isn't guaranteed to be stable
+/// between Rust versions, and both ends of a connection must agree.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+	fn default() -> Self {
+		Self(0xcbf29ce484222325)
+	}
+}
+
+impl Fnv1a {
+	fn write(&mut self, bytes: &[u8]) {
+		for byte in bytes {
+			self.0 ^= *byte as u64;
+			self.0 = self.0.wrapping_mul(0x100000001b3);
+		}
+	}
+
+	fn finish(&self) -> u64 {
+		self.0
+	}
+}
+
+/// Renet channel for [HandshakeRejection]s, far above the channels [bevy_replicon] uses.
+pub const HANDSHAKE_CHANNEL_ID: u8 = u8::MAX;
+
+/// Appended to both the server's and the client's server channels
+pub fn handshake_channel_config() -> ChannelConfig {
+	ChannelConfig {
+		channel_id: HANDSHAKE_CHANNEL_ID,
+		max_memory_usage_bytes: 64 * 1024,
+		send_type: SendType::ReliableOrdered {
+			resend_time: Duration::from_millis(100),
+		},
+	}
+}
+
+/// Sent by the server to a client whose [ProtocolVersion] doesn't match,
+/// just before disconnecting it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeRejection {
+	pub server: ProtocolVersion,
+
+	/// [None] if the client didn't send a [ProtocolVersion] at all
+	pub client: Option<ProtocolVersion>,
+}
+
+/// How long a rejected client has to receive its [HandshakeRejection]
+/// before the server disconnects it anyway.
+pub const REJECTION_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Clients that have been sent a [HandshakeRejection], and when to disconnect them.
+#[derive(Resource, Debug, Default)]
+pub struct RejectedClients(HashMap<ClientId, Duration>);
+
+impl RejectedClients {
+	pub fn reject(&mut self, client_id: ClientId, now: Duration) {
+		self.0.insert(client_id, now + REJECTION_GRACE_PERIOD);
+	}
+
+	/// Returns whether `client_id` was rejected
+	pub fn forget(&mut self, client_id: ClientId) -> bool {
+		self.0.remove(&client_id).is_some()
+	}
+
+	/// Clients whose grace period is over
+	pub fn expired(&self, now: Duration) -> impl Iterator<Item = ClientId> + '_ {
+		self
+			.0
+			.iter()
+			.filter(move |(_, disconnect_at)| **disconnect_at <= now)
+			.map(|(client_id, _)| *client_id)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn version(types: &[&'static str]) -> ProtocolVersion {
+		let mut replicated = ReplicatedTypes::default();
+		for name in types {
+			replicated.insert(name);
+		}
+		ProtocolVersion::current(&replicated)
+	}
+
+	#[test]
+	fn user_data_round_trip() {
+		let version = version(&["a::A", "b::B"]);
+
+		let user_data = version.to_user_data();
+
+		assert_eq!(ProtocolVersion::from_user_data(&user_data), Some(version));
+	}
+
+	#[test]
+	fn replicated_types_change_protocol_id() {
+		let original = version(&["a::A", "b::B"]);
+
+		assert_eq!(original, version(&["b::B", "a::A"]));
+		assert!(!original.is_compatible_with(&version(&["a::A"])));
+		assert!(!original.is_compatible_with(&version(&["a::A", "b::B", "c::C"])));
+		assert!(!original.is_compatible_with(&version(&["a::Ab::B"])));
+	}
+
+	/// A client that replicates one more type than the server must be told why it can't join,
+	/// and must never get a player.
+	#[test]
+	fn mismatched_client_is_rejected() {
+		let network = LoopbackNetwork::new(LinkConditions::PERFECT, 0);
+		let mut server = test_netcode_app(loopback_server_config(&network, ServerSettingsArgs::NONE));
+		let mut client = test_netcode_app(loopback_client_config(&network));
+		client
+			.world
+			.resource_mut::<ReplicatedTypes>()
+			.insert("test::OnlyOnTheClient");
+
+		update_all(120, &mut [&mut server, &mut client]);
+
+		assert!(
+			matches!(
+				client.world.get_resource::<ConnectionError>(),
+				Some(ConnectionError::ProtocolMismatch(HandshakeRejection {
+					client: Some(_),
+					..
+				}))
+			),
+			"Client should know why it was rejected"
+		);
+		assert_eq!(
+			*client
+				.world
+				.resource::<State<ClientConnectionState>>()
+				.get(),
+			ClientConnectionState::Disconnected
+		);
+
+		let mut players = server.world.query::<&NetworkId>();
+		assert!(
+			players
+				.iter(&server.world)
+				.all(|id| id.get_network_id() == SERVER_ID),
+			"Only the host's player should exist"
+		);
+	}
+}
@@ -0,0 +1,240 @@
+//! Interest management: clients are only sent what is near them.
+//!
+//! The server replicates with [VisibilityPolicy::Whitelist], and every update makes
+//! replicated entities visible to a client while they are within
+//! [ServerSettings::relevancy_radius] of that client's player, or within the range of
+//! a [RelevancySensor] the player owns.
+//! Once an entity is out of range again, `bevy_replicon` despawns it on the client.
+//!
+//! Entities without a [GlobalTransform], and those marked [AlwaysRelevant],
+//! are sent to every client.
+
+use bevy::transform::TransformSystem;
+use bevy_replicon::server::{ClientsInfo, ServerSet};
+
+use crate::prelude::*;
+
+pub(super) struct RelevancyPlugin;
+
+impl Plugin for RelevancyPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.register_type::<AlwaysRelevant>()
+			.register_type::<RelevancySensor>()
+			.init_resource::<RelevantEntities>()
+			.add_systems(
+				PostUpdate,
+				Self::update_relevancy
+					.after(TransformSystem::TransformPropagate)
+					.before(ServerSet::Send)
+					.run_if(resource_exists::<RenetServer>())
+					.run_if(resource_exists::<ServerSettings>()),
+			)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::forget_relevancy);
+	}
+}
+
+/// Replicated to every client regardless of distance
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct AlwaysRelevant;
+
+/// Lets the player owning this entity, i.e. the nearest ancestor with a [NetworkId],
+/// see everything within `range` of it as well.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[reflect(Component)]
+pub struct RelevancySensor {
+	pub range: f32,
+}
+
+/// Entities only stop being relevant this much further away than they became relevant,
+/// so that an entity on the edge doesn't flicker in and out of existence.
+const RELEVANCY_HYSTERESIS: f32 = 1.1;
+
+/// What every client was last made to see
+#[derive(Resource, Default)]
+struct RelevantEntities(HashMap<ClientId, HashSet<Entity>>);
+
+impl RelevancyPlugin {
+	fn update_relevancy(
+		settings: Res<ServerSettings>,
+		mut clients: ResMut<ClientsInfo>,
+		mut relevant: ResMut<RelevantEntities>,
+		players: Query<(&NetworkId, &GlobalTransform)>,
+		sensors: Query<(Entity, &RelevancySensor, &GlobalTransform)>,
+		parents: Query<&Parent>,
+		network_ids: Query<&NetworkId>,
+		replicated: Query<(Entity, Option<&GlobalTransform>, Has<AlwaysRelevant>), With<Replication>>,
+	) {
+		let send_everything = settings.relevancy_radius.is_infinite();
+
+		let mut viewers: HashMap<ClientId, Vec<(Vec3, f32)>> = HashMap::new();
+		for (network_id, transform) in players.iter() {
+			viewers
+				.entry(network_id.get_network_id())
+				.or_default()
+				.push((transform.translation(), settings.relevancy_radius));
+		}
+		for (sensor, RelevancySensor { range }, transform) in sensors.iter() {
+			let owner = std::iter::once(sensor)
+				.chain(parents.iter_ancestors(sensor))
+				.find_map(|entity| network_ids.get(entity).ok());
+			if let Some(owner) = owner {
+				viewers
+					.entry(owner.get_network_id())
+					.or_default()
+					.push((transform.translation(), *range));
+			}
+		}
+
+		// disconnected clients are dropped by only keeping the connected ones
+		let mut previously_relevant = std::mem::take(&mut relevant.0);
+		for client in clients.iter_mut() {
+			let client_id = client.id();
+			let viewers = viewers
+				.get(&client_id)
+				.map(Vec::as_slice)
+				.unwrap_or_default();
+			let previous = previously_relevant.remove(&client_id).unwrap_or_default();
+
+			let current: HashSet<Entity> = replicated
+				.iter()
+				.filter(|(entity, transform, always_relevant)| {
+					let Some(transform) = transform else {
+						return true;
+					};
+					if send_everything || *always_relevant {
+						return true;
+					}
+					let hysteresis = if previous.contains(entity) {
+						RELEVANCY_HYSTERESIS
+					} else {
+						1.0
+					};
+					viewers
+						.iter()
+						.any(|(viewer, range)| viewer.distance(transform.translation()) <= range * hysteresis)
+				})
+				.map(|(entity, ..)| entity)
+				.collect();
+
+			let visibility = client.visibility_mut();
+			for &entity in current.difference(&previous) {
+				visibility.set_visibility(entity, true);
+			}
+			// despawned entities are already despawned on the client
+			for &entity in previous
+				.difference(&current)
+				.filter(|entity| replicated.contains(**entity))
+			{
+				visibility.set_visibility(entity, false);
+			}
+
+			relevant.0.insert(client_id, current);
+		}
+	}
+
+	fn forget_relevancy(mut relevant: ResMut<RelevantEntities>) {
+		relevant.0.clear();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::prelude::*;
+
+	/// Far enough away that nothing there is relevant to players near the origin
+	const FAR: f32 = 5000.;
+
+	fn asteroids(world: &mut World) -> usize {
+		world
+			.query_filtered::<(), With<TerrainStructureBlueprint>>()
+			.iter(world)
+			.count()
+	}
+
+	/// Connects a client, then spawns a few asteroids near the origin and many far away,
+	/// all of which keep moving so that they are replicated every tick.
+	fn play(relevancy_radius: f32) -> (App, App, LoopbackNetwork) {
+		let network = LoopbackNetwork::new(LinkConditions::PERFECT, 0);
+		let mut apps = loopback_game(
+			&network,
+			ServerSettingsArgs {
+				relevancy_radius: Some(relevancy_radius),
+				..ServerSettingsArgs::NONE
+			},
+			1,
+		);
+		let mut client = apps.pop().unwrap();
+		let mut server = apps.pop().unwrap();
+
+		let drifting = |translation: Vec3| TerrainStructureBlueprint {
+			transform: Transform::from_translation(translation),
+			initial_velocity: Some((LinearVelocity(Vec3::X), AngularVelocity(Vec3::Y))),
+			..default()
+		};
+		for i in 0..3 {
+			server
+				.world
+				.spawn(drifting(Vec3::new(0., 100. + i as f32 * 20., 0.)));
+		}
+		for i in 0..100 {
+			server
+				.world
+				.spawn(drifting(Vec3::new(FAR, i as f32 * 20., 0.)));
+		}
+		update_all(120, &mut [&mut server, &mut client]);
+
+		(server, client, network)
+	}
+
+	#[test]
+	fn far_entities_are_not_replicated() {
+		let (mut server, mut client, _) = play(ServerSettings::DEFAULT.relevancy_radius);
+
+		assert_eq!(asteroids(&mut server.world), 103);
+		assert_eq!(
+			asteroids(&mut client.world),
+			3,
+			"Only the asteroids near the player should be replicated"
+		);
+	}
+
+	#[test]
+	fn relevancy_saves_bandwidth() {
+		let (.., filtered) = play(ServerSettings::DEFAULT.relevancy_radius);
+		let (.., everything) = play(f32::INFINITY);
+
+		let filtered = filtered.traffic().bytes_to_clients;
+		let everything = everything.traffic().bytes_to_clients;
+		assert!(
+			filtered * 4 < everything,
+			"Expected far less than {} bytes sent to the client, but sent {}",
+			everything,
+			filtered
+		);
+	}
+
+	#[test]
+	fn leaving_range_despawns_on_client() {
+		let (mut server, mut client, _) = play(ServerSettings::DEFAULT.relevancy_radius);
+		assert_eq!(asteroids(&mut client.world), 3);
+
+		let mut near = server
+			.world
+			.query_filtered::<(&mut Transform, &mut Position), With<TerrainStructureBlueprint>>();
+		let (mut transform, mut position) = near
+			.iter_mut(&mut server.world)
+			.find(|(transform, _)| transform.translation.x < FAR / 2.)
+			.unwrap();
+		transform.translation = Vec3::new(-FAR, 0., 0.);
+		position.0 = transform.translation;
+		update_all(10, &mut [&mut server, &mut client]);
+
+		assert_eq!(
+			asteroids(&mut client.world),
+			2,
+			"Asteroid out of range should be despawned on the client"
+		);
+	}
+}
@@ -0,0 +1,408 @@
+//! Settings for tuning a (dedicated) server.
+//!
+//! Every setting can be given as a `NetcodeConfig::Server` CLI argument,
+//! or in a TOML file passed with `--config`. CLI arguments take precedence
+//! over the file, which takes precedence over [ServerSettings::DEFAULT].
+//!
+//! Example `server.toml`:
+//! ```toml
+//! name = "Friday Night Dogfights"
+//! max_clients = 32
+//! tick_rate = 60.0
+//! update_timeout_secs = 10.0
+//! relevancy_radius = 2000.0
+//! admin_port = 5071
+//! seed = 12345
+//! ```
+//!
+//! Bans are kept in `bans.toml` next to the config file, see [ServerSettingsArgs::bans_file].
+
+use std::path::{Path, PathBuf};
+
+use crate::prelude::*;
+
+use super::bans::DEFAULT_BANS_FILE;
+
+/// Server settings after merging the CLI, the config file and the defaults,
+/// and validating the result.
+///
+/// Inserted as a resource once the server is set up.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct ServerSettings {
+	/// Shown to players looking for a game on the LAN
+	pub name: Cow<'static, str>,
+
+	/// How many clients can be connected at once
+	pub max_clients: usize,
+
+	/// How many times [FixedUpdate] runs per second,
+	/// on clients too once they have been sent a [ServerTickRate].
+	pub tick_rate: f64,
+
+	/// How long [bevy_replicon] waits for a client to acknowledge an update before resending it.
+	/// [bevy_replicon] only reads it when the app is built, so it can only be set for servers
+	/// configured from the CLI or by a [crate::DedicatedServerPlugin],
+	/// see [ServerSettings::validate_after_startup].
+	pub update_timeout: Duration,
+
+	/// How far from their player clients are sent entities, can be [f32::INFINITY].
+	/// Replicated entities further away are not sent to them at all.
+	pub relevancy_radius: f32,
+
+	/// Port on localhost that the admin console accepts commands on, if any
+	pub admin_port: Option<u16>,
+
+	/// Seed of the [GameRng], a random one is chosen when hosting without one.
+	/// Always [Some] in the resource, which holds the seed actually used.
+	pub seed: Option<u64>,
+}
+
+impl ServerSettings {
+	/// Bevy's default [FixedUpdate] rate
+	pub const DEFAULT_TICK_RATE: f64 = 64.0;
+
+	/// Upper bound on [ServerSettings::max_clients], imposed by `netcode`
+	pub const MAX_CLIENTS_LIMIT: usize = 1024;
+
+	pub const DEFAULT: Self = Self {
+		name: Cow::Borrowed("Space Craft Server"),
+		max_clients: 10,
+		tick_rate: Self::DEFAULT_TICK_RATE,
+		update_timeout: Duration::from_secs(5),
+		relevancy_radius: 1000.0,
+		admin_port: None,
+		seed: None,
+	};
+
+	pub fn validate(&self) -> Result<(), ServerSettingsError> {
+		let invalid = |reason: String| Err(ServerSettingsError::Invalid(reason));
+
+		if self.name.trim().is_empty() || self.name.len() > 64 {
+			return invalid(format!(
+				"name must be between 1 and 64 bytes long, got {:?}",
+				self.name
+			));
+		}
+		if self.max_clients == 0 || self.max_clients > Self::MAX_CLIENTS_LIMIT {
+			return invalid(format!(
+				"max_clients must be between 1 and {}, got {}",
+				Self::MAX_CLIENTS_LIMIT,
+				self.max_clients
+			));
+		}
+		if !(self.tick_rate.is_finite() && self.tick_rate > 0.0 && self.tick_rate <= 1000.0) {
+			return invalid(format!(
+				"tick_rate must be above 0 and at most 1000 ticks per second, got {}",
+				self.tick_rate
+			));
+		}
+		if self.update_timeout.is_zero() {
+			return invalid("update_timeout_secs must be above 0".into());
+		}
+		if self.relevancy_radius.is_nan() || self.relevancy_radius <= 0.0 {
+			return invalid(format!(
+				"relevancy_radius must be above 0, got {}",
+				self.relevancy_radius
+			));
+		}
+
+		Ok(())
+	}
+
+	/// Servers hosted from the start menu are only set up once [bevy_replicon] has been built
+	/// with the default [ServerSettings::update_timeout], so they can't have another one.
+	pub fn validate_after_startup(&self) -> Result<(), ServerSettingsError> {
+		if self.update_timeout != Self::DEFAULT.update_timeout {
+			return Err(ServerSettingsError::Invalid(format!(
+				"update_timeout_secs can only be set when starting the server from the CLI, got {:?}",
+				self.update_timeout
+			)));
+		}
+
+		Ok(())
+	}
+
+	pub fn log(&self) {
+		info!(
+			"Server settings: name = {:?}, max_clients = {}, tick_rate = {} Hz, update_timeout = {:?}, relevancy_radius = {}",
+			self.name,
+			self.max_clients,
+			self.tick_rate,
+			self.update_timeout,
+			self.relevancy_radius
+		);
+	}
+}
+
+impl Default for ServerSettings {
+	fn default() -> Self {
+		Self::DEFAULT
+	}
+}
+
+/// The [ServerSettings] that can be passed on the CLI, see [ServerSettingsArgs::resolve].
+#[derive(Debug, Clone, Default, PartialEq, clap::Args)]
+pub struct ServerSettingsArgs {
+	/// TOML file to read server settings from, CLI arguments take precedence over it.
+	#[arg(long)]
+	pub config: Option<PathBuf>,
+
+	/// File to record the session to, to be watched with `space_craft_replay`.
+	/// Only taken from the CLI, not the config file.
+	#[arg(long)]
+	pub record: Option<PathBuf>,
+
+	/// Shown to players looking for a game on the LAN [default: "Space Craft Server"]
+	#[arg(long)]
+	pub name: Option<String>,
+
+	/// How many clients can be connected at once [default: 10]
+	#[arg(long)]
+	pub max_clients: Option<usize>,
+
+	/// How many times the simulation is stepped per second [default: 64]
+	#[arg(long)]
+	pub tick_rate: Option<f64>,
+
+	/// Seconds to wait for a client to acknowledge an update before resending it [default: 5]
+	#[arg(long)]
+	pub update_timeout_secs: Option<f64>,
+
+	/// How far from their player clients are sent entities, `inf` to send everything [default: 1000]
+	#[arg(long)]
+	pub relevancy_radius: Option<f32>,
+
+	/// Port on localhost to accept admin console commands on, e.g. with `nc localhost <port>`
+	#[arg(long)]
+	pub admin_port: Option<u16>,
+
+	/// Seed the world is generated from, the same seed creates the same world [default: random]
+	#[arg(long)]
+	pub seed: Option<u64>,
+}
+
+/// The contents of a `--config` file, every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerSettingsFile {
+	name: Option<String>,
+	max_clients: Option<usize>,
+	tick_rate: Option<f64>,
+	update_timeout_secs: Option<f64>,
+	relevancy_radius: Option<f32>,
+	admin_port: Option<u16>,
+	seed: Option<u64>,
+}
+
+impl ServerSettingsFile {
+	fn load(path: &Path) -> Result<Self, ServerSettingsError> {
+		let text = std::fs::read_to_string(path)
+			.map_err(|err| ServerSettingsError::Io(path.to_path_buf(), err))?;
+		toml::from_str(&text).map_err(|err| ServerSettingsError::Parse(path.to_path_buf(), err))
+	}
+}
+
+impl ServerSettingsArgs {
+	/// No arguments, so only [ServerSettings::DEFAULT] is used
+	pub const NONE: Self = Self {
+		config: None,
+		record: None,
+		name: None,
+		max_clients: None,
+		tick_rate: None,
+		update_timeout_secs: None,
+		relevancy_radius: None,
+		admin_port: None,
+		seed: None,
+	};
+
+	/// Merges these arguments with the `--config` file (if any) and the defaults,
+	/// then validates the result.
+	pub fn resolve(&self) -> Result<ServerSettings, ServerSettingsError> {
+		let file = match &self.config {
+			Some(path) => {
+				info!("Reading server settings from {:?}", path);
+				ServerSettingsFile::load(path)?
+			}
+			None => ServerSettingsFile::default(),
+		};
+		let default = ServerSettings::DEFAULT;
+
+		let update_timeout_secs = self.update_timeout_secs.or(file.update_timeout_secs);
+		let update_timeout = match update_timeout_secs {
+			Some(secs) => Duration::try_from_secs_f64(secs).map_err(|_| {
+				ServerSettingsError::Invalid(format!(
+					"update_timeout_secs must be a positive number of seconds, got {}",
+					secs
+				))
+			})?,
+			None => default.update_timeout,
+		};
+
+		let settings = ServerSettings {
+			name: self
+				.name
+				.clone()
+				.or(file.name)
+				.map_or(default.name, Cow::Owned),
+			max_clients: self
+				.max_clients
+				.or(file.max_clients)
+				.unwrap_or(default.max_clients),
+			tick_rate: self
+				.tick_rate
+				.or(file.tick_rate)
+				.unwrap_or(default.tick_rate),
+			update_timeout,
+			relevancy_radius: self
+				.relevancy_radius
+				.or(file.relevancy_radius)
+				.unwrap_or(default.relevancy_radius),
+			admin_port: self.admin_port.or(file.admin_port),
+			seed: self.seed.or(file.seed),
+		};
+		settings.validate()?;
+
+		Ok(settings)
+	}
+
+	/// Bans are kept next to the `--config` file, or in the working directory without one
+	pub fn bans_file(&self) -> PathBuf {
+		match &self.config {
+			Some(config) => config.with_file_name(DEFAULT_BANS_FILE),
+			None => PathBuf::from(DEFAULT_BANS_FILE),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum ServerSettingsError {
+	Io(PathBuf, std::io::Error),
+	Parse(PathBuf, toml::de::Error),
+	Invalid(String),
+}
+
+impl std::fmt::Display for ServerSettingsError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(path, err) => write!(f, "Couldn't read server config {:?}: {}", path, err),
+			Self::Parse(path, err) => write!(f, "Couldn't parse server config {:?}: {}", path, err),
+			Self::Invalid(reason) => write!(f, "Invalid server settings: {}", reason),
+		}
+	}
+}
+
+impl std::error::Error for ServerSettingsError {}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn write_config(contents: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("space_craft-test-{}.toml", random::<u64>()));
+		std::fs::write(&path, contents).unwrap();
+		path
+	}
+
+	#[test]
+	fn defaults_without_args() {
+		assert_eq!(
+			ServerSettingsArgs::NONE.resolve().unwrap(),
+			ServerSettings::DEFAULT
+		);
+	}
+
+	#[test]
+	fn cli_overrides_config_file() {
+		let path = write_config("max_clients = 32\ntick_rate = 30.0\n");
+		let args = ServerSettingsArgs {
+			config: Some(path.clone()),
+			max_clients: Some(4),
+			..ServerSettingsArgs::NONE
+		};
+
+		let settings = args.resolve();
+		std::fs::remove_file(&path).unwrap();
+		let settings = settings.unwrap();
+
+		assert_eq!(settings.max_clients, 4);
+		assert_eq!(settings.tick_rate, 30.0);
+		assert_eq!(
+			settings.update_timeout,
+			ServerSettings::DEFAULT.update_timeout
+		);
+	}
+
+	#[test]
+	fn rejects_invalid_settings() {
+		for args in [
+			ServerSettingsArgs {
+				max_clients: Some(0),
+				..ServerSettingsArgs::NONE
+			},
+			ServerSettingsArgs {
+				tick_rate: Some(-1.0),
+				..ServerSettingsArgs::NONE
+			},
+			ServerSettingsArgs {
+				update_timeout_secs: Some(0.0),
+				..ServerSettingsArgs::NONE
+			},
+		] {
+			assert!(
+				matches!(args.resolve(), Err(ServerSettingsError::Invalid(_))),
+				"{:?} should be invalid",
+				args
+			);
+		}
+	}
+
+	#[test]
+	fn update_timeout_only_set_at_startup() {
+		let settings = ServerSettingsArgs {
+			update_timeout_secs: Some(10.0),
+			..ServerSettingsArgs::NONE
+		}
+		.resolve()
+		.unwrap();
+
+		assert!(ServerSettings::DEFAULT.validate_after_startup().is_ok());
+		assert!(matches!(
+			settings.validate_after_startup(),
+			Err(ServerSettingsError::Invalid(_))
+		));
+	}
+
+	#[test]
+	fn clients_run_at_the_server_tick_rate() {
+		let apps = loopback_game(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			ServerSettingsArgs {
+				tick_rate: Some(32.0),
+				..ServerSettingsArgs::NONE
+			},
+			1,
+		);
+
+		for app in &apps {
+			assert_eq!(
+				app.world.resource::<Time<Fixed>>().timestep(),
+				Duration::from_secs_f64(1. / 32.)
+			);
+		}
+	}
+
+	#[test]
+	fn rejects_unknown_config_keys() {
+		let path = write_config("max_players = 32\n");
+		let args = ServerSettingsArgs {
+			config: Some(path.clone()),
+			..ServerSettingsArgs::NONE
+		};
+
+		let settings = args.resolve();
+		std::fs::remove_file(&path).unwrap();
+
+		assert!(matches!(settings, Err(ServerSettingsError::Parse(..))));
+	}
+}
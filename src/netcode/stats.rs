@@ -0,0 +1,201 @@
+//! Collects [NetworkStats] while in a game, to be shown by the UI
+//! or logged periodically by headless servers.
+
+use bevy::time::common_conditions::on_timer;
+use bevy_replicon::renet::NetworkInfo;
+
+use crate::prelude::*;
+
+pub(super) struct NetworkStatsPlugin;
+
+impl Plugin for NetworkStatsPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<NetworkStats>()
+			.add_systems(
+				Update,
+				(
+					Self::update_client_stats.run_if(resource_exists::<RenetClient>()),
+					Self::update_server_stats.run_if(resource_exists::<RenetServer>()),
+				),
+			)
+			.add_systems(
+				Update,
+				Self::log_stats
+					.after(Self::update_server_stats)
+					.run_if(resource_exists::<RenetServer>())
+					.run_if(not(NetcodeConfig::not_headless()))
+					.run_if(on_timer(NetworkStats::LOG_INTERVAL)),
+			)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::reset_stats);
+	}
+}
+
+/// How the connection to the server, or every client's connection when hosting, is doing.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct NetworkStats {
+	/// One per connected client on servers, or just the one to the server on clients
+	pub connections: Vec<ConnectionStats>,
+
+	/// Entities with [Replication], i.e. sent to or received from clients
+	pub replicated_entities: usize,
+
+	/// How many ticks the [GameClock] is ahead of the [RepliconTick].
+	/// Always 0 on servers, since they drive the [RepliconTick].
+	pub tick_drift: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConnectionStats {
+	/// [None] for the connection to the server
+	pub client_id: Option<ClientId>,
+	pub rtt: Duration,
+
+	/// From 0 to 1
+	pub packet_loss: f64,
+	pub bytes_sent_per_second: f64,
+	pub bytes_received_per_second: f64,
+}
+
+impl NetworkStats {
+	/// How often headless servers log their [NetworkStats]
+	pub const LOG_INTERVAL: Duration = Duration::from_secs(10);
+}
+
+impl ConnectionStats {
+	fn new(client_id: Option<ClientId>, info: NetworkInfo) -> Self {
+		Self {
+			client_id,
+			rtt: Duration::from_secs_f64(info.rtt.max(0.)),
+			packet_loss: info.packet_loss,
+			bytes_sent_per_second: info.bytes_sent_per_second,
+			bytes_received_per_second: info.bytes_received_per_second,
+		}
+	}
+}
+
+impl std::fmt::Display for ConnectionStats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.client_id {
+			Some(client_id) => write!(f, "Client {}: ", client_id)?,
+			None => write!(f, "Server: ")?,
+		}
+		write!(
+			f,
+			"RTT {:.0} ms, loss {:.1}%, up {:.1} KB/s, down {:.1} KB/s",
+			self.rtt.as_secs_f64() * 1000.,
+			self.packet_loss * 100.,
+			self.bytes_sent_per_second / 1000.,
+			self.bytes_received_per_second / 1000.
+		)
+	}
+}
+
+impl std::fmt::Display for NetworkStats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(
+			f,
+			"Replicated entities: {}, tick drift: {}",
+			self.replicated_entities, self.tick_drift
+		)?;
+		if self.connections.is_empty() {
+			write!(f, "No connections")?;
+		}
+		for connection in &self.connections {
+			writeln!(f, "{}", connection)?;
+		}
+		Ok(())
+	}
+}
+
+impl NetworkStatsPlugin {
+	fn update_client_stats(
+		client: Res<RenetClient>,
+		game_clock: Res<GameClock>,
+		replicon_tick: Res<RepliconTick>,
+		replicated: Query<(), With<Replication>>,
+		mut stats: ResMut<NetworkStats>,
+	) {
+		*stats = NetworkStats {
+			connections: if client.is_connected() {
+				vec![ConnectionStats::new(None, client.network_info())]
+			} else {
+				Vec::new()
+			},
+			replicated_entities: replicated.iter().count(),
+			tick_drift: i64::from(game_clock.frame()) - i64::from(replicon_tick.get()),
+		};
+	}
+
+	fn update_server_stats(
+		server: Res<RenetServer>,
+		replicated: Query<(), With<Replication>>,
+		mut stats: ResMut<NetworkStats>,
+	) {
+		let mut connections: Vec<_> = server
+			.clients_id()
+			.into_iter()
+			.filter_map(|client_id| {
+				let info = server.network_info(client_id).ok()?;
+				Some(ConnectionStats::new(Some(client_id), info))
+			})
+			.collect();
+		connections.sort_by_key(|connection| connection.client_id.map(|id| id.raw()));
+
+		*stats = NetworkStats {
+			connections,
+			replicated_entities: replicated.iter().count(),
+			tick_drift: 0,
+		};
+	}
+
+	fn log_stats(stats: Res<NetworkStats>) {
+		info!("Network stats:\n{}", *stats);
+	}
+
+	fn reset_stats(mut stats: ResMut<NetworkStats>) {
+		*stats = NetworkStats::default();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::prelude::*;
+
+	#[test]
+	fn stats_cover_every_connection() {
+		let mut apps = loopback_game(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			ServerSettingsArgs::NONE,
+			2,
+		);
+		let (server, clients) = apps.split_first_mut().unwrap();
+
+		let server_stats = server.world.resource::<NetworkStats>();
+		assert_eq!(server_stats.connections.len(), 2, "One per client");
+		assert!(server_stats
+			.connections
+			.iter()
+			.all(|connection| connection.client_id.is_some()));
+		assert!(server_stats.replicated_entities > 0);
+
+		for client in clients.iter() {
+			let stats = client.world.resource::<NetworkStats>();
+			assert_eq!(stats.connections.len(), 1);
+			assert_eq!(stats.connections[0].client_id, None);
+			assert!(stats.connections[0].bytes_received_per_second > 0.);
+			assert!(stats.replicated_entities > 0);
+		}
+
+		server
+			.world
+			.insert_resource(NextState(Some(GlobalGameStates::StartMenu)));
+		server.update();
+		server.update();
+		assert!(server
+			.world
+			.resource::<NetworkStats>()
+			.connections
+			.is_empty());
+	}
+}
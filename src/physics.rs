@@ -8,8 +8,11 @@ impl Plugin for PhysicsPlugin {
 			PhysicsPlugins::new(FixedUpdate),
 			bevy_xpbd3d_parenting::PhysicsParentingPlugin,
 		));
+		// dedicated servers can't draw gizmos
 		#[cfg(feature = "debug")]
-		app.add_plugins(PhysicsDebugPlugin::default());
+		if app.is_plugin_added::<bevy::gizmos::GizmoPlugin>() {
+			app.add_plugins(PhysicsDebugPlugin::default());
+		}
 
 		app.insert_resource(Gravity(Vec3::ZERO));
 	}
@@ -2,15 +2,30 @@ use crate::prelude::*;
 
 mod player;
 mod player_movement;
+mod ship_designs;
+mod ship_validation;
 mod spawn_points;
 mod thruster_block;
 
+pub use player::{ControllablePlayer, PlayerBlueprintComponent};
+pub use player_movement::{PlayerInput, PressedInputs, ThrusterStrengths};
+pub use ship_designs::{
+	DesignedBlock, DesignedBlockKind, LocalShipDesign, ShipDesign, ShipDesignError, ShipDesigns,
+	SubmitShipDesign,
+};
+pub use ship_validation::{validate_ship, ShipLimits, ShipRejected, ShipValidationError};
+pub use spawn_points::{SpawnPointBlueprintComponent, SpawnPointsConfig};
+
 /// Plugin Group
 pub struct PlayerPlugins;
 
 /// Handled in [self::player_movement]
 #[derive(SystemSet, Hash, Debug, PartialEq, Eq, Clone, Copy)]
 enum PlayerMovementSet {
+	/// Sends local inputs to the server, and applies
+	/// inputs received from clients to their players
+	SyncInputs,
+
 	/// After this set, the strengths for each player are computed
 	ComputeStrengths,
 
@@ -25,6 +40,7 @@ impl PluginGroup for PlayerPlugins {
 			.add(player::PlayerPlugin)
 			.add(thruster_block::ThrusterPlugin)
 			.add(spawn_points::SpawnPointsPlugin)
+			.add(ship_designs::ShipDesignsPlugin)
 			.add(player_movement::PlayerMovementPlugin)
 			.build()
 	}
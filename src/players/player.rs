@@ -9,21 +9,24 @@ impl Plugin for PlayerPlugin {
 		app.depends_on::<RepliconCorePlugin, _>(ReplicationPlugins);
 		app.depends_on::<crate::cameras::CameraPlugin, _>(crate::cameras::CameraPlugin);
 
-		replicate_marked!(app, player_blueprint::PlayerBlueprintComponent);
-
 		app
-			.register_type::<player_blueprint::PlayerBlueprintComponent>()
-			.register_type::<components::ControllablePlayer>()
-			.add_systems(
-				Blueprints,
-				Self::handle_spawn_player_blueprints.in_set(BlueprintExpansion::Player),
+			.register_networked_blueprint::<player_blueprint::PlayerBlueprintComponent>(
+				"PlayerBlueprintComponent",
 			)
+			.register_type::<components::ControllablePlayer>()
 			.add_systems(
 				GameLogic,
 				(
 					Self::manage_primary_camera.run_if(NetcodeConfig::not_headless()),
 					Self::name_player,
-					Self::handle_player_join,
+					// leaves first, so that freed spawn points can be reused straight away
+					(
+						Self::handle_player_leave,
+						Self::handle_submitted_ship_designs,
+						Self::handle_player_respawn,
+						Self::handle_player_join,
+					)
+						.chain(),
 				),
 			);
 	}
@@ -37,51 +40,17 @@ mod api {
 mod systems {
 	use crate::{
 		cameras::{BlockEntity, CameraBlockMarker, ChangeCameraConfig},
-		players::spawn_points::AvailableSpawnPoints,
+		players::{
+			ship_designs::{ShipDesigns, SubmitShipDesign},
+			ship_validation::{validate_ship, ShipLimits, ShipRejected},
+			spawn_points::AvailableSpawnPoints,
+		},
 		prelude::*,
 	};
 
-	use super::{
-		player_blueprint::PlayerBlueprintComponent, ControllablePlayer, PlayerBlueprintBundle,
-		PlayerPlugin,
-	};
+	use super::{ControllablePlayer, PlayerBlueprintBundle, PlayerBlueprintComponent, PlayerPlugin};
 
 	impl PlayerPlugin {
-		pub(super) fn handle_spawn_player_blueprints(
-			player_blueprints: Query<
-				(Entity, &PlayerBlueprintComponent, &NetworkId),
-				Changed<PlayerBlueprintComponent>,
-			>,
-			mut commands: Commands,
-			mut mma: MMA,
-		) {
-			for (player, player_blueprint, id) in player_blueprints.iter() {
-				debug!("Expanding player blueprint for {:?}", id);
-				commands
-					.entity(player)
-					.despawn_descendants()
-					.insert(FreshlyExpanded)
-					.insert(player_blueprint.stamp())
-					.with_children(|parent| {
-						for blueprint in &player_blueprint.structure_children {
-							parent
-								.spawn(blueprint.stamp(&mut mma))
-								.insert(FreshlyExpanded);
-						}
-
-						for blueprint in &player_blueprint.thruster_children {
-							parent
-								.spawn(blueprint.stamp(&mut mma))
-								.insert(FreshlyExpanded);
-						}
-
-						parent
-							.spawn(player_blueprint.primary_camera.stamp(&mut mma))
-							.insert(FreshlyExpanded);
-					});
-			}
-		}
-
 		/// When new [CameraBlockMarker]s are spawned,
 		/// check if they are the child of the local player.
 		/// If so, set the primary camera to it.
@@ -109,15 +78,148 @@ mod systems {
 			mut commands: Commands,
 			mut spawn_point: AvailableSpawnPoints,
 			mut player_joins: EventReader<PlayerJoin>,
+			designs: Res<ShipDesigns>,
+			rng: Res<GameRng>,
 		) {
 			for id in player_joins.read() {
 				trace!("Received {:?}", id);
 
-				let transform = spawn_point
-					.try_get_spawn_location(id.0)
-					.expect("No more spawn points left!");
+				let Some(transform) = spawn_point.try_get_spawn_location(id.0) else {
+					error!(
+						"No spawn point left for player {}, every shell of spawn points is full",
+						id.0
+					);
+					continue;
+				};
+
+				commands.spawn(PlayerBlueprintBundle::new(
+					id.0,
+					transform,
+					designs.blueprint(id.0, &rng),
+				));
+			}
+		}
+
+		/// Despawns the player of every [PlayerLeave] event, frees their
+		/// spawn point and tells the remaining clients.
+		pub(super) fn handle_player_leave(
+			mut commands: Commands,
+			mut spawn_point: AvailableSpawnPoints,
+			mut player_leaves: EventReader<PlayerLeave>,
+			mut designs: ResMut<ShipDesigns>,
+			players: Query<(Entity, &NetworkId), With<ControllablePlayer>>,
+			mut departures: EventWriter<ToClients<PlayerDeparted>>,
+		) {
+			for PlayerLeave(id) in player_leaves.read() {
+				trace!("Received {:?}", PlayerLeave(*id));
+
+				match players
+					.iter()
+					.find(|(_, network_id)| network_id.get_network_id() == *id)
+				{
+					Some((player, _)) => commands.entity(player).despawn_recursive(),
+					None => warn!("Player {} left, but has no player entity to despawn", id),
+				}
+
+				if !spawn_point.release_spawn_location(*id) {
+					warn!("Player {} left, but wasn't occupying a spawn point", id);
+				}
+				designs.remove(*id);
 
-				commands.spawn(PlayerBlueprintBundle::new(id.0, transform));
+				departures.send(ToClients {
+					mode: SendMode::Broadcast,
+					event: PlayerDeparted(NetworkId::from_raw(id.raw())),
+				});
+			}
+		}
+
+		/// Keeps the design of every [SubmitShipDesign] in [ShipDesigns],
+		/// and rebuilds the sender's ship from it if they already have one.
+		///
+		/// Designs that fail [validate_ship] are sent back in a [ShipRejected] instead.
+		pub(super) fn handle_submitted_ship_designs(
+			mut submissions: EventReader<FromClient<SubmitShipDesign>>,
+			mut designs: ResMut<ShipDesigns>,
+			mut players: Query<(&NetworkId, &mut PlayerBlueprintComponent), With<ControllablePlayer>>,
+			mut rejections: EventWriter<ToClients<ShipRejected>>,
+			limits: Res<ShipLimits>,
+			rng: Res<GameRng>,
+		) {
+			for FromClient { client_id, event } in submissions.read() {
+				let SubmitShipDesign(design) = event;
+				let blueprint =
+					PlayerBlueprintComponent::from_design(design, &mut rng.block_ids(*client_id))
+						.map_err(|err| vec![err])
+						.and_then(|blueprint| validate_ship(&blueprint, &limits).map(|()| blueprint));
+				let blueprint = match blueprint {
+					Ok(blueprint) => blueprint,
+					Err(errors) => {
+						warn!(
+							"Rejecting the ship design of client {}: {}",
+							client_id,
+							errors
+								.iter()
+								.map(ToString::to_string)
+								.collect::<Vec<_>>()
+								.join(", ")
+						);
+						rejections.send(ToClients {
+							mode: SendMode::Direct(*client_id),
+							event: ShipRejected(errors),
+						});
+						continue;
+					}
+				};
+				debug!(
+					"Client {} submitted a ship design of {} blocks",
+					client_id,
+					design.blocks.len()
+				);
+				designs.insert(*client_id, design.clone());
+
+				for (_, mut player) in players
+					.iter_mut()
+					.filter(|(network_id, _)| network_id.get_network_id() == *client_id)
+				{
+					*player = blueprint.clone();
+				}
+			}
+		}
+
+		/// Replaces the player of every [PlayerRespawn] event with a fresh one,
+		/// at whichever spawn point is free first.
+		pub(super) fn handle_player_respawn(
+			mut commands: Commands,
+			mut spawn_point: AvailableSpawnPoints,
+			mut player_respawns: EventReader<PlayerRespawn>,
+			players: Query<(Entity, &NetworkId), With<ControllablePlayer>>,
+			designs: Res<ShipDesigns>,
+			rng: Res<GameRng>,
+		) {
+			for PlayerRespawn(id) in player_respawns.read() {
+				trace!("Received {:?}", PlayerRespawn(*id));
+
+				for (player, _) in players
+					.iter()
+					.filter(|(_, network_id)| network_id.get_network_id() == *id)
+				{
+					commands.entity(player).despawn_recursive();
+				}
+				spawn_point.release_spawn_location(*id);
+
+				let Some(transform) = spawn_point.try_get_spawn_location(*id) else {
+					error!(
+						"No spawn point left to respawn player {}, every shell of spawn points is full",
+						id
+					);
+					continue;
+				};
+
+				commands.spawn(PlayerBlueprintBundle::new(
+					*id,
+					transform,
+					designs.blueprint(*id, &rng),
+				));
 			}
 		}
 
@@ -144,25 +246,31 @@ mod components {
 
 mod player_blueprint {
 	use crate::{
-		blocks::manual_builder::Facing, cameras::CameraBlockBlueprint,
-		players::thruster_block::ThrusterBlockBlueprint, prelude::*,
+		blocks::manual_builder::{Facing, RelativePixel},
+		cameras::CameraBlockBlueprint,
+		players::{
+			ship_designs::{DesignedBlock, DesignedBlockKind, ShipDesign},
+			ship_validation::ShipValidationError,
+			thruster_block::ThrusterBlockBlueprint,
+		},
+		prelude::*,
 	};
 
 	/// What is used to construct a [PlayerBundle]
-	#[derive(Component, Reflect, Serialize, Deserialize, Debug)]
+	#[derive(Component, Reflect, Serialize, Deserialize, Debug, Clone)]
 	pub struct PlayerBlueprintComponent {
-		pub(super) structure_children: Vec<BlockBlueprint<StructureBlockBlueprint>>,
-		pub(super) thruster_children: Vec<BlockBlueprint<ThrusterBlockBlueprint>>,
-		pub(super) primary_camera: BlockBlueprint<CameraBlockBlueprint>,
+		pub(crate) structure_children: Vec<BlockBlueprint<StructureBlockBlueprint>>,
+		pub(crate) thruster_children: Vec<BlockBlueprint<ThrusterBlockBlueprint>>,
+		pub(crate) primary_camera: BlockBlueprint<CameraBlockBlueprint>,
 	}
 
-	#[derive(Bundle, Debug, Serialize, Deserialize, Deref)]
+	#[derive(Bundle, Debug, Serialize, Deserialize, NetworkedBlueprintBundle)]
 	pub struct PlayerBlueprintBundle {
 		/// Synced
 		pub(super) transform: Transform,
 
 		/// Synced
-		#[deref]
+		#[blueprint]
 		pub(super) blueprint: PlayerBlueprintComponent,
 
 		/// Synced
@@ -170,50 +278,124 @@ mod player_blueprint {
 	}
 
 	impl PlayerBlueprintBundle {
-		pub fn new(network_id: ClientId, transform: Transform) -> Self {
+		pub fn new(
+			network_id: ClientId,
+			transform: Transform,
+			blueprint: PlayerBlueprintComponent,
+		) -> Self {
 			PlayerBlueprintBundle {
 				transform,
 				network_id: NetworkId::from_raw(network_id.raw()),
-				blueprint: PlayerBlueprintComponent {
-					structure_children: vec![
-						BlockBlueprint::new_structure(StructureBlockBlueprint::Aluminum, IVec3::ZERO), // center
-						BlockBlueprint::new_structure(StructureBlockBlueprint::Aluminum, IVec3::new(0, 0, -1)), // front
-						BlockBlueprint::new_structure(StructureBlockBlueprint::Aluminum, IVec3::new(0, 0, 1)),
-						BlockBlueprint::new_structure(StructureBlockBlueprint::Aluminum, IVec3::new(0, 0, 2)),
-						BlockBlueprint::new_structure(StructureBlockBlueprint::Aluminum, IVec3::new(1, 0, 2)),
-						BlockBlueprint::new_structure(StructureBlockBlueprint::Aluminum, IVec3::new(-1, 0, 2)),
-						BlockBlueprint::new_structure(StructureBlockBlueprint::Aluminum, IVec3::new(1, 0, 3)),
-						BlockBlueprint::new_structure(StructureBlockBlueprint::Aluminum, IVec3::new(-1, 0, 3)),
-					],
-					thruster_children: vec![
-						BlockBlueprint::new_thruster(IVec3::new(-1, 0, 0), Facing::Left),
-						BlockBlueprint::new_thruster(IVec3::new(1, 0, 0), Facing::Right),
-						BlockBlueprint::new_thruster(IVec3::new(-1, 0, 1), Facing::Left),
-						BlockBlueprint::new_thruster(IVec3::new(1, 0, 1), Facing::Right),
-						BlockBlueprint::new_thruster(IVec3::new(-2, 0, 2), Facing::Left),
-						BlockBlueprint::new_thruster(IVec3::new(2, 0, 2), Facing::Right),
-						BlockBlueprint::new_thruster(IVec3::new(-2, 0, 3), Facing::Left),
-						BlockBlueprint::new_thruster(IVec3::new(2, 0, 3), Facing::Right),
-						// BlockBlueprint::new_thruster(IVec3::new(1, 1, 3), Facing::Up),
-						// BlockBlueprint::new_thruster(IVec3::new(1, -1, 3), Facing::Down),
-						// BlockBlueprint::new_thruster(IVec3::new(-1, 1, 3), Facing::Up),
-						// BlockBlueprint::new_thruster(IVec3::new(-1, -1, 3), Facing::Down),
-					],
-					primary_camera: BlockBlueprint::new_camera(IVec3::new(0, 1, 0), Facing::Forwards),
-				},
+				blueprint,
 			}
 		}
 	}
+
 	impl PlayerBlueprintComponent {
-		pub fn derive_thruster_ids(&self) -> impl Iterator<Item = BlockId> + '_ {
-			self.thruster_children.iter().map(|b| b.get_block_id())
+		/// Builds every block of `design`, with IDs drawn from `rng`.
+		///
+		/// Only fails if there isn't exactly one camera block,
+		/// [crate::players::validate_ship] checks everything else.
+		pub fn from_design(
+			design: &ShipDesign,
+			rng: &mut impl Rng,
+		) -> Result<Self, ShipValidationError> {
+			let mut structure_children = Vec::new();
+			let mut thruster_children = Vec::new();
+			let mut cameras = Vec::new();
+			for block in &design.blocks {
+				match block.kind {
+					DesignedBlockKind::Structure(kind) => {
+						let mut structure = BlockBlueprint::new_structure(kind, block.position, rng);
+						structure.transform.rotation = block.facing.into_quat();
+						structure_children.push(structure);
+					}
+					DesignedBlockKind::Thruster => {
+						thruster_children.push(BlockBlueprint::new_thruster(
+							block.position,
+							block.facing,
+							rng,
+						));
+					}
+					DesignedBlockKind::Camera => {
+						cameras.push(BlockBlueprint::new_camera(
+							block.position,
+							block.facing,
+							rng,
+						));
+					}
+				}
+			}
+
+			let [primary_camera] = <[_; 1]>::try_from(cameras)
+				.map_err(|cameras| ShipValidationError::CameraCount(cameras.len()))?;
+			Ok(Self {
+				structure_children,
+				thruster_children,
+				primary_camera,
+			})
 		}
-	}
 
-	impl PlayerBlueprintComponent {
-		// todo: impl spawn point semantics
-		pub fn stamp(&self) -> <PlayerBlueprintComponent as Blueprint>::Bundle {
-			Blueprint::stamp(self, &mut ())
+		/// The [ShipDesign] this was built from, to be saved
+		pub fn to_design(&self) -> Result<ShipDesign, ShipValidationError> {
+			Ok(ShipDesign {
+				version: ShipDesign::VERSION,
+				blocks: self
+					.designed_blocks()
+					.map(|(_, block)| block)
+					.collect::<Result<_, _>>()?,
+			})
+		}
+
+		/// Every block as it is saved in a [ShipDesign], the camera block last.
+		/// Fails for blocks that aren't on a [RelativePixel] facing a [Facing].
+		pub fn designed_blocks(
+			&self,
+		) -> impl Iterator<Item = (BlockId, Result<DesignedBlock, ShipValidationError>)> + '_ {
+			fn designed<T: GetBlockId>(
+				block: &BlockBlueprint<T>,
+				kind: DesignedBlockKind,
+				// where the block sits relative to its pixel
+				offset: Vec3,
+			) -> (BlockId, Result<DesignedBlock, ShipValidationError>) {
+				let id = block.get_block_id();
+				let Transform {
+					translation,
+					rotation,
+					scale,
+				} = block.transform;
+				if !(translation.is_finite() && rotation.is_finite() && scale.is_finite()) {
+					return (id, Err(ShipValidationError::NonFinite(id)));
+				}
+				let designed = RelativePixel::from_world_offset(translation - offset)
+					.zip(Facing::from_quat(rotation))
+					.map(|(position, facing)| DesignedBlock {
+						position,
+						facing,
+						kind,
+					})
+					.ok_or(ShipValidationError::OffGrid(id));
+				(id, designed)
+			}
+
+			let structures = self
+				.structure_children
+				.iter()
+				.map(|block| designed(block, DesignedBlockKind::Structure(block.kind), Vec3::ZERO));
+			let thrusters = self.thruster_children.iter().map(|block| {
+				designed(
+					block,
+					DesignedBlockKind::Thruster,
+					-block.transform.forward() * PIXEL_SIZE / 2.,
+				)
+			});
+			let camera = designed(&self.primary_camera, DesignedBlockKind::Camera, Vec3::ZERO);
+
+			structures.chain(thrusters).chain(std::iter::once(camera))
+		}
+
+		pub fn derive_thruster_ids(&self) -> impl Iterator<Item = BlockId> + '_ {
+			self.thruster_children.iter().map(|b| b.get_block_id())
 		}
 	}
 }
@@ -222,13 +404,13 @@ mod player_bundle {
 
 	use crate::{players::player_movement::PlayerBundleMovementExt, prelude::*};
 
-	use super::{ControllablePlayer, PlayerBlueprintBundle, PlayerBlueprintComponent};
+	use super::{ControllablePlayer, PlayerBlueprintComponent};
 
 	/// Parent entity of a player.
 	/// Doesn't actually have its own [Mesh] / [Collider],
 	/// because its children provide that for it.
 	///
-	/// Also, doesn't have a transform because [PlayerBlueprintBundle] provides
+	/// Also, doesn't have a transform because [super::PlayerBlueprintBundle] provides
 	/// that for it through [bevy_replicon].
 	#[derive(Bundle)]
 	pub struct PlayerBundle {
@@ -248,9 +430,9 @@ mod player_bundle {
 
 	impl Blueprint for PlayerBlueprintComponent {
 		type Bundle = PlayerBundle;
-		type StampSystemParam<'w, 's> = ();
+		type StampSystemParam = MMA<'static>;
 
-		fn stamp(&self, _system_param: &mut Self::StampSystemParam<'_, '_>) -> Self::Bundle {
+		fn stamp(&self, _mma: &mut SystemParamItem<'_, '_, Self::StampSystemParam>) -> Self::Bundle {
 			let PlayerBlueprintComponent {
 				structure_children: _,
 				thruster_children: _,
@@ -268,11 +450,70 @@ mod player_bundle {
 				no_frustum: NoFrustumCulling,
 			}
 		}
+
+		fn stamp_children(
+			&self,
+			mma: &mut SystemParamItem<'_, '_, Self::StampSystemParam>,
+			children: &mut BlueprintChildren,
+		) {
+			for blueprint in &self.structure_children {
+				children.stamp(blueprint.get_block_id(), blueprint, || blueprint.stamp(mma));
+			}
+			for blueprint in &self.thruster_children {
+				children.stamp(blueprint.get_block_id(), blueprint, || blueprint.stamp(mma));
+			}
+			let camera = &self.primary_camera;
+			children.stamp(camera.get_block_id(), camera, || camera.stamp(mma));
+		}
 	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{players::spawn_points::SpawnPointBlueprintComponent, prelude::*};
+
+	use super::ControllablePlayer;
+
+	/// More players join and leave than there are spawn points,
+	/// which only works if leaving players free their spawn point.
+	#[test]
+	fn join_leave_churn() {
+		let mut server = test_netcode_app(loopback_server_config(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			ServerSettingsArgs::NONE,
+		));
+		// creates the world, and spawns the server's own player
+		for _ in 0..10 {
+			server.update();
+		}
 
-	impl NetworkedBlueprintBundle for PlayerBlueprintBundle {
-		type NetworkedBlueprintComponent = PlayerBlueprintComponent;
+		for raw_id in 1..=50 {
+			let id = ClientId::from_raw(raw_id);
+
+			server.world.send_event(PlayerJoin(id));
+			for _ in 0..3 {
+				server.update();
+			}
+			server.world.send_event(PlayerLeave(id));
+			for _ in 0..3 {
+				server.update();
+			}
+		}
 
-		type SpawnSystemParam = ();
+		let players = server
+			.world
+			.query_filtered::<&NetworkId, With<ControllablePlayer>>()
+			.iter(&server.world)
+			.map(|id| id.get_network_id())
+			.collect::<Vec<_>>();
+		assert_eq!(players, vec![SERVER_ID]);
+
+		let occupied = server
+			.world
+			.query::<&SpawnPointBlueprintComponent>()
+			.iter(&server.world)
+			.filter_map(|spawn_point| spawn_point.occupation)
+			.collect::<Vec<_>>();
+		assert_eq!(occupied, vec![SERVER_ID.raw()]);
 	}
 }
@@ -13,10 +13,12 @@ pub struct PlayerMovementPlugin;
 impl Plugin for PlayerMovementPlugin {
 	fn build(&self, app: &mut App) {
 		app
+			.register_replicated_type("IntendedVelocity")
 			.replicate::<components::IntendedVelocity>()
 			.configure_sets(
 				FixedUpdate,
 				(
+					PlayerMovementSet::SyncInputs,
 					PlayerMovementSet::ComputeStrengths,
 					PlayerMovementSet::EnactThrusters,
 				)
@@ -36,9 +38,13 @@ impl Plugin for PlayerMovementPlugin {
 					.chain()
 					.in_set(PlayerMovementSet::ComputeStrengths),
 			)
-			.add_plugins(InputManagerPlugin::<PlayerInput>::default())
+			.add_plugins((
+				InputManagerPlugin::<PlayerInput>::default(),
+				input_processing::InputProcessingPlugin,
+			))
 			.register_type::<components::ThrusterAxis>()
 			.register_type::<components::ThrusterStrengths>()
+			.register_rollback::<components::ThrusterStrengths>()
 			.register_type::<components::IntendedVelocity>()
 			.register_type::<components::ActualVelocity>();
 	}
@@ -49,16 +55,20 @@ mod api {
 	use super::components::{ActualVelocity, IntendedVelocity, ThrusterAxis, ThrusterStrengths};
 	use crate::prelude::*;
 
-	pub use super::input_processing::PlayerInput;
+	pub use super::components::ThrusterStrengths;
+	pub use super::input_processing::{InputBuffer, PlayerInput, PressedInputs};
 
 	#[derive(SystemParam, Debug)]
 	pub struct GetThrusterData<'w, 's> {
 		players: Query<'w, 's, &'static ThrusterStrengths>,
 	}
 
+	/// The [InputMap] is only added to the local player,
+	/// see [super::input_processing::InputProcessingPlugin]
 	#[derive(Bundle)]
 	pub struct PlayerBundleMovementExt {
-		input: InputManagerBundle<PlayerInput>,
+		input: ActionState<PlayerInput>,
+		input_buffer: InputBuffer,
 		thruster_strengths: ThrusterStrengths,
 		thruster_axis: ThrusterAxis,
 		intended_velocity: IntendedVelocity,
@@ -69,6 +79,7 @@ mod api {
 		pub fn new() -> Self {
 			Self {
 				input: PlayerInput::new(),
+				input_buffer: InputBuffer::default(),
 				thruster_strengths: Default::default(),
 				thruster_axis: ThrusterAxis::default(),
 				intended_velocity: IntendedVelocity::default(),
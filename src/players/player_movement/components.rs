@@ -3,9 +3,10 @@ use crate::prelude::*;
 /// Stores all of the data concerning thruster movements.
 /// Placed on players.
 ///
-/// Is not replicated, is derived data
-#[derive(Component, Debug, Reflect, Serialize, Deserialize, Default)]
-pub(super) struct ThrusterStrengths {
+/// Replicated so that every client shows the same thrusters firing,
+/// and predicted on the local player like its velocities, see [crate::netcode::prediction].
+#[derive(Component, Debug, Reflect, Serialize, Deserialize, Default, Clone, PartialEq)]
+pub struct ThrusterStrengths {
 	blocks: HashMap<BlockId, f32>,
 }
 
@@ -1,19 +1,43 @@
+//! Sends the local player's [PlayerInput] to the server,
+//! and applies the inputs received from clients to their players.
+//!
+//! Only the locally controlled player has an [InputMap],
+//! every other player's [ActionState] is driven by the network.
+//!
+//! Inputs are kept per [GameClock] frame in an [InputBuffer], so that both the server
+//! and a client re-simulating a rollback apply them on the frame they were pressed for.
+
+use std::collections::BTreeMap;
+
 use crate::prelude::*;
 
-// pub struct InputProcessingPlugin;
-
-// impl Plugin for InputProcessingPlugin {
-// 	fn build(&self, app: &mut App) {
-// 		app
-// 			.add_systems(PreUpdate, process_action_diffs::<PlayerInput, Key>)
-// 			.add_systems(
-// 				PostUpdate,
-// 				generate_action_diffs::<PlayerInput, Key>.run_if(NetcodeConfig::not_headless()),
-// 			)
-// 			// .register_type::<Key>()
-// 			.add_client_event::<ActionDiff<PlayerInput, Key>>(SendType::Unreliable);
-// 	}
-// }
+use super::PlayerMovementSet;
+
+pub struct InputProcessingPlugin;
+
+impl Plugin for InputProcessingPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.register_replicated_type("PlayerInputSnapshot")
+			.add_client_event::<PlayerInputSnapshot>(EventType::Unreliable)
+			.add_systems(Update, Self::attach_local_input_map.in_set(Client))
+			.add_systems(
+				PreUpdate,
+				Self::sample_local_input
+					.after(leafwing_input_manager::plugin::InputManagerSystem::Update)
+					.run_if(not(NetcodeConfig::has_authority())),
+			)
+			.add_systems(
+				FixedUpdate,
+				(
+					Self::send_local_input.run_if(not(NetcodeConfig::has_authority())),
+					Self::apply_remote_input.run_if(NetcodeConfig::has_authority()),
+				)
+					.chain()
+					.in_set(PlayerMovementSet::SyncInputs),
+			);
+	}
+}
 
 #[derive(ActionLike, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect, Serialize, Deserialize)]
 pub enum PlayerInput {
@@ -28,39 +52,267 @@ impl PlayerInput {
 	pub const ROTATION_FACTOR: f32 = 2.;
 }
 
-// #[derive(SystemParam, Debug)]
-// pub struct PlayerInputs<'w, 's> {
-// 	query: Query<'w, 's, (Entity, &'static ActionState<PlayerInput>)>,
-// }
-
-// impl PlayerInputs<'_, '_> {
-// 	// pub fn get_from_id(&self, player_id: ClientId) -> Option<&ActionState<PlayerInput>> {
-// 	// 	self
-// 	// 		.query
-// 	// 		.iter()
-// 	// 		.find(|(_, player)| player.get_network_id() == player_id)
-// 	// 		.map(|(action_state, _)| action_state)
-// 	// }
-
-// 	// pub fn get(&self, e: Entity) -> Option<&ActionState<PlayerInput>> {
-// 	// 	self.query.get(e).ok().map(|(action_state, _)| action_state)
-// 	// }
-
-// 	pub fn iter(&self) -> impl Iterator<Item = (Entity, &ActionState<PlayerInput>)> {
-// 		self.query.iter()
-// 	}
-// }
-
 impl PlayerInput {
-	pub fn new() -> InputManagerBundle<Self> {
-		InputManagerBundle {
-			action_state: ActionState::default(),
-			input_map: InputMap::new([
-				(KeyCode::W, PlayerInput::Forward),
-				(KeyCode::S, PlayerInput::Backward),
-				(KeyCode::A, PlayerInput::Left),
-				(KeyCode::D, PlayerInput::Right),
-			]),
+	pub fn new() -> ActionState<Self> {
+		ActionState::default()
+	}
+
+	/// Only inserted on the locally controlled player, see [InputProcessingPlugin]
+	pub fn default_input_map() -> InputMap<Self> {
+		InputMap::new([
+			(KeyCode::W, PlayerInput::Forward),
+			(KeyCode::S, PlayerInput::Backward),
+			(KeyCode::A, PlayerInput::Left),
+			(KeyCode::D, PlayerInput::Right),
+		])
+	}
+}
+
+/// Every [PlayerInput] that is currently pressed, packed into bits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PressedInputs(u8);
+
+impl PressedInputs {
+	pub fn from_action_state(action_state: &ActionState<PlayerInput>) -> Self {
+		let mut bits = 0;
+		for action in PlayerInput::variants() {
+			if action_state.pressed(action) {
+				bits |= 1 << action.index();
+			}
+		}
+		Self(bits)
+	}
+
+	pub fn is_pressed(&self, action: PlayerInput) -> bool {
+		self.0 & (1 << action.index()) != 0
+	}
+
+	/// Presses and releases actions so that `action_state` matches `self`
+	pub fn apply_to(&self, action_state: &mut ActionState<PlayerInput>) {
+		for action in PlayerInput::variants() {
+			match (self.is_pressed(action), action_state.pressed(action)) {
+				(true, false) => action_state.press(action),
+				(false, true) => action_state.release(action),
+				_ => {}
+			}
+		}
+	}
+}
+
+/// Sent from clients to the server every [FixedUpdate],
+/// holding the inputs of the player they control.
+#[derive(Event, Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerInputSnapshot {
+	/// The player these inputs are for, checked against the sender
+	pub player: NetworkId,
+
+	/// The [GameClock] frame on the client when these inputs were pressed
+	pub tick: u32,
+
+	pub pressed: PressedInputs,
+}
+
+/// A player's [PressedInputs] by the [GameClock] frame they are for,
+/// at most [PREDICTION_HISTORY_FRAMES] of them.
+///
+/// On a client the local player's inputs are recorded as each frame is first simulated,
+/// and read back when it is re-simulated. On the server clients' inputs wait here
+/// until the frame they were pressed for.
+#[derive(Component, Debug, Default)]
+pub struct InputBuffer {
+	frames: BTreeMap<u32, PressedInputs>,
+
+	/// What the local player is pressing right now, recorded for the next new frame
+	live: PressedInputs,
+
+	/// The frame of the latest input applied, so that unreliable snapshots
+	/// arriving after it are ignored
+	last_applied: Option<u32>,
+}
+
+impl InputBuffer {
+	/// The inputs recorded for `frame`, recording [InputBuffer::live] if there are none yet.
+	/// Returns whether `frame` is new.
+	fn record(&mut self, frame: u32) -> (PressedInputs, bool) {
+		let live = self.live;
+		let is_new = !self.frames.contains_key(&frame);
+		let pressed = *self.frames.entry(frame).or_insert(live);
+		self.forget_before(frame.saturating_sub(PREDICTION_HISTORY_FRAMES as u32));
+		(pressed, is_new)
+	}
+
+	/// Keeps `pressed` until `frame` is simulated, unless it arrived too late or too early.
+	fn insert(&mut self, frame: u32, pressed: PressedInputs, current_frame: u32) {
+		if self.last_applied.is_some_and(|last| last >= frame) {
+			// stale or duplicate
+			return;
+		}
+		if frame > current_frame + PREDICTION_HISTORY_FRAMES as u32 {
+			trace!("Ignoring input for frame {}, which is too far ahead", frame);
+			return;
+		}
+		self.frames.insert(frame, pressed);
+	}
+
+	/// Takes the latest inputs for `frame` or before it, late ones are applied straight away
+	fn take(&mut self, frame: u32) -> Option<PressedInputs> {
+		let (applied, pressed) = self.frames.range(..=frame).next_back()?;
+		let (applied, pressed) = (*applied, *pressed);
+		self.last_applied = Some(applied);
+		self.forget_before(applied + 1);
+		Some(pressed)
+	}
+
+	fn forget_before(&mut self, frame: u32) {
+		self.frames = self.frames.split_off(&frame);
+	}
+}
+
+impl InputProcessingPlugin {
+	/// Inserts an [InputMap] on the player controlled by this instance,
+	/// so that only the local keyboard drives the local player.
+	fn attach_local_input_map(
+		players: Query<
+			(Entity, &NetworkId),
+			(With<ActionState<PlayerInput>>, Without<InputMap<PlayerInput>>),
+		>,
+		local_id: ClientID,
+		mut commands: Commands,
+	) {
+		for (player, id) in players.iter() {
+			if local_id.get() == Some(id.get_network_id()) {
+				debug!("Attaching local input map to {:?}", id);
+				commands
+					.entity(player)
+					.insert(PlayerInput::default_input_map());
+			}
+		}
+	}
+
+	/// Remembers what the local player is pressing, before a rollback
+	/// overwrites the [ActionState] with the inputs of the frames it re-simulates
+	fn sample_local_input(
+		mut players: Query<(&ActionState<PlayerInput>, &mut InputBuffer), With<InputMap<PlayerInput>>>,
+	) {
+		for (action_state, mut buffer) in players.iter_mut() {
+			buffer.live = PressedInputs::from_action_state(action_state);
+		}
+	}
+
+	/// Applies the local player's inputs for this frame, and sends them
+	/// to the server the first time the frame is simulated
+	fn send_local_input(
+		mut players: Query<
+			(&NetworkId, &mut ActionState<PlayerInput>, &mut InputBuffer),
+			With<InputMap<PlayerInput>>,
+		>,
+		game_clock: Res<GameClock>,
+		mut snapshots: EventWriter<PlayerInputSnapshot>,
+	) {
+		let frame = game_clock.frame();
+		for (id, mut action_state, mut buffer) in players.iter_mut() {
+			let (pressed, is_new) = buffer.record(frame);
+			pressed.apply_to(&mut action_state);
+			if is_new {
+				snapshots.send(PlayerInputSnapshot {
+					player: *id,
+					tick: frame,
+					pressed,
+				});
+			}
+		}
+	}
+
+	/// Buffers [PlayerInputSnapshot]s for the sender's player, rejecting input
+	/// for players the sender doesn't own, and applies those for this frame.
+	fn apply_remote_input(
+		mut snapshots: EventReader<FromClient<PlayerInputSnapshot>>,
+		mut players: Query<(&NetworkId, &mut ActionState<PlayerInput>, &mut InputBuffer)>,
+		game_clock: Res<GameClock>,
+	) {
+		let frame = game_clock.frame();
+		for FromClient { client_id, event } in snapshots.read() {
+			if event.player.get_network_id() != *client_id {
+				warn!(
+					"Rejecting input from client {} for player {:?} they don't own",
+					client_id, event.player
+				);
+				continue;
+			}
+
+			let Some((_, _, mut buffer)) = players
+				.iter_mut()
+				.find(|(id, _, _)| **id == event.player)
+			else {
+				trace!("Received input for {:?}, who has no player yet", event.player);
+				continue;
+			};
+			buffer.insert(event.tick, event.pressed, frame);
 		}
+
+		// players keep pressing what they were until their next input arrives
+		for (_, mut action_state, mut buffer) in players.iter_mut() {
+			if let Some(pressed) = buffer.take(frame) {
+				pressed.apply_to(&mut action_state);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn pressed_inputs_round_trip() {
+		let mut action_state = ActionState::<PlayerInput>::default();
+		action_state.press(PlayerInput::Forward);
+		action_state.press(PlayerInput::Left);
+
+		let pressed = PressedInputs::from_action_state(&action_state);
+		assert!(pressed.is_pressed(PlayerInput::Forward));
+		assert!(!pressed.is_pressed(PlayerInput::Backward));
+
+		let mut remote = ActionState::<PlayerInput>::default();
+		remote.press(PlayerInput::Right);
+		pressed.apply_to(&mut remote);
+
+		assert_eq!(PressedInputs::from_action_state(&remote), pressed);
+	}
+
+	fn pressing(action: PlayerInput) -> PressedInputs {
+		let mut action_state = ActionState::<PlayerInput>::default();
+		action_state.press(action);
+		PressedInputs::from_action_state(&action_state)
+	}
+
+	#[test]
+	fn resimulated_frames_keep_their_inputs() {
+		let mut buffer = InputBuffer::default();
+		buffer.live = pressing(PlayerInput::Forward);
+		assert_eq!(buffer.record(10), (pressing(PlayerInput::Forward), true));
+		buffer.live = pressing(PlayerInput::Left);
+		assert_eq!(buffer.record(11), (pressing(PlayerInput::Left), true));
+
+		// rolled back to frame 10
+		assert_eq!(buffer.record(10), (pressing(PlayerInput::Forward), false));
+		assert_eq!(buffer.record(11), (pressing(PlayerInput::Left), false));
+	}
+
+	#[test]
+	fn inputs_wait_for_their_frame() {
+		let mut buffer = InputBuffer::default();
+		buffer.insert(14, pressing(PlayerInput::Forward), 10);
+		buffer.insert(12, pressing(PlayerInput::Left), 10);
+		assert_eq!(buffer.take(10), None);
+		assert_eq!(buffer.take(12), Some(pressing(PlayerInput::Left)));
+		assert_eq!(buffer.take(13), None, "Keeps pressing Left");
+
+		// frame 13 arrives late, and is overtaken by frame 14
+		buffer.insert(13, pressing(PlayerInput::Right), 13);
+		assert_eq!(buffer.take(14), Some(pressing(PlayerInput::Forward)));
+		// and arrives again after that
+		buffer.insert(13, pressing(PlayerInput::Right), 14);
+		assert_eq!(buffer.take(15), None, "Stale inputs are ignored");
 	}
 }
@@ -41,10 +41,23 @@ impl PlayerMovementPlugin {
 	}
 
 	/// Adds the [IntendedVelocity] component to players.
+	///
+	/// Clients only know the inputs of their own player,
+	/// so the rest keep the [IntendedVelocity] replicated from the server.
 	pub(super) fn calculate_intended_velocity(
-		mut players: Query<(&mut IntendedVelocity, &ActionState<PlayerInput>)>,
+		mut players: Query<(
+			&mut IntendedVelocity,
+			&ActionState<PlayerInput>,
+			Has<InputMap<PlayerInput>>,
+		)>,
+		config: Option<Res<NetcodeConfig>>,
 	) {
-		for (mut player, inputs) in players.iter_mut() {
+		let is_authoritative = config.is_some_and(|config| config.is_authoritative());
+		for (mut player, inputs, is_local) in players.iter_mut() {
+			if !is_local && !is_authoritative {
+				continue;
+			}
+
 			let mut intended_velocity = IntendedVelocity::default();
 
 			if inputs.pressed(PlayerInput::Forward) {
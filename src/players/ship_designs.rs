@@ -0,0 +1,357 @@
+//! Ship designs are the layout of a player's ship, saved to files
+//! that can be shared and reviewed in git, e.g. `assets/ships/default.ship.ron`.
+//!
+//! Files ending in `.ron` are human readable [ron], anything else is the compact [bincode] format.
+//! Both start with the [ShipDesign::VERSION] they were saved with, and designs saved
+//! with older versions are upgraded when loaded, see [ShipDesign::migrate].
+//!
+//! Players fly the design passed with `--ship <FILE>`, which is sent to the server
+//! as a [SubmitShipDesign] once connected. The server keeps every player's design
+//! in [ShipDesigns], and rebuilds their ship from it. Designs that fail
+//! [crate::players::validate_ship] are answered with a [ShipRejected] instead.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+	blocks::manual_builder::{Facing, RelativePixel},
+	players::{PlayerBlueprintComponent, ShipLimits, ShipRejected},
+	prelude::*,
+};
+
+pub(super) struct ShipDesignsPlugin;
+
+impl Plugin for ShipDesignsPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<ShipDesigns>()
+			.init_resource::<ShipLimits>()
+			.register_replicated_type("SubmitShipDesign")
+			.add_client_event::<SubmitShipDesign>(EventType::Ordered)
+			.register_replicated_type("ShipRejected")
+			.add_server_event::<ShipRejected>(EventType::Ordered)
+			.add_systems(Update, Self::log_ship_rejections.in_set(Client))
+			.add_systems(
+				OnEnter(GlobalGameStates::InGame),
+				Self::load_local_ship_design,
+			)
+			.add_systems(
+				OnEnter(ClientConnectionState::Connected),
+				Self::submit_local_ship_design.run_if(NetcodeConfig::not_headless()),
+			)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::forget_ship_designs);
+	}
+}
+
+/// The layout of a ship, see [PlayerBlueprintComponent::from_design]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShipDesign {
+	/// [ShipDesign::VERSION] when loaded, the file may have been saved with an older one
+	pub version: u32,
+	pub blocks: Vec<DesignedBlock>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DesignedBlock {
+	pub position: RelativePixel,
+	pub facing: Facing,
+	pub kind: DesignedBlockKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DesignedBlockKind {
+	Structure(StructureBlockKind),
+	Thruster,
+	/// Every ship needs exactly one
+	Camera,
+}
+
+impl DesignedBlock {
+	fn new(kind: DesignedBlockKind, x: i32, y: i32, z: i32, facing: Facing) -> Self {
+		Self {
+			position: RelativePixel::new(x, y, z),
+			facing,
+			kind,
+		}
+	}
+}
+
+impl Default for ShipDesign {
+	/// The ship everybody flies unless they bring their own,
+	/// also saved as `assets/ships/default.ship.ron`
+	fn default() -> Self {
+		use DesignedBlockKind::{Camera, Thruster};
+		use Facing::{Forwards, Left, Right};
+		const ALUMINUM: DesignedBlockKind = DesignedBlockKind::Structure(StructureBlockKind::Aluminum);
+
+		Self {
+			version: Self::VERSION,
+			blocks: vec![
+				DesignedBlock::new(ALUMINUM, 0, 0, 0, Forwards), // center
+				DesignedBlock::new(ALUMINUM, 0, 0, -1, Forwards), // front
+				DesignedBlock::new(ALUMINUM, 0, 0, 1, Forwards),
+				DesignedBlock::new(ALUMINUM, 0, 0, 2, Forwards),
+				DesignedBlock::new(ALUMINUM, 1, 0, 2, Forwards),
+				DesignedBlock::new(ALUMINUM, -1, 0, 2, Forwards),
+				DesignedBlock::new(ALUMINUM, 1, 0, 3, Forwards),
+				DesignedBlock::new(ALUMINUM, -1, 0, 3, Forwards),
+				DesignedBlock::new(Thruster, -1, 0, 0, Left),
+				DesignedBlock::new(Thruster, 1, 0, 0, Right),
+				DesignedBlock::new(Thruster, -1, 0, 1, Left),
+				DesignedBlock::new(Thruster, 1, 0, 1, Right),
+				DesignedBlock::new(Thruster, -2, 0, 2, Left),
+				DesignedBlock::new(Thruster, 2, 0, 2, Right),
+				DesignedBlock::new(Thruster, -2, 0, 3, Left),
+				DesignedBlock::new(Thruster, 2, 0, 3, Right),
+				DesignedBlock::new(Camera, 0, 1, 0, Forwards),
+			],
+		}
+	}
+}
+
+/// A saved design, before it is migrated
+enum Encoded<'a> {
+	Ron(&'a str),
+	Binary(&'a [u8]),
+}
+
+impl Encoded<'_> {
+	fn decode<T: DeserializeOwned>(&self) -> Result<T, ShipDesignError> {
+		match self {
+			Self::Ron(text) => ron::from_str(text).map_err(ShipDesignError::Ron),
+			Self::Binary(bytes) => bincode::deserialize(bytes).map_err(ShipDesignError::Binary),
+		}
+	}
+
+	/// Only the version, which every version of the format starts with
+	fn version(&self) -> Result<u32, ShipDesignError> {
+		#[derive(Deserialize)]
+		struct Header {
+			version: u32,
+		}
+
+		match self {
+			Self::Ron(_) => self.decode::<Header>().map(|header| header.version),
+			// bincode ignores the rest of the bytes
+			Self::Binary(_) => self.decode::<u32>(),
+		}
+	}
+}
+
+impl ShipDesign {
+	/// Bumped whenever the format changes, see [ShipDesign::migrate]
+	pub const VERSION: u32 = 1;
+
+	pub fn from_ron(text: &str) -> Result<Self, ShipDesignError> {
+		Self::decode(Encoded::Ron(text))
+	}
+
+	pub fn from_binary(bytes: &[u8]) -> Result<Self, ShipDesignError> {
+		Self::decode(Encoded::Binary(bytes))
+	}
+
+	pub fn to_ron(&self) -> String {
+		let config = ron::ser::PrettyConfig::new().indentor("\t".into());
+		ron::ser::to_string_pretty(self, config).expect("Ship designs can be serialized")
+	}
+
+	pub fn to_binary(&self) -> Vec<u8> {
+		bincode::serialize(self).expect("Ship designs can be serialized")
+	}
+
+	/// Loads RON if `path` ends in `.ron`, otherwise the binary format
+	pub fn load(path: &Path) -> Result<Self, ShipDesignError> {
+		let io_err = |err| ShipDesignError::Io(path.to_path_buf(), err);
+		if Self::is_ron(path) {
+			Self::from_ron(&std::fs::read_to_string(path).map_err(io_err)?)
+		} else {
+			Self::from_binary(&std::fs::read(path).map_err(io_err)?)
+		}
+	}
+
+	/// Saves RON if `path` ends in `.ron`, otherwise the binary format
+	pub fn save(&self, path: &Path) -> Result<(), ShipDesignError> {
+		let contents = if Self::is_ron(path) {
+			self.to_ron().into_bytes()
+		} else {
+			self.to_binary()
+		};
+		std::fs::write(path, contents).map_err(|err| ShipDesignError::Io(path.to_path_buf(), err))
+	}
+
+	fn is_ron(path: &Path) -> bool {
+		path.extension().is_some_and(|extension| extension == "ron")
+	}
+
+	fn decode(encoded: Encoded) -> Result<Self, ShipDesignError> {
+		let version = encoded.version()?;
+		let design = Self::migrate(version, encoded)?;
+		Ok(Self {
+			version: Self::VERSION,
+			..design
+		})
+	}
+
+	/// The migration hook, reads a design saved with any `version`.
+	///
+	/// When the format changes, bump [ShipDesign::VERSION], move the old types
+	/// into a module named after the old version and add an arm here
+	/// that decodes them and converts them to the current ones.
+	fn migrate(version: u32, encoded: Encoded) -> Result<Self, ShipDesignError> {
+		match version {
+			Self::VERSION => encoded.decode(),
+			version => Err(ShipDesignError::UnknownVersion(version)),
+		}
+	}
+}
+
+/// Why a [ShipDesign] couldn't be loaded or saved
+#[derive(Debug)]
+pub enum ShipDesignError {
+	Io(PathBuf, std::io::Error),
+	Ron(ron::error::SpannedError),
+	Binary(bincode::Error),
+	UnknownVersion(u32),
+}
+
+impl std::fmt::Display for ShipDesignError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(path, err) => write!(f, "Couldn't access ship design {:?}: {}", path, err),
+			Self::Ron(err) => write!(f, "Couldn't parse ship design: {}", err),
+			Self::Binary(err) => write!(f, "Couldn't decode ship design: {}", err),
+			Self::UnknownVersion(version) => write!(
+				f,
+				"Ship design has version {}, but only versions up to {} can be loaded",
+				version,
+				ShipDesign::VERSION
+			),
+		}
+	}
+}
+
+impl std::error::Error for ShipDesignError {}
+
+/// Sent by clients once connected, and by the host for its own player
+#[derive(Event, Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitShipDesign(pub ShipDesign);
+
+/// The design the local player flies, loaded when entering [GlobalGameStates::InGame]
+#[derive(Resource, Debug, Clone)]
+pub struct LocalShipDesign(pub ShipDesign);
+
+/// Every player's [ShipDesign], server only.
+/// Only designs that pass [crate::players::validate_ship] are kept.
+#[derive(Resource, Debug, Default)]
+pub struct ShipDesigns {
+	designs: HashMap<ClientId, ShipDesign>,
+	default: ShipDesign,
+}
+
+impl ShipDesigns {
+	/// [ShipDesign::default] until the player submits their own
+	pub fn get(&self, id: ClientId) -> &ShipDesign {
+		self.designs.get(&id).unwrap_or(&self.default)
+	}
+
+	/// Builds `id`'s ship, with the same block IDs every time
+	pub fn blueprint(&self, id: ClientId, rng: &GameRng) -> PlayerBlueprintComponent {
+		PlayerBlueprintComponent::from_design(self.get(id), &mut rng.block_ids(id))
+			.expect("Only designs that ships can be built from are kept")
+	}
+
+	pub(super) fn insert(&mut self, id: ClientId, design: ShipDesign) {
+		self.designs.insert(id, design);
+	}
+
+	pub(super) fn remove(&mut self, id: ClientId) {
+		self.designs.remove(&id);
+	}
+}
+
+impl ShipDesignsPlugin {
+	fn load_local_ship_design(config: Res<NetcodeConfig>, mut commands: Commands) {
+		let design = match config.ship_design() {
+			Some(path) => ShipDesign::load(path).unwrap_or_else(|err| {
+				error!("{}, flying the default ship instead", err);
+				ShipDesign::default()
+			}),
+			None => ShipDesign::default(),
+		};
+		commands.insert_resource(LocalShipDesign(design));
+	}
+
+	fn submit_local_ship_design(
+		design: Res<LocalShipDesign>,
+		mut submissions: EventWriter<SubmitShipDesign>,
+	) {
+		debug!(
+			"Submitting a ship design of {} blocks",
+			design.0.blocks.len()
+		);
+		submissions.send(SubmitShipDesign(design.0.clone()));
+	}
+
+	fn log_ship_rejections(mut rejections: EventReader<ShipRejected>) {
+		for ShipRejected(errors) in rejections.read() {
+			warn!("The server rejected your ship design, so your ship wasn't changed:");
+			for err in errors {
+				warn!("- {}", err);
+			}
+		}
+	}
+
+	fn forget_ship_designs(mut commands: Commands) {
+		commands.remove_resource::<LocalShipDesign>();
+		commands.insert_resource(ShipDesigns::default());
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn default_design_file_is_up_to_date() {
+		let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/ships/default.ship.ron");
+		assert_eq!(ShipDesign::load(&path).unwrap(), ShipDesign::default());
+	}
+
+	#[test]
+	fn save_and_load() {
+		let design = ShipDesign::default();
+		for extension in ["ship.ron", "ship"] {
+			let path = std::env::temp_dir().join(format!(
+				"space_craft-test-{}.{}",
+				random::<u64>(),
+				extension
+			));
+			design.save(&path).unwrap();
+			assert_eq!(ShipDesign::load(&path).unwrap(), design);
+		}
+	}
+
+	#[test]
+	fn unknown_versions_are_rejected() {
+		let design = ShipDesign {
+			version: ShipDesign::VERSION + 1,
+			..default()
+		};
+		assert!(matches!(
+			ShipDesign::from_ron(&design.to_ron()),
+			Err(ShipDesignError::UnknownVersion(version)) if version == ShipDesign::VERSION + 1
+		));
+		assert!(matches!(
+			ShipDesign::from_binary(&design.to_binary()),
+			Err(ShipDesignError::UnknownVersion(_))
+		));
+	}
+
+	#[test]
+	fn blueprint_round_trip() {
+		let design = ShipDesign::default();
+		let blueprint =
+			PlayerBlueprintComponent::from_design(&design, &mut GameRng::default().block_ids(SERVER_ID))
+				.unwrap();
+		assert_eq!(blueprint.to_design().unwrap(), design);
+	}
+}
@@ -0,0 +1,286 @@
+//! Checks a [PlayerBlueprintComponent] before the server builds it, see [validate_ship].
+//!
+//! Ships submitted by players that fail are not expanded, instead the
+//! [ShipValidationError]s are sent back to the player in a [ShipRejected].
+
+use crate::{
+	blocks::manual_builder::RelativePixel,
+	players::{DesignedBlockKind, PlayerBlueprintComponent},
+	prelude::*,
+};
+
+/// How many blocks of each type a ship may have, server only
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct ShipLimits {
+	pub max_structures: usize,
+	pub max_thrusters: usize,
+}
+
+impl ShipLimits {
+	pub const DEFAULT: Self = Self {
+		max_structures: 256,
+		max_thrusters: 64,
+	};
+}
+
+impl Default for ShipLimits {
+	fn default() -> Self {
+		Self::DEFAULT
+	}
+}
+
+/// Why a ship can't be built
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ShipValidationError {
+	/// Ships need exactly one camera block, but this many were designed
+	CameraCount(usize),
+
+	/// The block's transform has a NaN or infinite component
+	NonFinite(BlockId),
+
+	/// The block isn't on a [RelativePixel], or isn't facing one of the [Facing](crate::blocks::manual_builder::Facing)s
+	OffGrid(BlockId),
+
+	/// More than one block is in `cell`
+	Overlapping {
+		cell: RelativePixel,
+		blocks: Vec<BlockId>,
+	},
+
+	/// The blocks aren't connected to the camera block through neighbouring blocks
+	Detached(Vec<BlockId>),
+
+	TooManyStructures {
+		count: usize,
+		limit: usize,
+	},
+
+	TooManyThrusters {
+		count: usize,
+		limit: usize,
+	},
+}
+
+impl std::fmt::Display for ShipValidationError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::CameraCount(count) => write!(
+				f,
+				"Ships need exactly one camera block, but this one has {}",
+				count
+			),
+			Self::NonFinite(id) => write!(f, "Block {:?} has an invalid position", id),
+			Self::OffGrid(id) => write!(f, "Block {:?} isn't aligned to the block grid", id),
+			Self::Overlapping { cell, blocks } => {
+				write!(f, "{} blocks overlap at {}", blocks.len(), cell.0)
+			}
+			Self::Detached(blocks) => write!(
+				f,
+				"{} blocks aren't attached to the camera block",
+				blocks.len()
+			),
+			Self::TooManyStructures { count, limit } => write!(
+				f,
+				"Ships may have up to {} structure blocks, but this one has {}",
+				limit, count
+			),
+			Self::TooManyThrusters { count, limit } => write!(
+				f,
+				"Ships may have up to {} thrusters, but this one has {}",
+				limit, count
+			),
+		}
+	}
+}
+
+impl std::error::Error for ShipValidationError {}
+
+/// Sent by the server to a player whose submitted ship failed [validate_ship]
+#[derive(Event, Debug, Clone, Serialize, Deserialize)]
+pub struct ShipRejected(pub Vec<ShipValidationError>);
+
+/// Every reason `ship` can't be built, in the order of its blocks
+pub fn validate_ship(
+	ship: &PlayerBlueprintComponent,
+	limits: &ShipLimits,
+) -> Result<(), Vec<ShipValidationError>> {
+	let mut errors = Vec::new();
+
+	let structures = ship.structure_children.len();
+	if structures > limits.max_structures {
+		errors.push(ShipValidationError::TooManyStructures {
+			count: structures,
+			limit: limits.max_structures,
+		});
+	}
+	let thrusters = ship.thruster_children.len();
+	if thrusters > limits.max_thrusters {
+		errors.push(ShipValidationError::TooManyThrusters {
+			count: thrusters,
+			limit: limits.max_thrusters,
+		});
+	}
+
+	// which blocks are in each cell, cells in the order they are first used
+	let mut cells: HashMap<RelativePixel, Vec<BlockId>> = HashMap::new();
+	let mut cell_order = Vec::new();
+	let mut camera = None;
+	for (id, block) in ship.designed_blocks() {
+		match block {
+			Ok(block) => {
+				if block.kind == DesignedBlockKind::Camera {
+					camera = Some(block.position);
+				}
+				let blocks = cells.entry(block.position).or_default();
+				if blocks.is_empty() {
+					cell_order.push(block.position);
+				}
+				blocks.push(id);
+			}
+			Err(err) => errors.push(err),
+		}
+	}
+
+	for cell in &cell_order {
+		if cells[cell].len() > 1 {
+			errors.push(ShipValidationError::Overlapping {
+				cell: *cell,
+				blocks: cells[cell].clone(),
+			});
+		}
+	}
+
+	// a broken camera block was already reported, and there is nothing to be connected to
+	if let Some(camera) = camera {
+		let mut attached = HashSet::new();
+		let mut unvisited = vec![camera];
+		while let Some(cell) = unvisited.pop() {
+			if cells.contains_key(&cell) && attached.insert(cell) {
+				unvisited.extend(cell.neighbours());
+			}
+		}
+
+		let detached: Vec<BlockId> = cell_order
+			.iter()
+			.filter(|cell| !attached.contains(*cell))
+			.flat_map(|cell| cells[cell].iter().copied())
+			.collect();
+		if !detached.is_empty() {
+			errors.push(ShipValidationError::Detached(detached));
+		}
+	}
+
+	if errors.is_empty() {
+		Ok(())
+	} else {
+		Err(errors)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::players::{DesignedBlock, ShipDesign};
+
+	use super::*;
+
+	fn build(design: &ShipDesign) -> PlayerBlueprintComponent {
+		PlayerBlueprintComponent::from_design(design, &mut GameRng::default().block_ids(SERVER_ID))
+			.unwrap()
+	}
+
+	fn add_block(design: &mut ShipDesign, kind: DesignedBlockKind, x: i32, y: i32, z: i32) {
+		design.blocks.push(DesignedBlock {
+			position: RelativePixel::new(x, y, z),
+			facing: crate::blocks::manual_builder::Facing::Forwards,
+			kind,
+		});
+	}
+
+	const ALUMINUM: DesignedBlockKind = DesignedBlockKind::Structure(StructureBlockKind::Aluminum);
+
+	#[test]
+	fn default_ship_is_valid() {
+		let ship = build(&ShipDesign::default());
+		assert_eq!(validate_ship(&ship, &ShipLimits::DEFAULT), Ok(()));
+	}
+
+	#[test]
+	fn ships_need_one_camera() {
+		let mut design = ShipDesign::default();
+		design
+			.blocks
+			.retain(|block| block.kind != DesignedBlockKind::Camera);
+		assert!(matches!(
+			PlayerBlueprintComponent::from_design(&design, &mut GameRng::default().block_ids(SERVER_ID)),
+			Err(ShipValidationError::CameraCount(0))
+		));
+	}
+
+	#[test]
+	fn overlapping_blocks() {
+		let mut design = ShipDesign::default();
+		add_block(&mut design, ALUMINUM, 0, 0, 0);
+		let ship = build(&design);
+
+		let errors = validate_ship(&ship, &ShipLimits::DEFAULT).unwrap_err();
+		assert!(matches!(
+			errors.as_slice(),
+			[ShipValidationError::Overlapping { cell, blocks }] if *cell == RelativePixel::new(0, 0, 0) && blocks.len() == 2
+		));
+	}
+
+	#[test]
+	fn detached_blocks() {
+		let mut design = ShipDesign::default();
+		add_block(&mut design, ALUMINUM, 5, 0, 0);
+		add_block(&mut design, ALUMINUM, 6, 0, 0);
+		// touching the ship only along an edge
+		add_block(&mut design, ALUMINUM, 2, 1, 0);
+		let ship = build(&design);
+
+		let errors = validate_ship(&ship, &ShipLimits::DEFAULT).unwrap_err();
+		assert!(matches!(
+			errors.as_slice(),
+			[ShipValidationError::Detached(blocks)] if blocks.len() == 3
+		));
+	}
+
+	#[test]
+	fn per_type_limits() {
+		let mut design = ShipDesign::default();
+		for z in 4..10 {
+			add_block(&mut design, DesignedBlockKind::Thruster, 1, 0, z);
+		}
+		let ship = build(&design);
+		let limits = ShipLimits {
+			max_structures: 8,
+			max_thrusters: 10,
+		};
+
+		assert_eq!(
+			validate_ship(&ship, &limits),
+			Err(vec![ShipValidationError::TooManyThrusters {
+				count: 14,
+				limit: 10
+			}])
+		);
+	}
+
+	#[test]
+	fn nan_and_off_grid_transforms() {
+		let mut ship = build(&ShipDesign::default());
+		ship.structure_children[1].transform.translation.x = f32::NAN;
+		ship.thruster_children[0].transform.rotation = Quat::from_rotation_y(0.3);
+		let nan = ship.structure_children[1].get_block_id();
+		let rotated = ship.thruster_children[0].get_block_id();
+
+		let errors = validate_ship(&ship, &ShipLimits::DEFAULT).unwrap_err();
+		assert_eq!(
+			errors,
+			vec![
+				ShipValidationError::NonFinite(nan),
+				ShipValidationError::OffGrid(rotated),
+			]
+		);
+	}
+}
@@ -6,30 +6,103 @@ pub struct SpawnPointsPlugin;
 
 impl Plugin for SpawnPointsPlugin {
 	fn build(&self, app: &mut App) {
-		replicate_marked!(app, blueprint::SpawnPointBlueprintComponent);
-
 		app
-			// .replicate_marked::<blueprint::SpawnPointBlueprintComponent>()
-			.register_type::<components::SpawnPoint>()
-			.register_type::<blueprint::SpawnPointBlueprintComponent>()
+			.register_networked_blueprint::<blueprint::SpawnPointBlueprintComponent>(
+				"SpawnPointBlueprintComponent",
+			)
+			.init_resource::<SpawnPointsConfig>()
+			.init_resource::<SpawnPointAllocator>()
 			.add_systems(Startup, Self::load_default_materials)
 			.add_systems(PostProcessCollisions, Self::filter_non_occupied_collisions)
-			.add_systems(
-				Blueprints,
-				blueprint::SpawnPointBlueprintBundle::expand_system.in_set(BlueprintExpansion::SpawnPoints),
-			)
 			.add_systems(
 				WorldCreation,
 				Self::creation_spawn_points.in_set(WorldCreationSet::SpawnPoints),
 			)
-			.add_systems(Update, Self::activate_local_spawn_points);
+			.add_systems(Update, Self::activate_local_spawn_points)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::despawn_spawn_points);
 	}
 }
 
 mod api {
 	use crate::prelude::*;
 
-	use super::blueprint::SpawnPointBlueprintComponent;
+	use super::blueprint::SpawnPointBlueprintBundle;
+	pub use super::blueprint::SpawnPointBlueprintComponent;
+
+	/// How spawn points are laid out around the world's center.
+	///
+	/// Spawn points are arranged in concentric shells, the first of which is
+	/// spawned with the world. Once every spawn point is occupied, the next shell
+	/// is spawned further out.
+	#[derive(Resource, Debug, Clone, PartialEq, clap::Args)]
+	pub struct SpawnPointsConfig {
+		/// Distance from the world's center to the first shell of spawn points.
+		#[arg(long, default_value_t = SpawnPointsConfig::DEFAULT.first_shell_radius)]
+		pub first_shell_radius: f32,
+
+		/// Distance between each shell of spawn points.
+		#[arg(long, default_value_t = SpawnPointsConfig::DEFAULT.shell_spacing)]
+		pub shell_spacing: f32,
+
+		/// Maximum number of shells of spawn points, once all are full no more players can spawn.
+		#[arg(long, default_value_t = SpawnPointsConfig::DEFAULT.max_shells)]
+		pub max_shells: u32,
+	}
+
+	impl SpawnPointsConfig {
+		pub const DEFAULT: Self = Self {
+			first_shell_radius: SpawnPointBlueprintBundle::DEFAULT_SIZE * 4.0,
+			shell_spacing: SpawnPointBlueprintBundle::DEFAULT_SIZE * 4.0,
+			max_shells: 8,
+		};
+
+		pub fn shell_radius(&self, shell: u32) -> f32 {
+			self.first_shell_radius + shell as f32 * self.shell_spacing
+		}
+
+		/// Positions of every spawn point in `shell`, `0` being the shell spawned with the world.
+		///
+		/// Shells further out get more latitude strips, and more spawn points per strip,
+		/// so that spawn points stay roughly as far apart as in the first shell.
+		pub fn shell_layout(&self, shell: u32) -> Vec<Vec3> {
+			let radius = self.shell_radius(shell);
+			// 2 for the first shell, so 5 total layers
+			let num_strips_magnitude = ((2.0 * radius / self.first_shell_radius).round() as isize).max(2);
+
+			let mut positions = Vec::new();
+			for strip_height_n in -num_strips_magnitude..=num_strips_magnitude {
+				// altitude
+				let phi = strip_height_n as f32 / num_strips_magnitude as f32 * TAU / 4.;
+
+				// rotations along xz axis, rounded down to a multiple of 4 so every strip
+				// is symmetric, and a single point at the poles
+				let num_thetas = ((num_strips_magnitude as f32 * phi.cos()).floor() as usize * 4).max(1);
+
+				for theta_n in 0..num_thetas {
+					let theta = theta_n as f32 * TAU / num_thetas as f32;
+					positions.push(vec3_polar(theta, phi) * radius);
+				}
+			}
+			positions
+		}
+	}
+
+	impl Default for SpawnPointsConfig {
+		fn default() -> Self {
+			Self::DEFAULT
+		}
+	}
+
+	/// Keeps track of how many shells of spawn points have been spawned,
+	/// see [SpawnPointsConfig].
+	#[derive(Resource, Debug, Default)]
+	pub struct SpawnPointAllocator {
+		pub(super) shells: u32,
+
+		/// Spawn points spawned this frame that can't be queried yet,
+		/// but can already be handed out
+		unclaimed: Vec<(Entity, Transform)>,
+	}
 
 	#[derive(SystemParam)]
 	// #[system_param(mutable)]
@@ -38,17 +111,23 @@ mod api {
 			'w,
 			's,
 			(
+				Entity,
 				&'static mut SpawnPointBlueprintComponent,
 				&'static Transform,
 			),
 		>,
+		terrain: Query<'w, 's, (&'static Transform, &'static TerrainStructureBlueprint)>,
+		allocator: ResMut<'w, SpawnPointAllocator>,
+		config: Res<'w, SpawnPointsConfig>,
 		state: Res<'w, NetcodeConfig>,
+		commands: Commands<'w, 's>,
 	}
 
 	impl AvailableSpawnPoints<'_, '_> {
 		/// Returns a valid spawn location, handling side effects.
 		///
-		/// Maybe: Handle spawning a new spawn location in the future?
+		/// If every spawn point is occupied, spawns the next shell of spawn points.
+		/// Returns [None] once [SpawnPointsConfig::max_shells] are full.
 		pub fn try_get_spawn_location(&mut self, player_occupying: ClientId) -> Option<Transform> {
 			if !self.state.is_authoritative() {
 				error!(
@@ -57,18 +136,88 @@ mod api {
 				);
 			}
 
+			let spawn_points = &self.spawn_points;
+			self
+				.allocator
+				.unclaimed
+				.retain(|(spawn_point, _)| !spawn_points.contains(*spawn_point));
+
 			let mut available_points = self
 				.spawn_points
 				.iter_mut()
-				.filter(|(sp, _)| sp.get_occupation().is_none());
+				.filter(|(_, sp, _)| sp.get_occupation().is_none());
 
-			let Some((mut spawn_point, transform)) = available_points.next() else {
-				return None;
-			};
+			if let Some((_, mut spawn_point, transform)) = available_points.next() {
+				spawn_point.set_occupation(player_occupying);
+				return Some(transform.with_scale(Vec3::splat(1.0)));
+			}
 
-			spawn_point.set_occupation(player_occupying);
+			loop {
+				if let Some((spawn_point, transform)) = self.allocator.unclaimed.pop() {
+					self
+						.commands
+						.entity(spawn_point)
+						.insert(SpawnPointBlueprintComponent {
+							occupation: Some(player_occupying.raw()),
+						});
+					return Some(transform);
+				}
 
-			Some(transform.with_scale(Vec3::splat(1.0)))
+				if self.allocator.shells >= self.config.max_shells {
+					return None;
+				}
+				self.spawn_next_shell();
+			}
+		}
+
+		/// Spawns the next shell of spawn points, skipping any that would overlap terrain.
+		fn spawn_next_shell(&mut self) {
+			let shell = self.allocator.shells;
+			self.allocator.shells += 1;
+
+			let positions = self.config.shell_layout(shell);
+			let num_positions = positions.len();
+			let mut spawned = 0;
+			for pos in positions {
+				if self.overlaps_terrain(pos) {
+					continue;
+				}
+				let transform = Transform::from_translation(pos);
+				let spawn_point = self
+					.commands
+					.spawn(SpawnPointBlueprintBundle::new(transform, None))
+					.id();
+				self.allocator.unclaimed.push((spawn_point, transform));
+				spawned += 1;
+			}
+			// handed out from the end, so reverse to fill the shell in layout order
+			self.allocator.unclaimed.reverse();
+
+			info!(
+				"Spawned shell {} of spawn points, {} of {} clear of terrain",
+				shell, spawned, num_positions
+			);
+		}
+
+		fn overlaps_terrain(&self, pos: Vec3) -> bool {
+			self.terrain.iter().any(|(transform, terrain)| {
+				transform.translation.distance(pos)
+					< terrain.bounding_radius() + SpawnPointBlueprintBundle::DEFAULT_SIZE
+			})
+		}
+
+		/// Frees any spawn point occupied by `player`, so that it can be
+		/// handed out to the next player that joins.
+		/// Returns whether a spawn point was occupied by `player`.
+		pub fn release_spawn_location(&mut self, player: ClientId) -> bool {
+			let mut released = false;
+			for (_, mut spawn_point, _) in self.spawn_points.iter_mut() {
+				if spawn_point.get_occupation() == Some(player) {
+					spawn_point.clear_occupation();
+					released = true;
+				}
+			}
+			released
 		}
 	}
 }
@@ -79,7 +228,10 @@ mod systems {
 		prelude::*,
 	};
 
-	use super::{blueprint::SpawnPointBlueprintComponent, components::SpawnPoint, SpawnPointsPlugin};
+	use super::{
+		blueprint::SpawnPointBlueprintComponent, components::SpawnPoint, SpawnPointAllocator,
+		SpawnPointsConfig, SpawnPointsPlugin,
+	};
 
 	impl SpawnPointsPlugin {
 		pub(super) fn filter_non_occupied_collisions(
@@ -133,64 +285,38 @@ mod systems {
 			});
 		}
 
-		pub(super) fn creation_spawn_points(mut commands: Commands) {
+		pub(super) fn creation_spawn_points(
+			mut commands: Commands,
+			config: Res<SpawnPointsConfig>,
+			mut allocator: ResMut<SpawnPointAllocator>,
+		) {
 			debug!("Spawning initial spawn points");
 
-			const CIRCLE_RADIUS: f32 = SpawnPointBlueprintBundle::DEFAULT_SIZE * 4.0;
-			const NUM_STRIPS_MAGNITUDE: isize = 2; // 5 total layers
-
-			// altitudes
-			let phi_per_strip_n = |strip_height_n: isize| -> f32 {
-				match strip_height_n {
-					2 => TAU / 4.,
-					1 => TAU / 8.,
-					0 => 0.0,
-					-1 => -TAU / 8.,
-					-2 => -TAU / 4.,
-					_ => unreachable!(),
-				}
-			};
-
-			// rotations along xz axis
-			let num_thetas_per_strip_n = |strip_height_n: isize| -> usize {
-				match strip_height_n {
-					2 | -2 => 1,
-					1 | -1 => 4,
-					0 => 8,
-					_ => unreachable!(),
-				}
-			};
-
-			let thetas_per_strip_n = |strip_height_n: isize| -> Vec<f32> {
-				let num_thetas = num_thetas_per_strip_n(strip_height_n);
-				let mut thetas = Vec::with_capacity(num_thetas);
-				for theta_n in 0..num_thetas {
-					let theta = theta_n as f32 * TAU / num_thetas as f32;
-					thetas.push(theta);
-				}
-				thetas
-			};
-
-			let mut starting_positions = Vec::new();
-
-			for strip_height_n in -NUM_STRIPS_MAGNITUDE..=NUM_STRIPS_MAGNITUDE {
-				let phi = phi_per_strip_n(strip_height_n);
-				for theta in thetas_per_strip_n(strip_height_n) {
-					starting_positions.push(vec3_polar(theta, phi) * CIRCLE_RADIUS);
-				}
-			}
-
-			let spawn_points: Vec<SpawnPointBlueprintBundle> = starting_positions
-				.iter()
+			let spawn_points: Vec<SpawnPointBlueprintBundle> = config
+				.shell_layout(0)
+				.into_iter()
 				.map(|pos| {
-					let transform = Transform::from_translation(*pos);
+					let transform = Transform::from_translation(pos);
 					SpawnPointBlueprintBundle::new(transform, None)
 				})
 				.collect::<Vec<_>>();
+			allocator.shells = 1;
 
 			commands.spawn_batch(spawn_points);
 		}
 
+		/// Replicated spawn points are already despawned with the rest of the replicated world
+		pub(super) fn despawn_spawn_points(
+			spawn_points: Query<Entity, (With<SpawnPointBlueprintComponent>, Without<Replication>)>,
+			mut allocator: ResMut<SpawnPointAllocator>,
+			mut commands: Commands,
+		) {
+			for spawn_point in spawn_points.iter() {
+				commands.entity(spawn_point).despawn_recursive();
+			}
+			*allocator = SpawnPointAllocator::default();
+		}
+
 		pub(super) fn load_default_materials(mut materials: ResMut<Assets<StandardMaterial>>) {
 			materials.insert(
 				SpawnPointBlueprintComponent::DEFAULT_MATERIAL_HANDLE,
@@ -228,13 +354,13 @@ mod blueprint {
 		pub occupation: Option<u64>,
 	}
 
-	#[derive(Bundle, Deref)]
+	#[derive(Bundle, NetworkedBlueprintBundle)]
 	pub struct SpawnPointBlueprintBundle {
 		/// synced
 		transform: Transform,
 
 		/// synced
-		#[deref]
+		#[blueprint]
 		blueprint: SpawnPointBlueprintComponent,
 	}
 
@@ -276,6 +402,7 @@ mod bundle {
 		// spawn_point: SpawnPoint,
 		rigid_body: RigidBody,
 		collider: AsyncCollider,
+		replication: Replication,
 	}
 
 	impl SpawnPointBlueprintComponent {
@@ -322,9 +449,9 @@ mod bundle {
 
 	impl Blueprint for SpawnPointBlueprintComponent {
 		type Bundle = SpawnPointBundle;
-		type StampSystemParam<'w, 's> = MMA<'w>;
+		type StampSystemParam = MMA<'static>;
 
-		fn stamp(&self, mma: &mut Self::StampSystemParam<'_, '_>) -> Self::Bundle {
+		fn stamp(&self, mma: &mut SystemParamItem<'_, '_, Self::StampSystemParam>) -> Self::Bundle {
 			let SpawnPointBlueprintComponent { .. } = self;
 
 			SpawnPointBundle {
@@ -340,15 +467,10 @@ mod bundle {
 				// spawn_point: SpawnPoint::new(initial_occupation.map(ClientId::from_raw)),
 				rigid_body: RigidBody::Kinematic,
 				collider: AsyncCollider::default(),
+				replication: Replication,
 			}
 		}
 	}
-
-	impl NetworkedBlueprintBundle for SpawnPointBlueprintBundle {
-		type NetworkedBlueprintComponent = SpawnPointBlueprintComponent;
-
-		type SpawnSystemParam = ();
-	}
 }
 
 mod components {
@@ -380,5 +502,106 @@ mod components {
 		pub(super) fn set_occupation(&mut self, id: ClientId) {
 			self.occupation = Some(id.raw());
 		}
+
+		pub(super) fn clear_occupation(&mut self) {
+			self.occupation = None;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::ops::Range;
+
+	use crate::prelude::*;
+
+	use super::{
+		blueprint::SpawnPointBlueprintComponent, AvailableSpawnPoints, SpawnPointAllocator,
+		SpawnPointsConfig, SpawnPointsPlugin,
+	};
+
+	fn allocator_world(config: SpawnPointsConfig) -> World {
+		let mut world = World::new();
+		world.insert_resource(NetcodeConfig::new_hosting_machine_local(true));
+		world.insert_resource(config);
+		world.init_resource::<SpawnPointAllocator>();
+		world.run_system_once(SpawnPointsPlugin::creation_spawn_points);
+		world
+	}
+
+	/// Allocates a spawn point for every id in `ids`, all within the same frame
+	fn allocate(world: &mut World, ids: Range<u64>) -> Vec<Option<Transform>> {
+		world.run_system_once_with(
+			ids,
+			|In(ids): In<Range<u64>>, mut spawn_points: AvailableSpawnPoints| {
+				ids
+					.map(|id| spawn_points.try_get_spawn_location(ClientId::from_raw(id)))
+					.collect()
+			},
+		)
+	}
+
+	#[test]
+	fn first_shell_matches_initial_layout() {
+		let config = SpawnPointsConfig::DEFAULT;
+		let layout = config.shell_layout(0);
+
+		assert_eq!(layout.len(), 18);
+		for pos in layout {
+			assert!((pos.length() - config.first_shell_radius).abs() < 0.001);
+		}
+		assert!(config.shell_layout(1).len() > 18);
+	}
+
+	#[test]
+	fn grows_new_shells_when_full() {
+		let config = SpawnPointsConfig {
+			max_shells: 2,
+			..SpawnPointsConfig::DEFAULT
+		};
+		let first = config.shell_layout(0).len() as u64;
+		let second = config.shell_layout(1).len() as u64;
+		let mut world = allocator_world(config.clone());
+
+		// more players than the first shell fits
+		let spawns = allocate(&mut world, 0..first + 2);
+		assert!(spawns.iter().all(Option::is_some));
+		for spawn in spawns[first as usize..].iter().flatten() {
+			assert!((spawn.translation.length() - config.shell_radius(1)).abs() < 0.001);
+		}
+
+		let spawns = allocate(&mut world, first + 2..first + second);
+		assert!(spawns.iter().all(Option::is_some));
+		assert_eq!(allocate(&mut world, 1000..1001), vec![None]);
+
+		let mut spawn_points = world.query::<&SpawnPointBlueprintComponent>();
+		assert_eq!(
+			spawn_points
+				.iter(&world)
+				.filter(|sp| sp.occupation.is_some())
+				.count() as u64,
+			first + second
+		);
+	}
+
+	#[test]
+	fn new_shells_avoid_terrain() {
+		let config = SpawnPointsConfig::DEFAULT;
+		let first = config.shell_layout(0).len();
+		let second = config.shell_layout(1).len();
+		let blocked = config.shell_layout(1)[0];
+		let mut world = allocator_world(config);
+		world.spawn((
+			Transform::from_translation(blocked),
+			TerrainStructureBlueprint::default(),
+		));
+
+		allocate(&mut world, 0..first as u64 + 1);
+
+		let mut spawn_points = world.query_filtered::<&Transform, With<SpawnPointBlueprintComponent>>();
+		assert_eq!(spawn_points.iter(&world).count(), first + second - 1);
+		assert!(spawn_points
+			.iter(&world)
+			.all(|transform| transform.translation.distance(blocked) > 0.001));
 	}
 }
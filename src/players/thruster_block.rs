@@ -13,7 +13,7 @@ impl Plugin for ThrusterPlugin {
 			.register_type::<Thruster>()
 			.add_systems(
 				Blueprints,
-				Self::spawn_thruster_visuals.in_set(BlueprintExpansion::ThrusterBlocks),
+				Self::spawn_thruster_visuals.in_set(BlueprintsSet::Expanded),
 			)
 			.add_systems(
 				GameLogic,
@@ -65,44 +65,17 @@ mod bundle {
 
 	use crate::prelude::*;
 
-	use super::{blueprint::ThrusterBlockBlueprint, Thruster};
+	use super::Thruster;
 
 	/// Thruster that is spawned into the world
 	#[derive(Bundle)]
 	pub struct ThrusterBlockBundle {
-		pbr: PbrBundle,
-		collider: AsyncCollider,
+		pub(super) pbr: PbrBundle,
+		pub(super) collider: AsyncCollider,
 		// body: RigidBody,
-		name: Name,
-		thruster: Thruster,
-		internal_force: InternalForce,
-	}
-
-	impl Blueprint for BlockBlueprint<ThrusterBlockBlueprint> {
-		type Bundle = ThrusterBlockBundle;
-		type StampSystemParam<'w, 's> = MMA<'w>;
-
-		fn stamp(&self, mma: &mut Self::StampSystemParam<'_, '_>) -> Self::Bundle {
-			let BlockBlueprint {
-				transform,
-				mesh,
-				material,
-				specific_marker,
-			} = self;
-			Self::Bundle {
-				pbr: PbrBundle {
-					transform: *transform,
-					mesh: mesh.clone().into_mesh(mma),
-					material: material.clone().into_material(&mut mma.mats),
-					..default()
-				},
-				collider: AsyncCollider(ComputedCollider::ConvexHull),
-				// body: RigidBody::Dynamic,
-				name: Name::new("ThrusterBlock"),
-				thruster: specific_marker.clone().into(),
-				internal_force: InternalForce(Vec3::Z),
-			}
-		}
+		pub(super) name: Name,
+		pub(super) thruster: Thruster,
+		pub(super) internal_force: InternalForce,
 	}
 }
 
@@ -267,23 +240,37 @@ mod systems {
 }
 
 mod blueprint {
+	use bevy_xpbd3d_parenting::InternalForce;
+
 	use crate::prelude::*;
 
-	use super::Thruster;
+	use super::{bundle::ThrusterBlockBundle, Thruster};
 
 	/// Builder for [ThrusterBlockBundle].
 	///
 	/// Will spawn a particle emitter as a child
-	#[derive(Debug, Reflect, Serialize, Deserialize, Clone)]
+	#[derive(Debug, Reflect, Serialize, Deserialize, Clone, Blueprint)]
+	#[blueprint(
+		for = BlockBlueprint<ThrusterBlockBlueprint>,
+		bundle = ThrusterBlockBundle,
+		param(mma: MMA<'static>),
+		stamp(
+			pbr = self.pbr_bundle(mma),
+			collider = AsyncCollider(ComputedCollider::ConvexHull),
+			name = Name::new("ThrusterBlock"),
+			thruster = self.specific_marker.clone().into(),
+			internal_force = InternalForce(Vec3::Z),
+		)
+	)]
 	pub struct ThrusterBlockBlueprint {
 		id: BlockId,
 		strength: f32,
 	}
 
 	impl ThrusterBlockBlueprint {
-		pub fn new() -> Self {
+		pub fn new(rng: &mut impl Rng) -> Self {
 			Self {
-				id: BlockId::random(),
+				id: BlockId::random(rng),
 				strength: 10.,
 			}
 		}
@@ -309,6 +296,7 @@ mod blueprint {
 		pub fn new_thruster(
 			location: impl Into<manual_builder::RelativePixel>,
 			facing: impl Into<Quat>,
+			rng: &mut impl Rng,
 		) -> Self {
 			let rotation = facing.into();
 			BlockBlueprint {
@@ -322,7 +310,7 @@ mod blueprint {
 					size: Vec3::splat(PIXEL_SIZE / 2.),
 				},
 				material: OptimizableMaterial::OpaqueColour(Color::RED),
-				specific_marker: ThrusterBlockBlueprint::new(),
+				specific_marker: ThrusterBlockBlueprint::new(rng),
 			}
 		}
 	}
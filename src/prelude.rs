@@ -38,7 +38,6 @@ pub use strum::IntoEnumIterator as _;
 // pub use uuid::Uuid;
 pub use clap::Parser;
 pub use rand::random;
-pub use rand::rngs::ThreadRng;
 pub use rand::Rng;
 pub use serde::de::DeserializeOwned;
 pub use serde::{Deserialize, Serialize};
@@ -50,6 +49,7 @@ pub use crate::blocks::*;
 pub(crate) use crate::global;
 pub use crate::global::assets::*;
 pub use crate::global::blueprints::*;
+pub use crate::global::rng::*;
 pub use crate::global::*;
 
 pub use crate::netcode::*;
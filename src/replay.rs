@@ -0,0 +1,21 @@
+//! Recording sessions to a file with `space_craft server --record <FILE>`,
+//! and watching them again with `space_craft_replay <FILE>`.
+//!
+//! Only what the server decided is recorded, i.e. the networked blueprints and the inputs
+//! of every player. Everything else is simulated again, so a recording can only be replayed
+//! by a build that simulates the same way as the one that recorded it.
+
+pub use api::*;
+
+mod playback;
+mod recording;
+
+pub(crate) use recording::RecordingPlugin;
+
+mod api {
+	pub use super::playback::{ReplayPlayback, ReplayPlugin};
+	pub use super::recording::{
+		BlueprintChanges, RecordedBlueprint, RecordedTick, RecordingHeader, SessionRecording,
+		RECORDING_VERSION,
+	};
+}
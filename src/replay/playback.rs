@@ -0,0 +1,520 @@
+//! Plays a [SessionRecording] back, by hosting a private server that nobody can join
+//! and feeding it the recorded blueprints and inputs instead of creating a world
+//! and listening to clients.
+//!
+//! Space pauses, `.` steps one tick while paused, Up and Down change the speed,
+//! Left and Right seek and Tab follows the next player.
+
+use std::path::PathBuf;
+
+use bevy::{hierarchy::despawn_with_children_recursive, sprite::Anchor};
+
+use crate::cameras::{BlockEntity, CameraBlockMarker, ChangeCameraConfig};
+use crate::players::{PlayerBlueprintComponent, PlayerInput, SubmitShipDesign};
+use crate::prelude::*;
+
+use super::recording::{BlueprintChanges, RecordedBlueprint, SessionRecording};
+
+/// Replaces the CLI configuration of [crate::MainPlugin], so must be added before it
+pub struct ReplayPlugin {
+	recording: SessionRecording,
+	paused: bool,
+}
+
+/// Arguments of the `space_craft_replay` binary
+#[derive(Parser, Debug)]
+#[command(about = "Replays a session recorded with `space_craft server --record <FILE>`")]
+struct ReplayArgs {
+	/// The recording to play back
+	file: PathBuf,
+
+	/// Start paused on the first tick
+	#[arg(long)]
+	paused: bool,
+}
+
+impl ReplayPlugin {
+	/// Panics if the recording can't be loaded
+	pub fn from_args() -> Self {
+		let args = ReplayArgs::parse();
+		let recording = SessionRecording::load(&args.file)
+			.unwrap_or_else(|err| panic!("Couldn't load recording {:?}: {}", args.file, err));
+		Self {
+			recording,
+			paused: args.paused,
+		}
+	}
+
+	pub fn new(recording: SessionRecording) -> Self {
+		Self {
+			recording,
+			paused: false,
+		}
+	}
+
+	/// A server on its own loopback network, stepping as fast as the recording did
+	pub fn netcode_config(&self) -> NetcodeConfig {
+		NetcodeConfig::Server {
+			ip: Ipv4Addr::LOCALHOST.into(),
+			port: DEFAULT_PORT,
+			headless: false,
+			auth: Authentication::Unsecure,
+			token_port: DEFAULT_TOKEN_PORT,
+			private_key: None,
+			token_secret: None,
+			spawn_points: crate::players::SpawnPointsConfig::DEFAULT,
+			settings: ServerSettingsArgs {
+				tick_rate: Some(self.recording.header.tick_rate),
+				seed: Some(self.recording.header.seed),
+				..ServerSettingsArgs::NONE
+			},
+			ship: None,
+			transport: NetcodeTransport::Loopback(LoopbackNetwork::new(LinkConditions::PERFECT, 0)),
+		}
+	}
+}
+
+impl Plugin for ReplayPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.insert_resource(self.netcode_config())
+			.insert_resource(ReplayPlayback {
+				recording: self.recording.clone(),
+				tick: 0,
+				entities: HashMap::new(),
+				followed: None,
+			})
+			.add_systems(
+				FixedUpdate,
+				(
+					Self::replay_world_creation
+						.after(GlobalSystemSet::TickClocks)
+						.before(GlobalSystemSet::WorldCreation),
+					// exclusive, so it can't share a set with [GameLogic]
+					Self::replay_game_logic
+						.after(GlobalSystemSet::ExecuteGameLogic)
+						.before(GlobalSystemSet::BlueprintExpansion),
+				)
+					.run_if(in_state(GlobalGameStates::InGame)),
+			)
+			.add_systems(OnEnter(GlobalGameStates::InGame), Self::spawn_overlay)
+			.add_systems(
+				Update,
+				(
+					Self::control_playback,
+					Self::follow_next_player,
+					Self::update_overlay,
+				)
+					.chain()
+					.run_if(in_state(GlobalGameStates::InGame)),
+			);
+
+		if self.paused {
+			app.add_systems(Startup, |mut time: ResMut<Time<Virtual>>| time.pause());
+		}
+	}
+}
+
+/// How far a [SessionRecording] has been played back
+#[derive(Resource, Debug)]
+pub struct ReplayPlayback {
+	recording: SessionRecording,
+
+	/// The next [crate::replay::RecordedTick] to replay
+	tick: usize,
+
+	/// Recorded entity IDs to the entities replaying them
+	entities: HashMap<u64, Entity>,
+
+	/// The player whose camera is shown
+	followed: Option<NetworkId>,
+}
+
+impl ReplayPlayback {
+	pub fn tick(&self) -> usize {
+		self.tick
+	}
+
+	pub fn total_ticks(&self) -> usize {
+		self.recording.ticks.len()
+	}
+
+	pub fn is_finished(&self) -> bool {
+		self.tick >= self.total_ticks()
+	}
+
+	/// Spawns, changes and despawns blueprints like the recording server did
+	fn apply(&mut self, changes: &BlueprintChanges, world: &mut World) {
+		for id in &changes.despawned {
+			if let Some(entity) = self.entities.remove(id) {
+				despawn_with_children_recursive(world, entity);
+			}
+		}
+		for (id, blueprint) in &changes.spawned {
+			let entity = match blueprint.clone() {
+				RecordedBlueprint::Terrain(blueprint) => world.spawn(blueprint).id(),
+				RecordedBlueprint::SpawnPoint(transform, blueprint) => {
+					world.spawn((transform, blueprint)).id()
+				}
+				RecordedBlueprint::Player(transform, network_id, blueprint) => {
+					world.spawn((transform, blueprint, network_id)).id()
+				}
+			};
+			self.entities.insert(*id, entity);
+		}
+		for (id, blueprint) in &changes.changed {
+			let Some(mut entity) = self
+				.entities
+				.get(id)
+				.and_then(|entity| world.get_entity_mut(*entity))
+			else {
+				warn!(
+					"Recorded a change to entity {}, which was never spawned",
+					id
+				);
+				continue;
+			};
+			match blueprint.clone() {
+				RecordedBlueprint::Terrain(blueprint) => entity.insert(blueprint),
+				RecordedBlueprint::SpawnPoint(_, blueprint) => entity.insert(blueprint),
+				RecordedBlueprint::Player(_, _, blueprint) => entity.insert(blueprint),
+			};
+		}
+	}
+}
+
+/// Text at the top of the screen showing the [ReplayPlayback]
+#[derive(Component)]
+struct ReplayOverlayText;
+
+impl ReplayPlugin {
+	const CAM: UiCameras = UiCameras::TopMiddle;
+
+	/// How far Left and Right seek
+	const SEEK: Duration = Duration::from_secs(5);
+
+	const MIN_SPEED: f32 = 0.125;
+	const MAX_SPEED: f32 = 8.;
+
+	/// Replaces world creation, joining players and their ship designs with the recorded
+	/// blueprints, and applies the recorded inputs before players move
+	fn replay_world_creation(world: &mut World) {
+		world.resource_mut::<Events<CreateWorldEvent>>().clear();
+		world.resource_mut::<Events<PlayerJoin>>().clear();
+		world
+			.resource_mut::<Events<FromClient<SubmitShipDesign>>>()
+			.clear();
+
+		world.resource_scope(|world, mut playback: Mut<ReplayPlayback>| {
+			let Some(tick) = playback.recording.ticks.get(playback.tick).cloned() else {
+				return;
+			};
+			if !tick.world_creation.is_empty() {
+				playback.apply(&tick.world_creation, world);
+				world.run_schedule(Blueprints);
+			}
+
+			let mut players = world.query::<(&NetworkId, &mut ActionState<PlayerInput>)>();
+			for (network_id, mut action_state) in players.iter_mut(world) {
+				let pressed = tick
+					.inputs
+					.iter()
+					.find(|(id, _)| id == network_id)
+					.map(|(_, pressed)| *pressed)
+					.unwrap_or_default();
+				pressed.apply_to(&mut action_state);
+			}
+		});
+	}
+
+	fn replay_game_logic(world: &mut World) {
+		world.resource_scope(|world, mut playback: Mut<ReplayPlayback>| {
+			let Some(tick) = playback.recording.ticks.get(playback.tick).cloned() else {
+				return;
+			};
+			playback.apply(&tick.game_logic, world);
+			playback.tick += 1;
+
+			if playback.is_finished() {
+				info!("Replayed all {} recorded ticks", playback.total_ticks());
+				world.resource_mut::<Time<Virtual>>().pause();
+			}
+		});
+	}
+
+	fn control_playback(
+		keys: Res<Input<KeyCode>>,
+		mut playback: ResMut<ReplayPlayback>,
+		mut virtual_time: ResMut<Time<Virtual>>,
+		mut fixed_time: ResMut<Time<Fixed>>,
+		mut game_clock: ResMut<GameClock>,
+		mut commands: Commands,
+	) {
+		let timestep = fixed_time.timestep();
+		let seek_ticks = (Self::SEEK.as_secs_f64() / timestep.as_secs_f64()).round() as usize;
+
+		if keys.just_pressed(KeyCode::Space) {
+			if virtual_time.is_paused() {
+				if playback.is_finished() {
+					info!("Nothing left to replay, seek back with Left");
+				} else {
+					virtual_time.unpause();
+				}
+			} else {
+				virtual_time.pause();
+			}
+		}
+		if keys.just_pressed(KeyCode::Period) && virtual_time.is_paused() && !playback.is_finished() {
+			fixed_time.accumulate(timestep);
+		}
+
+		if keys.just_pressed(KeyCode::Up) {
+			let speed = (virtual_time.relative_speed() * 2.).min(Self::MAX_SPEED);
+			virtual_time.set_relative_speed(speed);
+		}
+		if keys.just_pressed(KeyCode::Down) {
+			let speed = (virtual_time.relative_speed() / 2.).max(Self::MIN_SPEED);
+			virtual_time.set_relative_speed(speed);
+		}
+
+		// every tick sought over is simulated during the next [FixedUpdate]
+		if keys.just_pressed(KeyCode::Right) {
+			let ticks = seek_ticks.min(playback.total_ticks() - playback.tick);
+			for _ in 0..ticks {
+				fixed_time.accumulate(timestep);
+			}
+		}
+		if keys.just_pressed(KeyCode::Left) {
+			// the physics can't be rewound, so start again and simulate up to the target
+			let target = playback.tick.saturating_sub(seek_ticks);
+			for (_, entity) in playback.entities.drain() {
+				commands.entity(entity).despawn_recursive();
+			}
+			playback.tick = 0;
+			// the [GameRng] is reseeded from the clock, so it has to start again too
+			*game_clock = GameClock::new();
+			for _ in 0..target {
+				fixed_time.accumulate(timestep);
+			}
+		}
+	}
+
+	fn follow_next_player(
+		keys: Res<Input<KeyCode>>,
+		mut playback: ResMut<ReplayPlayback>,
+		players: Query<(&NetworkId, &Children), With<PlayerBlueprintComponent>>,
+		camera_blocks: Query<(), With<CameraBlockMarker>>,
+		mut set_primary_camera: EventWriter<ChangeCameraConfig>,
+	) {
+		if !keys.just_pressed(KeyCode::Tab) {
+			return;
+		}
+
+		let mut ids: Vec<_> = players.iter().map(|(id, _)| *id).collect();
+		ids.sort_by_key(|id| id.get_network_id().raw());
+		let next = match playback.followed {
+			Some(followed) => ids
+				.iter()
+				.find(|id| id.get_network_id().raw() > followed.get_network_id().raw())
+				.or(ids.first()),
+			None => ids.first(),
+		};
+		let Some(next) = next.copied() else {
+			return;
+		};
+
+		let camera_block = players
+			.iter()
+			.find(|(id, _)| **id == next)
+			.and_then(|(_, children)| {
+				children
+					.iter()
+					.find(|child| camera_blocks.contains(**child))
+			});
+		if let Some(camera_block) = camera_block {
+			info!("Following player {}", next.get_network_id());
+			playback.followed = Some(next);
+			set_primary_camera.send(ChangeCameraConfig::SetPrimaryCamera {
+				follow_camera_block: BlockEntity(*camera_block),
+			});
+		}
+	}
+
+	fn spawn_overlay(mut commands: Commands, ass: Res<AssetServer>) {
+		let style = TextStyle {
+			font: ass.load(GlobalFont::Default),
+			font_size: 20.,
+			color: Color::WHITE,
+		};
+		commands
+			.spawn((
+				Text2dBundle {
+					text: Text::from_section("", style),
+					text_anchor: Anchor::TopCenter,
+					// just below the top edge
+					transform: Transform::from_xyz(0., -10., 1.),
+					..default()
+				},
+				ReplayOverlayText,
+				Name::new("Replay Overlay Text"),
+			))
+			.render_layer(GlobalRenderLayers::Ui(Self::CAM));
+	}
+
+	fn update_overlay(
+		playback: Res<ReplayPlayback>,
+		virtual_time: Res<Time<Virtual>>,
+		mut texts: Query<&mut Text, With<ReplayOverlayText>>,
+	) {
+		let state = if virtual_time.is_paused() {
+			"paused".to_string()
+		} else {
+			format!("{}x", virtual_time.relative_speed())
+		};
+		let value = format!(
+			"Replay tick {} / {} ({})",
+			playback.tick(),
+			playback.total_ticks(),
+			state
+		);
+		for mut text in texts.iter_mut() {
+			text.sections[0].value = value.clone();
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use bevy::input::{keyboard::KeyboardInput, ButtonState};
+
+	use crate::players::{PlayerBlueprintComponent, PlayerInput};
+	use crate::prelude::*;
+	use crate::replay::{RecordedBlueprint, SessionRecording};
+
+	use super::{ReplayPlayback, ReplayPlugin};
+
+	fn player_positions(app: &mut App) -> HashMap<NetworkId, Vec3> {
+		app
+			.world
+			.query_filtered::<(&NetworkId, &Transform), With<PlayerBlueprintComponent>>()
+			.iter(&app.world)
+			.map(|(id, transform)| (*id, transform.translation))
+			.collect()
+	}
+
+	/// Records a server and a client, who flies forwards halfway through,
+	/// returning the recording and where the players ended up
+	fn record_session() -> (SessionRecording, HashMap<NetworkId, Vec3>) {
+		let path = std::env::temp_dir().join(format!("space_craft-replay-{}.bin", random::<u64>()));
+		let mut apps = loopback_game(
+			&LoopbackNetwork::new(LinkConditions::PERFECT, 0),
+			ServerSettingsArgs {
+				record: Some(path.clone()),
+				..ServerSettingsArgs::NONE
+			},
+			1,
+		);
+		apps[1]
+			.world
+			.resource_mut::<Input<KeyCode>>()
+			.press(KeyCode::W);
+		update_all(60, &mut apps);
+		let mut server = apps.swap_remove(0);
+		let recorded_positions = player_positions(&mut server);
+		assert_eq!(recorded_positions.len(), 2);
+		let seed = server.world.resource::<GameRng>().seed();
+
+		// finishes the recording
+		server
+			.world
+			.resource_mut::<NextState<GlobalGameStates>>()
+			.set(GlobalGameStates::StartMenu);
+		server.update();
+
+		let recording = SessionRecording::load(&path);
+		std::fs::remove_file(&path).unwrap();
+		let recording = recording.unwrap();
+		assert_eq!(recording.header.seed, seed);
+		(recording, recorded_positions)
+	}
+
+	/// Updates `replay` until it has replayed every tick
+	fn finish(replay: &mut App) {
+		let mut frames = 0;
+		while !replay.world.resource::<ReplayPlayback>().is_finished() {
+			replay.update();
+			frames += 1;
+			assert!(frames < 1000, "The replay never finished");
+		}
+	}
+
+	fn assert_replayed(recorded_positions: &HashMap<NetworkId, Vec3>, replay: &mut App) {
+		let replayed_positions = player_positions(replay);
+		assert_eq!(replayed_positions.len(), recorded_positions.len());
+		for (id, recorded) in recorded_positions {
+			let replayed = replayed_positions[id];
+			assert!(
+				recorded.distance(replayed) < 0.01,
+				"Player {:?} was recorded at {} but replayed at {}",
+				id,
+				recorded,
+				replayed
+			);
+		}
+	}
+
+	#[test]
+	fn replay_follows_the_recording() {
+		let (recording, recorded_positions) = record_session();
+		assert!(
+			recording.ticks[0]
+				.world_creation
+				.spawned
+				.iter()
+				.any(|(_, blueprint)| matches!(blueprint, RecordedBlueprint::SpawnPoint(..))),
+			"The world is created on the first tick"
+		);
+		let player_spawns = recording
+			.ticks
+			.iter()
+			.flat_map(|tick| &tick.game_logic.spawned)
+			.filter(|(_, blueprint)| matches!(blueprint, RecordedBlueprint::Player(..)))
+			.count();
+		assert_eq!(player_spawns, 2);
+		let client_id = NetworkId::from_raw(1);
+		assert!(recording.ticks.iter().any(|tick| tick
+			.inputs
+			.iter()
+			.any(|(id, pressed)| *id == client_id && pressed.is_pressed(PlayerInput::Forward))));
+
+		let plugin = ReplayPlugin::new(recording);
+		let mut replay = test_netcode_app(plugin.netcode_config());
+		replay.add_plugins(plugin);
+		finish(&mut replay);
+
+		assert_replayed(&recorded_positions, &mut replay);
+	}
+
+	#[test]
+	fn seeking_back_replays_the_same() {
+		let (recording, recorded_positions) = record_session();
+		let plugin = ReplayPlugin::new(recording);
+		let mut replay = test_netcode_app(plugin.netcode_config());
+		replay.add_plugins(plugin);
+		finish(&mut replay);
+
+		// further back than the start, so everything is simulated again
+		replay.world.send_event(KeyboardInput {
+			scan_code: 0,
+			key_code: Some(KeyCode::Left),
+			state: ButtonState::Pressed,
+			window: Entity::PLACEHOLDER,
+		});
+		replay.update();
+		assert!(!replay.world.resource::<ReplayPlayback>().is_finished());
+		replay.world.resource_mut::<Time<Virtual>>().unpause();
+		finish(&mut replay);
+
+		assert_replayed(&recorded_positions, &mut replay);
+	}
+}
@@ -0,0 +1,347 @@
+//! Records every [FixedUpdate] tick of a session hosted with `--record <FILE>`:
+//! the blueprints spawned, changed and despawned, and what every player was pressing.
+//!
+//! The file starts with a [RecordingHeader] followed by one [RecordedTick] per tick,
+//! all encoded with [bincode]. Ticks are written as they happen,
+//! so a server that stops abruptly only loses the tick it was writing.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use bevy::ecs::component::Tick;
+
+use crate::players::{
+	PlayerBlueprintComponent, PlayerInput, PressedInputs, SpawnPointBlueprintComponent,
+};
+use crate::prelude::*;
+
+pub(crate) struct RecordingPlugin;
+
+impl Plugin for RecordingPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(OnEnter(GlobalGameStates::InGame), Self::start_recording)
+			.add_systems(
+				FixedUpdate,
+				(
+					// world creation expands its blueprints before anything moves
+					Self::record_world_creation
+						.after(GlobalSystemSet::WorldCreation)
+						.before(GlobalSystemSet::PlayerMovement),
+					Self::record_tick.after(GlobalSystemSet::BlueprintExpansion),
+				)
+					.run_if(resource_exists::<SessionRecorder>()),
+			)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::stop_recording);
+	}
+}
+
+/// Bumped whenever [RecordedTick] changes, recordings of other versions can't be replayed
+pub const RECORDING_VERSION: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingHeader {
+	pub version: u32,
+
+	/// How many times [FixedUpdate] ran per second
+	pub tick_rate: f64,
+
+	/// The world seed, so that the [GameRng] draws the same numbers when replaying
+	pub seed: u64,
+}
+
+/// Everything needed to simulate one [FixedUpdate] tick again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordedTick {
+	/// Blueprints from the [WorldCreation] schedule
+	pub world_creation: BlueprintChanges,
+
+	/// Blueprints from [GameLogic], e.g. players joining or spawn points being occupied
+	pub game_logic: BlueprintChanges,
+
+	/// What every player was pressing, sorted by [NetworkId]
+	pub inputs: Vec<(NetworkId, PressedInputs)>,
+}
+
+/// Entities are identified by their [Entity::to_bits] on the recording server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlueprintChanges {
+	pub spawned: Vec<(u64, RecordedBlueprint)>,
+
+	/// Only the blueprint component is updated, e.g. when a spawn point is occupied
+	pub changed: Vec<(u64, RecordedBlueprint)>,
+
+	pub despawned: Vec<u64>,
+}
+
+impl BlueprintChanges {
+	pub fn is_empty(&self) -> bool {
+		self.spawned.is_empty() && self.changed.is_empty() && self.despawned.is_empty()
+	}
+}
+
+/// The networked blueprints that make up the world,
+/// with the other components they are spawned with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordedBlueprint {
+	Terrain(TerrainStructureBlueprint),
+	SpawnPoint(Transform, SpawnPointBlueprintComponent),
+	Player(Transform, NetworkId, PlayerBlueprintComponent),
+}
+
+/// A whole recording, as loaded by the replay viewer
+#[derive(Debug, Clone)]
+pub struct SessionRecording {
+	pub header: RecordingHeader,
+	pub ticks: Vec<RecordedTick>,
+}
+
+fn invalid_data(err: bincode::Error) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+}
+
+impl SessionRecording {
+	pub fn load(path: &Path) -> std::io::Result<Self> {
+		let mut reader = BufReader::new(File::open(path)?);
+		let header: RecordingHeader = bincode::deserialize_from(&mut reader).map_err(invalid_data)?;
+		if header.version != RECORDING_VERSION {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				format!(
+					"recorded with version {}, but only version {} can be replayed",
+					header.version, RECORDING_VERSION
+				),
+			));
+		}
+
+		let mut ticks = Vec::new();
+		loop {
+			match bincode::deserialize_from::<_, RecordedTick>(&mut reader) {
+				Ok(tick) => ticks.push(tick),
+				// the end of the file, or a tick cut short by the server stopping
+				Err(err) if matches!(&*err, bincode::ErrorKind::Io(io) if io.kind() == std::io::ErrorKind::UnexpectedEof) => {
+					break
+				}
+				Err(err) => return Err(invalid_data(err)),
+			}
+		}
+		info!("Loaded {} recorded ticks from {:?}", ticks.len(), path);
+
+		Ok(Self { header, ticks })
+	}
+}
+
+/// Inserted while recording
+#[derive(Resource)]
+struct SessionRecorder {
+	path: PathBuf,
+	file: BufWriter<File>,
+	ticks_written: u64,
+
+	/// Blueprint entities that have been recorded as spawned, and not as despawned yet
+	known: HashSet<Entity>,
+
+	/// Blueprint changes after this are recorded next
+	last_recorded: Tick,
+
+	/// Filled in halfway through the tick, written with the rest of it
+	world_creation: BlueprintChanges,
+}
+
+impl SessionRecorder {
+	fn write(&mut self, tick: &RecordedTick, tick_rate: f64, seed: u64) -> bincode::Result<()> {
+		// the tick rate and seed are only known for sure once the first tick runs
+		if self.ticks_written == 0 {
+			let header = RecordingHeader {
+				version: RECORDING_VERSION,
+				tick_rate,
+				seed,
+			};
+			bincode::serialize_into(&mut self.file, &header)?;
+		}
+		bincode::serialize_into(&mut self.file, tick)?;
+		// buffered so a tick is written at once, flushed so that it survives the server stopping
+		self.file.flush()?;
+		self.ticks_written += 1;
+		Ok(())
+	}
+}
+
+/// Every blueprint that is recorded, see [RecordedBlueprint]
+#[derive(SystemParam)]
+struct RecordableBlueprints<'w, 's> {
+	terrain: Query<'w, 's, (Entity, Ref<'static, TerrainStructureBlueprint>)>,
+	spawn_points: Query<
+		'w,
+		's,
+		(
+			Entity,
+			&'static Transform,
+			Ref<'static, SpawnPointBlueprintComponent>,
+		),
+	>,
+	players: Query<
+		'w,
+		's,
+		(
+			Entity,
+			&'static Transform,
+			&'static NetworkId,
+			Ref<'static, PlayerBlueprintComponent>,
+		),
+	>,
+	system_ticks: SystemChangeTick,
+}
+
+impl RecordableBlueprints<'_, '_> {
+	/// Everything spawned, changed or despawned since the last time this was called
+	fn collect_changes(&self, recorder: &mut SessionRecorder) -> BlueprintChanges {
+		let mut collector = ChangeCollector {
+			known: &mut recorder.known,
+			present: HashSet::new(),
+			changes: BlueprintChanges::default(),
+			last_recorded: recorder.last_recorded,
+			this_run: self.system_ticks.this_run(),
+		};
+		for (entity, blueprint) in self.terrain.iter() {
+			collector.note(entity, blueprint.last_changed(), || {
+				RecordedBlueprint::Terrain(blueprint.clone())
+			});
+		}
+		for (entity, transform, blueprint) in self.spawn_points.iter() {
+			collector.note(entity, blueprint.last_changed(), || {
+				RecordedBlueprint::SpawnPoint(*transform, blueprint.clone())
+			});
+		}
+		for (entity, transform, network_id, blueprint) in self.players.iter() {
+			collector.note(entity, blueprint.last_changed(), || {
+				RecordedBlueprint::Player(*transform, *network_id, blueprint.clone())
+			});
+		}
+		recorder.last_recorded = self.system_ticks.this_run();
+		collector.finish()
+	}
+}
+
+struct ChangeCollector<'a> {
+	known: &'a mut HashSet<Entity>,
+	present: HashSet<Entity>,
+	changes: BlueprintChanges,
+	last_recorded: Tick,
+	this_run: Tick,
+}
+
+impl ChangeCollector<'_> {
+	fn note(
+		&mut self,
+		entity: Entity,
+		last_changed: Tick,
+		blueprint: impl FnOnce() -> RecordedBlueprint,
+	) {
+		self.present.insert(entity);
+		if self.known.insert(entity) {
+			self.changes.spawned.push((entity.to_bits(), blueprint()));
+		} else if last_changed.is_newer_than(self.last_recorded, self.this_run) {
+			self.changes.changed.push((entity.to_bits(), blueprint()));
+		}
+	}
+
+	fn finish(self) -> BlueprintChanges {
+		let ChangeCollector {
+			known,
+			present,
+			mut changes,
+			..
+		} = self;
+		known.retain(|entity| {
+			let still_there = present.contains(entity);
+			if !still_there {
+				changes.despawned.push(entity.to_bits());
+			}
+			still_there
+		});
+
+		// queries iterate in whatever order the archetypes are in
+		changes.spawned.sort_by_key(|(id, _)| *id);
+		changes.changed.sort_by_key(|(id, _)| *id);
+		changes.despawned.sort();
+		changes
+	}
+}
+
+impl RecordingPlugin {
+	fn start_recording(config: Res<NetcodeConfig>, mut commands: Commands) {
+		let NetcodeConfig::Server { settings, .. } = config.into_inner() else {
+			return;
+		};
+		let Some(path) = &settings.record else {
+			return;
+		};
+		match File::create(path) {
+			Ok(file) => {
+				info!("Recording the session to {:?}", path);
+				commands.insert_resource(SessionRecorder {
+					path: path.clone(),
+					file: BufWriter::new(file),
+					ticks_written: 0,
+					known: HashSet::new(),
+					last_recorded: Tick::new(0),
+					world_creation: BlueprintChanges::default(),
+				});
+			}
+			Err(err) => error!("Couldn't record the session to {:?}: {}", path, err),
+		}
+	}
+
+	fn record_world_creation(
+		mut recorder: ResMut<SessionRecorder>,
+		blueprints: RecordableBlueprints,
+	) {
+		let changes = blueprints.collect_changes(&mut recorder);
+		recorder.world_creation = changes;
+	}
+
+	fn record_tick(
+		mut recorder: ResMut<SessionRecorder>,
+		blueprints: RecordableBlueprints,
+		players: Query<(&NetworkId, &ActionState<PlayerInput>)>,
+		time: Res<Time<Fixed>>,
+		rng: Res<GameRng>,
+		mut commands: Commands,
+	) {
+		let game_logic = blueprints.collect_changes(&mut recorder);
+		let mut inputs: Vec<_> = players
+			.iter()
+			.map(|(network_id, action_state)| {
+				(*network_id, PressedInputs::from_action_state(action_state))
+			})
+			.collect();
+		inputs.sort_by_key(|(network_id, _)| network_id.get_network_id().raw());
+
+		let tick = RecordedTick {
+			world_creation: std::mem::take(&mut recorder.world_creation),
+			game_logic,
+			inputs,
+		};
+		let tick_rate = 1. / time.timestep().as_secs_f64();
+		if let Err(err) = recorder.write(&tick, tick_rate, rng.seed()) {
+			error!("Stopped recording to {:?}: {}", recorder.path, err);
+			commands.remove_resource::<SessionRecorder>();
+		}
+	}
+
+	fn stop_recording(recorder: Option<ResMut<SessionRecorder>>, mut commands: Commands) {
+		let Some(mut recorder) = recorder else {
+			return;
+		};
+		match recorder.file.flush() {
+			Ok(()) => info!(
+				"Recorded {} ticks to {:?}",
+				recorder.ticks_written, recorder.path
+			),
+			Err(err) => error!("Couldn't finish recording to {:?}: {}", recorder.path, err),
+		}
+		commands.remove_resource::<SessionRecorder>();
+	}
+}
@@ -10,3 +10,20 @@ pub enum GlobalGameStates {
 
 	InGame,
 }
+
+/// Where a client is in connecting to the server, only meaningful while in [GlobalGameStates::InGame].
+///
+/// Servers are always [ClientConnectionState::Connected] while in game.
+/// Reset to [ClientConnectionState::Offline] when leaving the game.
+#[derive(States, Default, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ClientConnectionState {
+	#[default]
+	Offline,
+
+	Connecting,
+
+	Connected,
+
+	/// The reason is in the [ConnectionError] resource
+	Disconnected,
+}
@@ -8,10 +8,18 @@ impl PluginGroup for UiPlugins {
 		PluginGroupBuilder::start::<Self>()
 			.add(self::start_screen::StartScreen)
 			.add(self::ui_cameras::UiCamerasPlugin)
+			.add(self::connection_status::ConnectionStatusPlugin)
+			.add(self::pause_menu::PauseMenuPlugin)
+			.add(self::network_stats::NetworkStatsOverlayPlugin)
+			.add(self::chat::ChatBoxPlugin)
 			.build()
 	}
 }
 
+mod chat;
+mod connection_status;
+mod network_stats;
+mod pause_menu;
 mod ui_cameras;
 
 mod manual_ui {
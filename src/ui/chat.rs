@@ -0,0 +1,186 @@
+//! Chat box in the bottom left corner while in a game, showing the [ChatHistory].
+//!
+//! [ChatAction::Open] starts typing a message, Enter sends it and Escape throws it away.
+//! The player doesn't move while typing.
+
+use bevy::sprite::Anchor;
+
+use crate::players::PlayerInput;
+use crate::prelude::*;
+
+/// Plugin
+pub struct ChatBoxPlugin;
+
+/// Sub-state of [GlobalGameStates::InGame]
+#[derive(States, Default, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub(super) enum ChatBoxStates {
+	#[default]
+	Closed,
+
+	Typing,
+}
+
+impl Plugin for ChatBoxPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_state::<ChatBoxStates>()
+			.add_plugins(InputManagerPlugin::<ChatAction>::default())
+			.init_resource::<ActionState<ChatAction>>()
+			.insert_resource(ChatAction::default_input_map())
+			.init_resource::<ChatDraft>()
+			.add_systems(OnEnter(GlobalGameStates::InGame), Self::spawn_chat_box)
+			.add_systems(
+				Update,
+				(
+					Self::open_chat_box.run_if(in_state(ChatBoxStates::Closed)),
+					Self::type_chat_message,
+					Self::update_chat_box,
+				)
+					.chain()
+					.run_if(in_state(GlobalGameStates::InGame)),
+			)
+			.add_systems(OnEnter(ChatBoxStates::Typing), Self::disable_player_input)
+			.add_systems(OnExit(ChatBoxStates::Typing), Self::enable_player_input)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::despawn_chat_box);
+	}
+}
+
+/// Keys for the chat box, kept apart from [PlayerInput] so that they work without a player
+#[derive(ActionLike, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect)]
+pub enum ChatAction {
+	Open,
+}
+
+impl ChatAction {
+	fn default_input_map() -> InputMap<Self> {
+		InputMap::new([(KeyCode::T, ChatAction::Open)])
+	}
+}
+
+/// The message being typed
+#[derive(Resource, Debug, Default)]
+struct ChatDraft(String);
+
+/// Text in the bottom left corner listing the latest messages
+#[derive(Component)]
+struct ChatBoxText;
+
+impl ChatBoxPlugin {
+	const CAM: UiCameras = UiCameras::BottomLeft;
+
+	/// How many of the latest messages are shown
+	const SHOWN_MESSAGES: usize = 8;
+
+	fn spawn_chat_box(mut commands: Commands, ass: Res<AssetServer>) {
+		let style = TextStyle {
+			font: ass.load(GlobalFont::Default),
+			font_size: 18.,
+			color: Color::WHITE,
+		};
+		commands
+			.spawn((
+				Text2dBundle {
+					text: Text::from_section("", style),
+					text_anchor: Anchor::BottomLeft,
+					// just inside the bottom left corner
+					transform: Transform::from_xyz(10., 10., 1.),
+					..default()
+				},
+				ChatBoxText,
+				Name::new("Chat Box Text"),
+			))
+			.render_layer(GlobalRenderLayers::Ui(Self::CAM));
+	}
+
+	fn open_chat_box(
+		actions: Res<ActionState<ChatAction>>,
+		mut next_state: ResMut<NextState<ChatBoxStates>>,
+	) {
+		if actions.just_pressed(ChatAction::Open) {
+			next_state.set(ChatBoxStates::Typing);
+		}
+	}
+
+	/// Always reads the typed characters, so that the key opening the chat box
+	/// isn't typed into it on the next frame
+	fn type_chat_message(
+		mut received_chars: EventReader<ReceivedCharacter>,
+		keys: Res<Input<KeyCode>>,
+		state: Res<State<ChatBoxStates>>,
+		mut next_state: ResMut<NextState<ChatBoxStates>>,
+		mut draft: ResMut<ChatDraft>,
+		mut messages: EventWriter<SendChatMessage>,
+	) {
+		if *state.get() != ChatBoxStates::Typing {
+			received_chars.clear();
+			return;
+		}
+
+		for received in received_chars.read() {
+			if !received.char.is_control() && draft.0.chars().count() < MAX_CHAT_MESSAGE_LEN {
+				draft.0.push(received.char);
+			}
+		}
+		if keys.just_pressed(KeyCode::Back) {
+			draft.0.pop();
+		}
+
+		if keys.just_pressed(KeyCode::Return) {
+			let text = std::mem::take(&mut draft.0);
+			if !text.trim().is_empty() {
+				messages.send(SendChatMessage(text));
+			}
+			next_state.set(ChatBoxStates::Closed);
+		} else if keys.just_pressed(KeyCode::Escape) {
+			draft.0.clear();
+			next_state.set(ChatBoxStates::Closed);
+		}
+	}
+
+	fn update_chat_box(
+		history: Res<ChatHistory>,
+		draft: Res<ChatDraft>,
+		state: Res<State<ChatBoxStates>>,
+		mut texts: Query<&mut Text, With<ChatBoxText>>,
+	) {
+		if !history.is_changed() && !draft.is_changed() && !state.is_changed() {
+			return;
+		}
+
+		let mut lines: Vec<String> = history
+			.iter()
+			.rev()
+			.take(Self::SHOWN_MESSAGES)
+			.map(ToString::to_string)
+			.collect();
+		lines.reverse();
+		if *state.get() == ChatBoxStates::Typing {
+			lines.push(format!("> {}_", draft.0));
+		}
+		let value = lines.join("\n");
+		for mut text in texts.iter_mut() {
+			text.sections[0].value = value.clone();
+		}
+	}
+
+	fn disable_player_input(mut toggle: ResMut<ToggleActions<PlayerInput>>) {
+		toggle.enabled = false;
+	}
+
+	fn enable_player_input(mut toggle: ResMut<ToggleActions<PlayerInput>>) {
+		toggle.enabled = true;
+	}
+
+	fn despawn_chat_box(
+		mut commands: Commands,
+		texts: Query<Entity, With<ChatBoxText>>,
+		mut next_state: ResMut<NextState<ChatBoxStates>>,
+		mut draft: ResMut<ChatDraft>,
+	) {
+		for text in texts.iter() {
+			commands.entity(text).despawn_recursive();
+		}
+		next_state.set(ChatBoxStates::Closed);
+		draft.0.clear();
+	}
+}
@@ -0,0 +1,109 @@
+//! Shows clients how their connection to the server is going,
+//! and lets them return to the start menu once disconnected.
+
+use bevy::text::Text2dBounds;
+
+use crate::prelude::*;
+
+/// Plugin
+pub struct ConnectionStatusPlugin;
+
+impl Plugin for ConnectionStatusPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(
+				OnEnter(GlobalGameStates::InGame),
+				Self::spawn_status_text.run_if(not(NetcodeConfig::has_authority())),
+			)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::despawn_status_text)
+			.add_systems(
+				Update,
+				(
+					Self::update_status_text.run_if(state_changed::<ClientConnectionState>()),
+					Self::return_to_menu.run_if(in_state(ClientConnectionState::Disconnected)),
+				)
+					.run_if(in_state(GlobalGameStates::InGame)),
+			);
+	}
+}
+
+/// Text at the top of the screen describing the [ClientConnectionState]
+#[derive(Component)]
+struct ConnectionStatusText;
+
+impl ConnectionStatusPlugin {
+	const CAM: UiCameras = UiCameras::TopMiddle;
+
+	/// Keys that leave the game once disconnected
+	const RETURN_KEYS: [KeyCode; 2] = [KeyCode::Return, KeyCode::Escape];
+
+	fn spawn_status_text(mut commands: Commands, ass: Res<AssetServer>) {
+		let style = TextStyle {
+			font: ass.load(GlobalFont::Default),
+			font_size: 24.,
+			color: Color::WHITE,
+		};
+		commands
+			.spawn((
+				Text2dBundle {
+					text: Text::from_section("", style).with_alignment(TextAlignment::Center),
+					// just below the top of the screen
+					transform: Transform::from_xyz(0., -60., 1.),
+					text_2d_bounds: Text2dBounds {
+						size: Vec2::new(600., 100.),
+					},
+					..default()
+				},
+				ConnectionStatusText,
+				Name::new("Connection Status Text"),
+			))
+			.render_layer(GlobalRenderLayers::Ui(Self::CAM));
+	}
+
+	fn despawn_status_text(
+		mut commands: Commands,
+		texts: Query<Entity, With<ConnectionStatusText>>,
+	) {
+		for text in texts.iter() {
+			commands.entity(text).despawn_recursive();
+		}
+	}
+
+	fn update_status_text(
+		mut texts: Query<&mut Text, With<ConnectionStatusText>>,
+		state: Res<State<ClientConnectionState>>,
+		error: Option<Res<ConnectionError>>,
+		config: Res<NetcodeConfig>,
+	) {
+		let (status, color) = match state.get() {
+			ClientConnectionState::Connecting => match config.into_inner() {
+				NetcodeConfig::Client { ip, port, .. } => {
+					(format!("Connecting to {}...", SocketAddr::new(*ip, *port)), Color::WHITE)
+				}
+				NetcodeConfig::Server { .. } => (String::new(), Color::WHITE),
+			},
+			ClientConnectionState::Disconnected => (
+				format!(
+					"{}\nPress Enter to return to the menu",
+					error.map_or_else(|| "Disconnected".into(), |error| error.to_string())
+				),
+				Color::ORANGE_RED,
+			),
+			ClientConnectionState::Offline | ClientConnectionState::Connected => {
+				(String::new(), Color::WHITE)
+			}
+		};
+
+		for mut text in texts.iter_mut() {
+			text.sections[0].value = status.clone();
+			text.sections[0].style.color = color;
+		}
+	}
+
+	fn return_to_menu(keys: Res<Input<KeyCode>>, mut next_state: ResMut<NextState<GlobalGameStates>>) {
+		if keys.any_just_pressed(Self::RETURN_KEYS) {
+			info!("Returning to the start menu");
+			next_state.set(GlobalGameStates::StartMenu);
+		}
+	}
+}
@@ -0,0 +1,223 @@
+//! Shows the [NetworkStats] while in a game with the other screen diagnostics, toggled with F3.
+//!
+//! Every connection gets its own RTT, packet loss and bandwidth diagnostics,
+//! so when hosting they are labelled with the client's id.
+//! They are taken off the screen again once the connection is gone.
+
+use bevy::diagnostic::{
+	Diagnostic, DiagnosticId, Diagnostics, DiagnosticsStore, RegisterDiagnostic,
+};
+use bevy_screen_diagnostics::{Aggregate, ScreenDiagnostics};
+
+use crate::prelude::*;
+
+/// Plugin
+pub struct NetworkStatsOverlayPlugin;
+
+impl Plugin for NetworkStatsOverlayPlugin {
+	fn build(&self, app: &mut App) {
+		for (id, name, suffix) in Self::DIAGNOSTICS {
+			app.register_diagnostic(Diagnostic::new(id, name, Self::HISTORY).with_suffix(suffix));
+		}
+		app
+			.init_resource::<NetworkDiagnosticsShown>()
+			.init_resource::<ConnectionDiagnostics>()
+			.add_systems(Startup, Self::add_screen_diagnostics)
+			.add_systems(
+				Update,
+				(
+					Self::toggle_diagnostics,
+					Self::track_connections,
+					Self::measure_diagnostics,
+				)
+					.chain()
+					.run_if(in_state(GlobalGameStates::InGame)),
+			)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::hide_diagnostics);
+	}
+}
+
+/// Whether the network diagnostics are on screen
+#[derive(Resource, Default)]
+struct NetworkDiagnosticsShown(bool);
+
+/// The connections that currently have diagnostics, [None] being the one to the server
+#[derive(Resource, Default)]
+struct ConnectionDiagnostics(Vec<Option<ClientId>>);
+
+impl NetworkStatsOverlayPlugin {
+	const TOGGLE_KEY: KeyCode = KeyCode::F3;
+
+	const HISTORY: usize = 20;
+
+	const TICK_DRIFT: DiagnosticId = DiagnosticId::from_u128(0x5c1f4f0e9a7d4c3b8e2167d0b3a41e05);
+	const REPLICATED_ENTITIES: DiagnosticId =
+		DiagnosticId::from_u128(0x5c1f4f0e9a7d4c3b8e2167d0b3a41e06);
+
+	const DIAGNOSTICS: [(DiagnosticId, &'static str, &'static str); 2] = [
+		(Self::TICK_DRIFT, "drift", "ticks"),
+		(Self::REPLICATED_ENTITIES, "entities", ""),
+	];
+
+	/// Top bits of every per connection [DiagnosticId], see [Self::connection_diagnostic]
+	const CONNECTION_DIAGNOSTIC_BASE: u128 = 0x5c1f4f0e9a7d << 80;
+
+	const RTT: u8 = 0;
+	const PACKET_LOSS: u8 = 1;
+	const UPLOAD: u8 = 2;
+	const DOWNLOAD: u8 = 3;
+
+	const CONNECTION_DIAGNOSTICS: [(u8, &'static str, &'static str); 4] = [
+		(Self::RTT, "rtt", "ms"),
+		(Self::PACKET_LOSS, "loss", "%"),
+		(Self::UPLOAD, "up", "KB/s"),
+		(Self::DOWNLOAD, "down", "KB/s"),
+	];
+
+	/// Stable for as long as the connection lasts, so its history isn't mixed with another's
+	fn connection_diagnostic(client_id: Option<ClientId>, kind: u8) -> DiagnosticId {
+		let client = match client_id {
+			Some(client_id) => u128::from(client_id.raw()),
+			None => 1 << 64,
+		};
+		DiagnosticId::from_u128(Self::CONNECTION_DIAGNOSTIC_BASE | client << 8 | u128::from(kind))
+	}
+
+	/// Hidden until toggled
+	fn add_screen_diagnostics(mut diagnostics: ResMut<ScreenDiagnostics>) {
+		diagnostics
+			.add("drift".into(), Self::TICK_DRIFT)
+			.aggregate(Aggregate::Value)
+			.format(|v| format!("{:.0}", v))
+			.toggle();
+		diagnostics
+			.add("entities".into(), Self::REPLICATED_ENTITIES)
+			.aggregate(Aggregate::Value)
+			.format(|v| format!("{:.0}", v))
+			.toggle();
+	}
+
+	fn toggle_diagnostics(
+		keys: Res<Input<KeyCode>>,
+		connections: Res<ConnectionDiagnostics>,
+		mut diagnostics: ResMut<ScreenDiagnostics>,
+		mut shown: ResMut<NetworkDiagnosticsShown>,
+	) {
+		if keys.just_pressed(Self::TOGGLE_KEY) {
+			for (id, _, _) in Self::DIAGNOSTICS {
+				diagnostics.modify(id).toggle();
+			}
+			for &client_id in &connections.0 {
+				for (kind, _, _) in Self::CONNECTION_DIAGNOSTICS {
+					diagnostics
+						.modify(Self::connection_diagnostic(client_id, kind))
+						.toggle();
+				}
+			}
+			shown.0 = !shown.0;
+		}
+	}
+
+	/// Adds diagnostics for new connections and removes those of closed ones
+	fn track_connections(
+		stats: Res<NetworkStats>,
+		shown: Res<NetworkDiagnosticsShown>,
+		mut connections: ResMut<ConnectionDiagnostics>,
+		mut store: ResMut<DiagnosticsStore>,
+		mut diagnostics: ResMut<ScreenDiagnostics>,
+	) {
+		let current: Vec<_> = stats
+			.connections
+			.iter()
+			.map(|connection| connection.client_id)
+			.collect();
+		if current == connections.0 {
+			return;
+		}
+
+		for &client_id in connections.0.iter().filter(|id| !current.contains(id)) {
+			Self::remove_connection(client_id, &mut store, &mut diagnostics);
+		}
+		for &client_id in current.iter().filter(|id| !connections.0.contains(id)) {
+			for (kind, name, suffix) in Self::CONNECTION_DIAGNOSTICS {
+				let id = Self::connection_diagnostic(client_id, kind);
+				let name = match client_id {
+					Some(client_id) => format!("{} {}", client_id, name),
+					None => name.to_string(),
+				};
+				if store.get(id).is_none() {
+					store.add(Diagnostic::new(id, name.clone(), Self::HISTORY).with_suffix(suffix));
+				}
+
+				let format: fn(f64) -> String = match kind {
+					Self::RTT => |v| format!("{:.0}ms", v),
+					Self::PACKET_LOSS => |v| format!("{:.1}%", v),
+					_ => |v| format!("{:.1}KB/s", v),
+				};
+				diagnostics
+					.add(name, id)
+					.aggregate(Aggregate::Value)
+					.format(format);
+				if !shown.0 {
+					diagnostics.modify(id).toggle();
+				}
+			}
+		}
+		connections.0 = current;
+	}
+
+	fn remove_connection(
+		client_id: Option<ClientId>,
+		store: &mut DiagnosticsStore,
+		diagnostics: &mut ScreenDiagnostics,
+	) {
+		for (kind, _, _) in Self::CONNECTION_DIAGNOSTICS {
+			let id = Self::connection_diagnostic(client_id, kind);
+			diagnostics.remove(id);
+			if let Some(diagnostic) = store.get_mut(id) {
+				diagnostic.clear_history();
+			}
+		}
+	}
+
+	fn measure_diagnostics(stats: Res<NetworkStats>, mut diagnostics: Diagnostics) {
+		for connection in &stats.connections {
+			let id = |kind| Self::connection_diagnostic(connection.client_id, kind);
+			diagnostics.add_measurement(id(Self::RTT), || connection.rtt.as_secs_f64() * 1000.);
+			diagnostics.add_measurement(id(Self::PACKET_LOSS), || connection.packet_loss * 100.);
+			diagnostics.add_measurement(id(Self::UPLOAD), || {
+				connection.bytes_sent_per_second / 1000.
+			});
+			diagnostics.add_measurement(id(Self::DOWNLOAD), || {
+				connection.bytes_received_per_second / 1000.
+			});
+		}
+		diagnostics.add_measurement(Self::TICK_DRIFT, || stats.tick_drift as f64);
+		diagnostics.add_measurement(Self::REPLICATED_ENTITIES, || {
+			stats.replicated_entities as f64
+		});
+	}
+
+	/// Also forgets everything measured, so the next game starts from scratch
+	fn hide_diagnostics(
+		mut store: ResMut<DiagnosticsStore>,
+		mut diagnostics: ResMut<ScreenDiagnostics>,
+		mut shown: ResMut<NetworkDiagnosticsShown>,
+		mut connections: ResMut<ConnectionDiagnostics>,
+	) {
+		for client_id in connections.0.drain(..) {
+			Self::remove_connection(client_id, &mut store, &mut diagnostics);
+		}
+		for (id, _, _) in Self::DIAGNOSTICS {
+			if let Some(diagnostic) = store.get_mut(id) {
+				diagnostic.clear_history();
+			}
+		}
+		if shown.0 {
+			for (id, _, _) in Self::DIAGNOSTICS {
+				diagnostics.modify(id).toggle();
+			}
+			shown.0 = false;
+		}
+	}
+}
@@ -0,0 +1,137 @@
+//! In-game menu for leaving the game, opened with Escape.
+//!
+//! Doesn't actually pause anything, the server keeps simulating regardless.
+
+use super::chat::ChatBoxStates;
+use super::manual_ui::*;
+use super::start_screen::{ButtonText, GameButtonBundle};
+use super::ui_cameras::CorrectCamera;
+use crate::prelude::*;
+
+/// Plugin
+pub struct PauseMenuPlugin;
+
+/// Sub-state of [GlobalGameStates::InGame]
+#[derive(States, Default, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+enum PauseMenuStates {
+	#[default]
+	Closed,
+
+	Open,
+}
+
+impl Plugin for PauseMenuPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_state::<PauseMenuStates>()
+			.add_systems(
+				Update,
+				Self::toggle_pause_menu
+					.run_if(in_state(GlobalGameStates::InGame))
+					// Escape returns to the menu straight away once disconnected
+					.run_if(not(in_state(ClientConnectionState::Disconnected)))
+					// Escape throws away the message being typed instead
+					.run_if(in_state(ChatBoxStates::Closed)),
+			)
+			.add_systems(
+				Update,
+				Self::handle_click_interactions.run_if(in_state(PauseMenuStates::Open)),
+			)
+			.add_systems(OnEnter(PauseMenuStates::Open), Self::spawn_pause_menu)
+			.add_systems(OnExit(PauseMenuStates::Open), Self::despawn_pause_menu)
+			.add_systems(OnExit(GlobalGameStates::InGame), Self::close_pause_menu);
+	}
+}
+
+/// List of buttons that can be clicked
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+enum PauseMenuButtons {
+	Resume,
+	LeaveGame,
+}
+
+impl PauseMenuButtons {
+	const fn get_text(self) -> &'static str {
+		match self {
+			PauseMenuButtons::Resume => "Resume",
+			PauseMenuButtons::LeaveGame => "Leave Game",
+		}
+	}
+}
+
+impl PauseMenuPlugin {
+	const CAM: UiCameras = UiCameras::Center;
+
+	fn toggle_pause_menu(
+		keys: Res<Input<KeyCode>>,
+		state: Res<State<PauseMenuStates>>,
+		mut next_state: ResMut<NextState<PauseMenuStates>>,
+	) {
+		if keys.just_pressed(KeyCode::Escape) {
+			next_state.set(match state.get() {
+				PauseMenuStates::Closed => PauseMenuStates::Open,
+				PauseMenuStates::Open => PauseMenuStates::Closed,
+			});
+		}
+	}
+
+	fn close_pause_menu(mut next_state: ResMut<NextState<PauseMenuStates>>) {
+		next_state.set(PauseMenuStates::Closed);
+	}
+
+	fn spawn_pause_menu(mut commands: Commands, mut mma: MM2, ass: Res<AssetServer>) {
+		let mut column = ManualColumn {
+			const_x: 0.,
+			const_width: 200.,
+			current_y: 0.,
+			item_height: 50.,
+			margin: 10.,
+		}
+		.center_with(2);
+
+		for btn in PauseMenuButtons::iter() {
+			let manual_node = column.next();
+			let wrap_size = manual_node.bbox.dimensions();
+			commands
+				.spawn(GameButtonBundle::new(btn, manual_node, &mut mma))
+				.render_layer(GlobalRenderLayers::Ui(Self::CAM))
+				.named(btn.get_text())
+				.with_children(|parent| {
+					parent
+						.spawn(ButtonText::new(btn.get_text(), 30., wrap_size, &ass))
+						.render_layer(GlobalRenderLayers::Ui(Self::CAM));
+				});
+		}
+	}
+
+	fn despawn_pause_menu(mut commands: Commands, btns: Query<Entity, With<PauseMenuButtons>>) {
+		for btn in btns.iter() {
+			commands.entity(btn).despawn_recursive();
+		}
+	}
+
+	fn handle_click_interactions(
+		mut click_events: EventReader<Pointer<Click>>,
+		btns: Query<&PauseMenuButtons>,
+		correct_camera: CorrectCamera,
+		mut pause_state: ResMut<NextState<PauseMenuStates>>,
+		mut global_state: ResMut<NextState<GlobalGameStates>>,
+	) {
+		for click_event in click_events.read() {
+			let Ok(btn) = btns.get(click_event.target) else {
+				continue;
+			};
+			if !correct_camera.confirm(&click_event.event.hit.camera, Self::CAM) {
+				continue;
+			}
+
+			match btn {
+				PauseMenuButtons::Resume => pause_state.set(PauseMenuStates::Closed),
+				PauseMenuButtons::LeaveGame => {
+					info!("Leaving the game");
+					global_state.set(GlobalGameStates::StartMenu);
+				}
+			}
+		}
+	}
+}
@@ -1,12 +1,18 @@
 use bevy::sprite::Mesh2dHandle;
 use bevy::text::Text2dBounds;
 
+use self::direct_connect::{
+	client_config, is_address_char, parse_server_address, RecentServers, MAX_ADDRESS_LEN,
+	RECENT_SERVERS_PATH,
+};
 use super::manual_ui::*;
 use super::path_tracing::*;
 use super::ui_cameras::CorrectCamera;
 use crate::netcode::NetcodeConfig;
 use crate::prelude::*;
 
+mod direct_connect;
+
 /// Sub-state
 #[derive(States, Default, Debug, PartialEq, Eq, Clone, Copy, Hash)]
 enum StartScreenStates {
@@ -28,7 +34,9 @@ struct Cam(UiCameras);
 
 impl Plugin for StartScreen {
 	fn build(&self, app: &mut App) {
-		app.add_state::<StartScreenStates>();
+		app
+			.add_state::<StartScreenStates>()
+			.add_systems(Startup, Self::load_recent_servers);
 		app.add_systems(
 			Update,
 			(
@@ -50,6 +58,23 @@ impl Plugin for StartScreen {
 				OnEnter(GlobalGameStates::InGame),
 				// cleanup
 				Self::despawn_initial,
+			)
+			.add_systems(
+				// [StartScreenStates::Initial] is never left while in game,
+				// so it isn't entered again when coming back
+				OnExit(GlobalGameStates::InGame),
+				Self::spawn_initial,
+			);
+
+		// why the last game ended, if it ended unexpectedly
+		app
+			.add_systems(
+				OnEnter(GlobalGameStates::StartMenu),
+				Self::spawn_connection_error,
+			)
+			.add_systems(
+				OnExit(GlobalGameStates::StartMenu),
+				Self::despawn_connection_error,
 			);
 
 		// hosting submenu
@@ -67,11 +92,27 @@ impl Plugin for StartScreen {
 		app
 			.add_systems(
 				OnEnter(StartScreenStates::ConfigureClient),
-				Self::spawn_configure_client,
+				(
+					Self::spawn_configure_client,
+					Self::spawn_direct_connect,
+					Self::start_lan_discovery,
+				),
 			)
 			.add_systems(
 				OnExit(StartScreenStates::ConfigureClient),
-				Self::despawn_configure_client,
+				(Self::despawn_configure_client, Self::stop_lan_discovery),
+			)
+			.add_systems(
+				Update,
+				Self::list_discovered_servers
+					.run_if(in_state(StartScreenStates::ConfigureClient))
+					.run_if(resource_changed::<DiscoveredServers>()),
+			)
+			.add_systems(
+				Update,
+				Self::handle_direct_connect_typing
+					.run_if(in_state(GlobalGameStates::StartMenu))
+					.run_if(in_state(StartScreenStates::ConfigureClient)),
 			);
 	}
 }
@@ -123,9 +164,111 @@ impl ClientGameButtons {
 	}
 }
 
+/// A server found on the LAN, listed below the [ClientGameButtons]
+#[derive(Component, Debug, Clone)]
+struct DiscoveredServerButton {
+	addr: SocketAddr,
+	auth: Authentication,
+	token_port: u16,
+}
+
+impl DiscoveredServerButton {
+	fn get_text(beacon: &ServerBeacon, local_version: &ProtocolVersion) -> String {
+		let mut text = format!(
+			"{} ({}/{})",
+			beacon.name, beacon.players, beacon.max_players
+		);
+		if !beacon.version.is_compatible_with(local_version) {
+			text.push_str(&format!(" - incompatible {}", beacon.version));
+		}
+		text
+	}
+
+	fn into_config(self) -> NetcodeConfig {
+		NetcodeConfig::Client {
+			ip: self.addr.ip(),
+			port: self.addr.port(),
+			auth: self.auth,
+			token_port: self.token_port,
+			token_secret: None,
+			ship: None,
+			transport: NetcodeTransport::Udp,
+		}
+	}
+}
+
+/// Text box for typing in the address of a server to join, see [direct_connect]
+#[derive(Component, Debug, Default)]
+struct DirectConnectInput {
+	text: String,
+}
+
+impl DirectConnectInput {
+	const PLACEHOLDER: &'static str = "Type IP:port, then Enter";
+
+	fn display_text(&self) -> String {
+		if self.text.is_empty() {
+			Self::PLACEHOLDER.to_owned()
+		} else {
+			format!("{}_", self.text)
+		}
+	}
+}
+
+/// Shows why the typed in address can't be joined
+#[derive(Component)]
+struct DirectConnectStatus;
+
+/// A server from [RecentServers]
+#[derive(Component, Debug, Clone, Copy)]
+struct RecentServerButton(SocketAddr);
+
+/// Shows the [ConnectionError] of the last game
+#[derive(Component)]
+struct ConnectionErrorText;
+
 impl StartScreen {
 	const INITIAL_CAM: UiCameras = UiCameras::MiddleLeft;
 
+	fn spawn_connection_error(
+		mut commands: Commands,
+		error: Option<Res<ConnectionError>>,
+		ass: Res<AssetServer>,
+	) {
+		let Some(error) = error else {
+			return;
+		};
+		let style = TextStyle {
+			font: ass.load(GlobalFont::Default),
+			font_size: 20.,
+			color: Color::ORANGE_RED,
+		};
+		commands
+			.spawn((
+				Text2dBundle {
+					text: Text::from_section(error.to_string(), style).with_alignment(TextAlignment::Center),
+					// above the initial buttons
+					transform: Transform::from_xyz(200., 150., 1.),
+					text_2d_bounds: Text2dBounds {
+						size: Vec2::new(350., 150.),
+					},
+					..default()
+				},
+				ConnectionErrorText,
+				Name::new("Connection Error Text"),
+			))
+			.render_layer(GlobalRenderLayers::Ui(Self::INITIAL_CAM));
+	}
+
+	fn despawn_connection_error(
+		mut commands: Commands,
+		texts: Query<Entity, With<ConnectionErrorText>>,
+	) {
+		for text in texts.iter() {
+			commands.entity(text).despawn_recursive();
+		}
+	}
+
 	fn spawn_initial(
 		mut commands: Commands,
 		mut mma: MM2,
@@ -209,20 +352,25 @@ impl StartScreen {
 
 	const CLIENT_CAM: UiCameras = UiCameras::MiddleRight;
 
-	fn spawn_configure_client(
-		mut commands: Commands,
-		mut mma: MM2,
-		ass: Res<AssetServer>,
-		mut effects: ResMut<Assets<EffectAsset>>,
-	) {
-		let mut column = ManualColumn {
+	/// Column of the [ClientGameButtons], followed by the [DiscoveredServerButton]s
+	fn client_column() -> ManualColumn {
+		ManualColumn {
 			const_x: -200.,
 			const_width: 200.,
 			current_y: 0.,
 			item_height: 50.,
 			margin: 10.,
 		}
-		.center_with(2);
+		.center_with(2)
+	}
+
+	fn spawn_configure_client(
+		mut commands: Commands,
+		mut mma: MM2,
+		ass: Res<AssetServer>,
+		mut effects: ResMut<Assets<EffectAsset>>,
+	) {
+		let mut column = Self::client_column();
 
 		for btn in ClientGameButtons::iter() {
 			let manual_node = column.next();
@@ -244,13 +392,240 @@ impl StartScreen {
 
 	fn despawn_configure_client(
 		mut commands: Commands,
-		btns: Query<Entity, With<ClientGameButtons>>,
+		btns: Query<
+			Entity,
+			Or<(
+				With<ClientGameButtons>,
+				With<DiscoveredServerButton>,
+				With<DirectConnectInput>,
+				With<DirectConnectStatus>,
+				With<RecentServerButton>,
+			)>,
+		>,
 	) {
 		for btn in btns.iter() {
 			commands.entity(btn).despawn_recursive();
 		}
 	}
 
+	fn load_recent_servers(mut commands: Commands) {
+		let recent = match RecentServers::load(RECENT_SERVERS_PATH) {
+			Ok(recent) => recent,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => RecentServers::default(),
+			Err(err) => {
+				warn!(
+					"Couldn't load recent servers from {:?}: {}",
+					RECENT_SERVERS_PATH, err
+				);
+				RecentServers::default()
+			}
+		};
+		commands.insert_resource(recent);
+	}
+
+	/// Spawns the address text box above the [ClientGameButtons],
+	/// and a column of [RecentServerButton]s next to them
+	fn spawn_direct_connect(
+		mut commands: Commands,
+		mut mma: MM2,
+		ass: Res<AssetServer>,
+		mut effects: ResMut<Assets<EffectAsset>>,
+		recent: Res<RecentServers>,
+	) {
+		let input = DirectConnectInput::default();
+		let manual_node = ManualNode {
+			bbox: BBox {
+				half_width: 150.,
+				half_height: 25.,
+			},
+			position: Vec2::new(-200., 130.),
+		};
+		let text_wrap = manual_node.bbox.dimensions();
+		let text = input.display_text();
+		commands
+			.spawn(GameButtonBundle::new(input, manual_node, &mut mma))
+			.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM))
+			.insert(Cam(Self::CLIENT_CAM))
+			.with_children(|parent| {
+				parent
+					.spawn(ButtonParticles::new(&mut effects))
+					.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM));
+				parent
+					.spawn(ButtonText::new(text, 20., text_wrap, &ass))
+					.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM));
+			});
+
+		let style = TextStyle {
+			font: ass.load(GlobalFont::Default),
+			font_size: 18.,
+			color: Color::ORANGE_RED,
+		};
+		commands
+			.spawn((
+				Text2dBundle {
+					text: Text::from_section("", style).with_alignment(TextAlignment::Center),
+					transform: Transform::from_xyz(-200., 175., 1.),
+					text_2d_bounds: Text2dBounds {
+						size: Vec2::new(300., 40.),
+					},
+					..default()
+				},
+				DirectConnectStatus,
+				Name::new("Direct Connect Status"),
+			))
+			.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM));
+
+		let mut column = ManualColumn {
+			const_x: -420.,
+			..Self::client_column()
+		};
+		for addr in recent.iter() {
+			let btn = RecentServerButton(addr);
+			let manual_node = column.next();
+			let text_wrap = manual_node.bbox.dimensions();
+			commands
+				.spawn(GameButtonBundle::new(btn, manual_node, &mut mma))
+				.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM))
+				.insert(Cam(Self::CLIENT_CAM))
+				.with_children(|parent| {
+					parent
+						.spawn(ButtonParticles::new(&mut effects))
+						.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM));
+					parent
+						.spawn(ButtonText::new(
+							format!("Recent: {}", addr),
+							18.,
+							text_wrap,
+							&ass,
+						))
+						.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM));
+				});
+		}
+	}
+
+	/// Edits the [DirectConnectInput], and joins the typed in address on Enter
+	fn handle_direct_connect_typing(
+		mut received_chars: EventReader<ReceivedCharacter>,
+		keys: Res<Input<KeyCode>>,
+		mut input: Query<(&mut DirectConnectInput, &Children)>,
+		mut input_texts: Query<&mut Text, Without<DirectConnectStatus>>,
+		mut status: Query<&mut Text, With<DirectConnectStatus>>,
+		mut recent: ResMut<RecentServers>,
+		mut global_state: ResMut<NextState<GlobalGameStates>>,
+		mut local_state: ResMut<NextState<StartScreenStates>>,
+		mut commands: Commands,
+	) {
+		let Ok((mut input, children)) = input.get_single_mut() else {
+			return;
+		};
+
+		let mut edited = false;
+		for received in received_chars.read() {
+			if is_address_char(received.char) && input.text.len() < MAX_ADDRESS_LEN {
+				input.text.push(received.char);
+				edited = true;
+			}
+		}
+		if keys.just_pressed(KeyCode::Back) {
+			input.text.pop();
+			edited = true;
+		}
+		if edited {
+			for child in children.iter() {
+				if let Ok(mut text) = input_texts.get_mut(*child) {
+					text.sections[0].value = input.display_text();
+				}
+			}
+		}
+
+		if keys.just_pressed(KeyCode::Return) {
+			match parse_server_address(&input.text) {
+				Ok(addr) => {
+					info!("Joining {} by address", addr);
+					recent.push(addr);
+					if let Err(err) = recent.save(RECENT_SERVERS_PATH) {
+						warn!(
+							"Couldn't save recent servers to {:?}: {}",
+							RECENT_SERVERS_PATH, err
+						);
+					}
+
+					global_state.set(GlobalGameStates::InGame);
+					local_state.set(StartScreenStates::Initial);
+					commands.insert_resource(client_config(addr));
+				}
+				Err(err) => {
+					for mut text in status.iter_mut() {
+						text.sections[0].value = err.to_string();
+					}
+				}
+			}
+		}
+	}
+
+	fn start_lan_discovery(mut commands: Commands, mut discovered: ResMut<DiscoveredServers>) {
+		discovered.clear();
+		match LanDiscovery::listen(DISCOVERY_PORT) {
+			Ok(lan_discovery) => commands.insert_resource(lan_discovery),
+			Err(err) => warn!(
+				"Couldn't listen for servers on the LAN (port {}): {}",
+				DISCOVERY_PORT, err
+			),
+		}
+	}
+
+	fn stop_lan_discovery(mut commands: Commands) {
+		commands.remove_resource::<LanDiscovery>();
+	}
+
+	/// Respawns a button for every server in [DiscoveredServers]
+	fn list_discovered_servers(
+		mut commands: Commands,
+		discovered: Res<DiscoveredServers>,
+		btns: Query<Entity, With<DiscoveredServerButton>>,
+		replicated_types: Res<ReplicatedTypes>,
+		mut mma: MM2,
+		ass: Res<AssetServer>,
+		mut effects: ResMut<Assets<EffectAsset>>,
+	) {
+		for btn in btns.iter() {
+			commands.entity(btn).despawn_recursive();
+		}
+
+		let local_version = ProtocolVersion::current(&replicated_types);
+		let mut column = Self::client_column();
+		for _ in ClientGameButtons::iter() {
+			column.next();
+		}
+
+		for (addr, beacon) in discovered.servers() {
+			let btn = DiscoveredServerButton {
+				addr,
+				auth: beacon.auth,
+				token_port: beacon.token_port,
+			};
+			let manual_node = column.next();
+			let text_wrap = manual_node.bbox.dimensions();
+			commands
+				.spawn(GameButtonBundle::new(btn, manual_node, &mut mma))
+				.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM))
+				.insert(Cam(Self::CLIENT_CAM))
+				.with_children(|parent| {
+					parent
+						.spawn(ButtonParticles::new(&mut effects))
+						.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM));
+					parent
+						.spawn(ButtonText::new(
+							DiscoveredServerButton::get_text(beacon, &local_version),
+							18.,
+							text_wrap,
+							&ass,
+						))
+						.render_layer(GlobalRenderLayers::Ui(Self::CLIENT_CAM));
+				});
+		}
+	}
+
 	fn handle_hover_interactions(
 		mut start_hover_events: EventReader<Pointer<Move>>,
 		mut end_hover_events: EventReader<Pointer<Out>>,
@@ -312,6 +687,10 @@ impl StartScreen {
 		initial_btns: Query<(&Cam, &InitialUiButtons)>,
 		host_btns: Query<(&Cam, &HostGameButtons)>,
 		client_btns: Query<(&Cam, &ClientGameButtons)>,
+		discovered_btns: Query<(&Cam, &DiscoveredServerButton)>,
+		recent_btns: Query<(&Cam, &RecentServerButton)>,
+		direct_connect_inputs: Query<(), With<DirectConnectInput>>,
+		mut recent: ResMut<RecentServers>,
 		correct_camera: CorrectCamera,
 
 		mut global_state: ResMut<NextState<GlobalGameStates>>,
@@ -356,10 +735,43 @@ impl StartScreen {
 					// correct camera
 
 					global_state.set(GlobalGameStates::InGame);
+					local_state.set(StartScreenStates::Initial);
 					commands.insert_resource(match btn {
 						ClientGameButtons::MachineLocalGame => NetcodeConfig::new_client_machine_local(),
 					});
 				}
+			} else if let Ok((cam, btn)) = discovered_btns.get(click_event.target) {
+				// found callback target
+				let camera = click_event.event.hit.camera;
+				if correct_camera.confirm(&camera, **cam) {
+					// correct camera
+
+					info!("Joining discovered server at {}", btn.addr);
+					global_state.set(GlobalGameStates::InGame);
+					local_state.set(StartScreenStates::Initial);
+					commands.insert_resource(btn.clone().into_config());
+				}
+			} else if let Ok((cam, RecentServerButton(addr))) = recent_btns.get(click_event.target) {
+				// found callback target
+				let camera = click_event.event.hit.camera;
+				if correct_camera.confirm(&camera, **cam) {
+					// correct camera
+
+					info!("Joining recent server {}", addr);
+					recent.push(*addr);
+					if let Err(err) = recent.save(RECENT_SERVERS_PATH) {
+						warn!(
+							"Couldn't save recent servers to {:?}: {}",
+							RECENT_SERVERS_PATH, err
+						);
+					}
+					global_state.set(GlobalGameStates::InGame);
+					local_state.set(StartScreenStates::Initial);
+					commands.insert_resource(client_config(*addr));
+				}
+			} else if direct_connect_inputs.contains(click_event.target) {
+				// nothing to do, the text box always has the keyboard's focus,
+				// see [Self::handle_direct_connect_typing]
 			} else {
 				warn!("Cannot find target callback");
 			}
@@ -368,7 +780,7 @@ impl StartScreen {
 }
 
 #[derive(Bundle)]
-struct GameButtonBundle<T: Component + Send + Sync + 'static> {
+pub(super) struct GameButtonBundle<T: Component + Send + Sync + 'static> {
 	mesh: Mesh2dHandle,
 	material: Handle<ColorMaterial>,
 	spatial: SpatialBundle,
@@ -380,7 +792,7 @@ struct GameButtonBundle<T: Component + Send + Sync + 'static> {
 }
 
 impl<T: Component + Send + Sync + 'static> GameButtonBundle<T> {
-	fn new(btn: T, manual_node: ManualNode, mma: &mut MM2) -> Self {
+	pub(super) fn new(btn: T, manual_node: ManualNode, mma: &mut MM2) -> Self {
 		Self {
 			btn,
 			mesh: mma
@@ -406,7 +818,7 @@ impl<T: Component + Send + Sync + 'static> GameButtonBundle<T> {
 }
 
 #[derive(Bundle)]
-struct ButtonText {
+pub(super) struct ButtonText {
 	text_bundle: Text2dBundle,
 
 	name: Name,
@@ -414,7 +826,7 @@ struct ButtonText {
 }
 
 impl ButtonText {
-	fn new(
+	pub(super) fn new(
 		// cam: UiCameras,
 		text: impl Into<Cow<'static, str>>,
 		font_size: f32,
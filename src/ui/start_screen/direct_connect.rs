@@ -0,0 +1,172 @@
+//! Joining a server by typing its address, and remembering the servers joined like that.
+
+use std::path::Path;
+
+use crate::prelude::*;
+
+/// Where [RecentServers] are saved, relative to the working directory
+pub const RECENT_SERVERS_PATH: &str = "recent_servers.txt";
+
+/// Longest address that can be typed in, enough for any IPv6 address and port
+pub const MAX_ADDRESS_LEN: usize = 47;
+
+/// Why a typed in address can't be joined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+	Empty,
+	Invalid(String),
+	UnspecifiedIp,
+	ZeroPort,
+}
+
+impl std::fmt::Display for AddressError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Empty => write!(f, "Type in an address like 192.168.1.10:{}", DEFAULT_PORT),
+			Self::Invalid(input) => write!(f, "{:?} isn't an IP address or IP:port", input),
+			Self::UnspecifiedIp => write!(f, "Can't join an unspecified address"),
+			Self::ZeroPort => write!(f, "Port 0 can't be joined"),
+		}
+	}
+}
+
+/// Parses `IP:port`, or just an IP to use [DEFAULT_PORT].
+/// IPv6 addresses with a port are written as `[IP]:port`.
+pub fn parse_server_address(input: &str) -> Result<SocketAddr, AddressError> {
+	let input = input.trim();
+	if input.is_empty() {
+		return Err(AddressError::Empty);
+	}
+
+	let addr = match input.parse::<SocketAddr>() {
+		Ok(addr) => addr,
+		Err(_) => match input.parse::<IpAddr>() {
+			Ok(ip) => SocketAddr::new(ip, DEFAULT_PORT),
+			Err(_) => return Err(AddressError::Invalid(input.to_owned())),
+		},
+	};
+
+	if addr.ip().is_unspecified() {
+		return Err(AddressError::UnspecifiedIp);
+	}
+	if addr.port() == 0 {
+		return Err(AddressError::ZeroPort);
+	}
+
+	Ok(addr)
+}
+
+/// Whether `c` can be part of a typed in address
+pub fn is_address_char(c: char) -> bool {
+	c.is_ascii_hexdigit() || matches!(c, '.' | ':' | '[' | ']')
+}
+
+/// Servers joined by address are assumed to be [Authentication::Unsecure],
+/// there is no way to know before connecting.
+pub fn client_config(addr: SocketAddr) -> NetcodeConfig {
+	NetcodeConfig::Client {
+		ip: addr.ip(),
+		port: addr.port(),
+		auth: Authentication::Unsecure,
+		token_port: DEFAULT_TOKEN_PORT,
+		token_secret: None,
+		ship: None,
+		transport: NetcodeTransport::Udp,
+	}
+}
+
+/// Servers most recently joined by address, most recent first
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct RecentServers(Vec<SocketAddr>);
+
+impl RecentServers {
+	pub const MAX_REMEMBERED: usize = 5;
+
+	pub fn iter(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+		self.0.iter().copied()
+	}
+
+	/// Moves `addr` to the front, forgetting the oldest server if there are too many
+	pub fn push(&mut self, addr: SocketAddr) {
+		self.0.retain(|known| *known != addr);
+		self.0.insert(0, addr);
+		self.0.truncate(Self::MAX_REMEMBERED);
+	}
+
+	/// One address per line, unparseable lines are skipped
+	pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+		let text = std::fs::read_to_string(path)?;
+		let mut recent = Self::default();
+		for addr in text
+			.lines()
+			.filter_map(|line| line.trim().parse::<SocketAddr>().ok())
+			.rev()
+		{
+			recent.push(addr);
+		}
+		Ok(recent)
+	}
+
+	pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+		let text: String = self.0.iter().map(|addr| format!("{}\n", addr)).collect();
+		std::fs::write(path, text)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn parses_addresses() {
+		assert_eq!(
+			parse_server_address(" 192.168.1.10:1234 "),
+			Ok(SocketAddr::new(Ipv4Addr::new(192, 168, 1, 10).into(), 1234))
+		);
+		assert_eq!(
+			parse_server_address("10.0.0.1"),
+			Ok(SocketAddr::new(
+				Ipv4Addr::new(10, 0, 0, 1).into(),
+				DEFAULT_PORT
+			))
+		);
+		assert_eq!(
+			parse_server_address("[::1]:1234"),
+			Ok(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 1234))
+		);
+		assert_eq!(parse_server_address(""), Err(AddressError::Empty));
+		assert_eq!(
+			parse_server_address("0.0.0.0:1234"),
+			Err(AddressError::UnspecifiedIp)
+		);
+		assert_eq!(
+			parse_server_address("10.0.0.1:0"),
+			Err(AddressError::ZeroPort)
+		);
+		assert!(matches!(
+			parse_server_address("10.0.0:1234"),
+			Err(AddressError::Invalid(_))
+		));
+	}
+
+	#[test]
+	fn recent_servers_round_trip() {
+		let path = std::env::temp_dir().join(format!("space_craft-test-{}.txt", random::<u64>()));
+		let addr = |port| SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port);
+
+		let mut recent = RecentServers::default();
+		for port in 1..=RecentServers::MAX_REMEMBERED as u16 + 1 {
+			recent.push(addr(port));
+		}
+		recent.push(addr(3));
+		recent.save(&path).unwrap();
+		let loaded = RecentServers::load(&path);
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(
+			recent.iter().collect::<Vec<_>>(),
+			vec![addr(3), addr(6), addr(5), addr(4), addr(2)]
+		);
+		assert_eq!(loaded.unwrap(), recent);
+	}
+}
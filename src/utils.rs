@@ -2,6 +2,8 @@ use std::borrow::Cow;
 
 pub use crate::replicate_marked;
 
+use bevy::reflect::GetTypeRegistration;
+
 use crate::prelude::*;
 
 use extension_traits::extension;
@@ -55,15 +57,41 @@ impl &mut App {
 		}
 	}
 
-	fn replicate_marked<T>(self) -> Self
+	/// Use [replicate_marked!], which names `T` after how it is spelled in the source
+	fn replicate_marked<T>(self, name: &'static str) -> Self
 	where
 		T: Component + Serialize + DeserializeOwned + ReplicationMarker,
 	{
-		debug!("Replicating {:?}", std::any::type_name::<T>());
+		debug!("Replicating {:?}", name);
 
 		// impl ReplicationMarker for T {}
 
-		self.replicate::<T>()
+		self.register_replicated_type(name).replicate::<T>()
+	}
+
+	/// [BlueprintsAppExt::register_blueprint], which replicates `B`,
+	/// so it is added to the [ReplicatedTypes] as `name` as well.
+	fn register_networked_blueprint<B>(self, name: &'static str) -> Self
+	where
+		B: Component + Blueprint + Serialize + DeserializeOwned + GetTypeRegistration,
+	{
+		self
+			.register_blueprint::<B>()
+			.register_replicated_type(name)
+	}
+
+	/// Adds `name` to the [ReplicatedTypes] that make up this build's [ProtocolVersion].
+	/// Call for every replicated component and networked event.
+	///
+	/// The name is spelled out rather than taken from [std::any::type_name],
+	/// whose output may change between compiler versions.
+	/// Change it when the type's serialized layout changes, e.g. to `"ChatMessage/2"`.
+	fn register_replicated_type(self, name: &'static str) -> Self {
+		self
+			.world
+			.get_resource_or_insert_with(ReplicatedTypes::default)
+			.insert(name);
+		self
 	}
 }
 
@@ -72,7 +100,7 @@ macro_rules! replicate_marked {
 	// &mut App
 	($app:expr, $($t:ty),*) => {
 		$(
-			$app.replicate_marked::<$t>();
+			$app.replicate_marked::<$t>(stringify!($t));
 
 			impl ReplicationMarker for $t {}
 		)*
@@ -129,7 +157,7 @@ mod polar_tests {
 	}
 }
 
-pub fn vec3_polar_random(rng: &mut ThreadRng) -> Vec3 {
+pub fn vec3_polar_random(rng: &mut impl Rng) -> Vec3 {
 	let phi = rng.gen_range(0. ..TAU);
 	let z: f32 = rng.gen_range(-1. ..1.);
 	let theta = z.acos();
@@ -1,9 +1,14 @@
+use std::borrow::BorrowMut;
+
 use bevy::{
 	app::{App, PluginGroup},
+	time::TimeUpdateStrategy,
 	winit::WinitPlugin,
 	DefaultPlugins,
 };
 
+use crate::prelude::*;
+
 /// Returns an app that can run basic schedules
 pub fn test_app() -> App {
 	let mut app = App::new();
@@ -12,3 +17,145 @@ pub fn test_app() -> App {
 
 	app
 }
+
+/// Returns an app running the [crate::SimulationPlugin] as configured by `config`,
+/// that enters [GlobalGameStates::InGame] on its first update.
+///
+/// Every update advances time by exactly one [FixedUpdate] tick.
+pub fn test_netcode_app(config: NetcodeConfig) -> App {
+	let mut app = test_app();
+
+	app
+		.add_plugins((HanabiPlugin, crate::SimulationPlugin))
+		.add_state::<GlobalGameStates>()
+		.insert_resource(config)
+		.insert_resource(NextState(Some(GlobalGameStates::InGame)))
+		.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+			1. / 64.,
+		)));
+
+	app
+}
+
+/// A server hosting over `network`, configured by `settings`
+pub fn loopback_server_config(
+	network: &LoopbackNetwork,
+	settings: ServerSettingsArgs,
+) -> NetcodeConfig {
+	NetcodeConfig::Server {
+		ip: Ipv4Addr::LOCALHOST.into(),
+		port: DEFAULT_PORT,
+		headless: false,
+		auth: Authentication::Unsecure,
+		token_port: DEFAULT_TOKEN_PORT,
+		private_key: None,
+		token_secret: None,
+		spawn_points: crate::players::SpawnPointsConfig::DEFAULT,
+		settings,
+		ship: None,
+		transport: NetcodeTransport::Loopback(network.clone()),
+	}
+}
+
+/// A client joining the server hosting over `network`
+pub fn loopback_client_config(network: &LoopbackNetwork) -> NetcodeConfig {
+	NetcodeConfig::Client {
+		ip: Ipv4Addr::LOCALHOST.into(),
+		port: DEFAULT_PORT,
+		auth: Authentication::Unsecure,
+		token_port: DEFAULT_TOKEN_PORT,
+		token_secret: None,
+		ship: None,
+		transport: NetcodeTransport::Loopback(network.clone()),
+	}
+}
+
+/// A server hosting on a real localhost socket, `port_offset` above the default ports.
+/// Only for testing the sockets themselves, every test needs its own offset.
+pub fn udp_server_config(port_offset: u16) -> NetcodeConfig {
+	NetcodeConfig::Server {
+		ip: Ipv4Addr::LOCALHOST.into(),
+		port: DEFAULT_PORT + port_offset,
+		headless: false,
+		auth: Authentication::Unsecure,
+		token_port: DEFAULT_TOKEN_PORT + port_offset,
+		private_key: None,
+		token_secret: None,
+		spawn_points: crate::players::SpawnPointsConfig::DEFAULT,
+		settings: ServerSettingsArgs::NONE,
+		ship: None,
+		transport: NetcodeTransport::Udp,
+	}
+}
+
+/// A client joining over a real localhost socket, see [udp_server_config]
+pub fn udp_client_config(port_offset: u16) -> NetcodeConfig {
+	NetcodeConfig::Client {
+		ip: Ipv4Addr::LOCALHOST.into(),
+		port: DEFAULT_PORT + port_offset,
+		auth: Authentication::Unsecure,
+		token_port: DEFAULT_TOKEN_PORT + port_offset,
+		token_secret: None,
+		ship: None,
+		transport: NetcodeTransport::Udp,
+	}
+}
+
+/// Updates every app `frames` times, in lockstep
+pub fn update_all(frames: usize, apps: &mut [impl BorrowMut<App>]) {
+	for _ in 0..frames {
+		for app in apps.iter_mut() {
+			app.borrow_mut().update();
+		}
+	}
+}
+
+/// How long [loopback_game] waits for its clients to join
+const MAX_JOIN_FRAMES: usize = 600;
+
+/// A server hosting over `network` as configured by `settings`, followed by `clients` clients,
+/// updated in lockstep until every client is connected and has been replicated its own player.
+pub fn loopback_game(
+	network: &LoopbackNetwork,
+	settings: ServerSettingsArgs,
+	clients: usize,
+) -> Vec<App> {
+	let mut apps = vec![test_netcode_app(loopback_server_config(network, settings))];
+	apps.extend((0..clients).map(|_| test_netcode_app(loopback_client_config(network))));
+
+	for _ in 0..MAX_JOIN_FRAMES {
+		update_all(1, &mut apps);
+		if apps[1..].iter_mut().all(has_joined) {
+			return apps;
+		}
+	}
+	panic!(
+		"{clients} clients didn't join within {MAX_JOIN_FRAMES} frames over {:?}",
+		network.conditions()
+	);
+}
+
+/// The ID the server knows a client joined over a [LoopbackNetwork] by
+pub fn loopback_client_id(client: &App) -> ClientId {
+	client
+		.world
+		.resource::<LoopbackClientTransport>()
+		.client_id()
+}
+
+fn has_joined(client: &mut App) -> bool {
+	if *client
+		.world
+		.resource::<State<ClientConnectionState>>()
+		.get()
+		!= ClientConnectionState::Connected
+	{
+		return false;
+	}
+	let id = loopback_client_id(client);
+	client
+		.world
+		.query::<&NetworkId>()
+		.iter(&client.world)
+		.any(|network_id| network_id.get_network_id() == id)
+}